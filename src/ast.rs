@@ -1,6 +1,8 @@
-use std::collections::HashMap;
-
-use crate::{token::Token, types::Type};
+use crate::{
+    resolve::source_name,
+    token::{Position, Token},
+    types::{FnPrimative, Type},
+};
 
 #[derive(Debug)]
 pub enum Qualifier {
@@ -31,18 +33,36 @@ pub struct Binary<'a> {
 #[derive(Debug)]
 pub struct Call<'a> {
     pub callee: Box<ParsedAST<'a>>,
-    pub args: Vec<ParsedAST<'a>>,
+    pub args: Vec<Arg<'a>>,
+}
+
+// an argument at a call site, i.e. `x=123`. only intrinsics take positional arguments
+#[derive(Debug)]
+pub struct Arg<'a> {
+    pub name: Option<String>,
+    pub value: ParsedAST<'a>,
+    // covers the name and the value
+    pub position: Position,
 }
 
 #[derive(Debug)]
 pub struct Decl<'a> {
     pub identifier: String,
+    // the span of the name
+    pub position: Position,
     pub qualifier: Qualifier,
     pub typ: Option<Type>,
-    // todo types
-    // pub typ: Type,
+    // there was no type written, so it is the type of the value. the type checker fills
+    // in typ once it knows it
     pub requires_infering: bool,
     pub value: Option<Box<ParsedAST<'a>>>,
+    pub doc: Option<String>,
+}
+
+// an expression in parentheses, kept so diagnostics can point at the whole group
+#[derive(Debug)]
+pub struct Group<'a> {
+    pub expression: Box<ParsedAST<'a>>,
 }
 
 #[derive(Debug)]
@@ -52,17 +72,33 @@ pub struct If<'a> {
     pub else_body: Option<Box<ParsedAST<'a>>>,
 }
 
+#[derive(Debug)]
+pub struct While<'a> {
+    pub condition: Box<ParsedAST<'a>>,
+    pub body: Box<ParsedAST<'a>>,
+}
+
+// for variable in iterable { body }
+#[derive(Debug)]
+pub struct For<'a> {
+    pub variable: String,
+    pub iterable: Box<ParsedAST<'a>>,
+    pub body: Box<ParsedAST<'a>>,
+}
+
 #[derive(Debug)]
 pub struct LhsAccess<'a> {
     pub left: Box<ParsedAST<'a>>,
-    // todo this should probably be an identifier?
-    pub right: Box<ParsedAST<'a>>,
+    // the name of the field
+    pub right: String,
 }
 
 #[derive(Debug)]
 pub enum Number {
-    INTEGER(i32),
-    FLOAT(f32),
+    // the type is either the suffix (`10u8`) or the smallest of i32, i64 and u64 it fits in
+    INTEGER(u64, Type),
+    // f32 unless it has a f64 suffix
+    FLOAT(f64, Type),
 }
 
 #[derive(Debug)]
@@ -87,42 +123,175 @@ pub struct Fun<'a> {
     // pub typ: Type,
     pub identifier: Option<String>,
     pub params: Vec<Decl<'a>>,
+    // none if the function doesn't return a value
+    pub return_type: Option<Type>,
     pub body: Box<ParsedAST<'a>>,
+    pub doc: Option<String>,
+}
+
+impl<'a> Fun<'a> {
+    // the type of a value holding the function
+    pub fn fn_type(&self) -> Type {
+        Type::FN(FnPrimative {
+            args: self.params.iter().filter_map(|p| p.typ.clone()).collect(),
+            return_type: self.return_type.clone().map(Box::new),
+            names: self
+                .params
+                .iter()
+                .map(|p| source_name(&p.identifier).to_string())
+                .collect(),
+        })
+    }
 }
 
 #[derive(Debug)]
 pub enum LeftUnary<'a> {
     COMP(Box<ParsedAST<'a>>),
+    // -x
+    NEG(Box<ParsedAST<'a>>),
+    // !x
+    NOT(Box<ParsedAST<'a>>),
+    // &x, the address of a variable or an element of one
+    ADDRESS(Box<ParsedAST<'a>>),
+    // *p, the value a pointer points to
+    DEREF(Box<ParsedAST<'a>>),
+}
+
+// `left[index]`, the index can be a range to get a slice, i.e. `left[1..3]`
+#[derive(Debug)]
+pub struct Index<'a> {
+    pub left: Box<ParsedAST<'a>>,
+    pub index: Box<ParsedAST<'a>>,
+}
+
+// a value of a struct type, i.e. `Point{x=1, y=2}`
+#[derive(Debug)]
+pub struct StructLiteral<'a> {
+    pub name: String,
+    pub fields: Vec<Arg<'a>>,
+}
+
+// a value of an enum variant, i.e. `Shape::Circle(2.0)`. the payload is positional
+#[derive(Debug)]
+pub struct Variant<'a> {
+    pub enum_name: String,
+    pub variant: String,
+    pub payload: Vec<ParsedAST<'a>>,
+}
+
+#[derive(Debug)]
+pub enum PatternKind<'a> {
+    // `_`, matches anything
+    WILDCARD,
+    // matches anything and names it for the arm
+    BINDING(String),
+    // a number, char or bool the value must equal
+    LITERAL(Box<ParsedAST<'a>>),
+    // `Shape::Rect(w, _)`, a variant whose payload matches the patterns
+    VARIANT(String, String, Vec<Pattern<'a>>),
+}
+
+#[derive(Debug)]
+pub struct Pattern<'a> {
+    pub kind: PatternKind<'a>,
+    pub position: Position,
+}
+
+// `pattern => body`
+#[derive(Debug)]
+pub struct Arm<'a> {
+    pub pattern: Pattern<'a>,
+    pub body: Box<ParsedAST<'a>>,
+}
+
+// the arms are tried in order, the first whose pattern matches is taken
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub scrutinee: Box<ParsedAST<'a>>,
+    pub arms: Vec<Arm<'a>>,
 }
 
 #[derive(Debug)]
 pub struct Typ {
-    pub fields: HashMap<String, Type>,
+    // in declaration order, which is the order they are laid out in
+    pub fields: Vec<(String, Type)>,
     pub anon_name: Option<String>,
 }
 
-// todo this should be a struct so we get positional information
 #[derive(Debug)]
-pub enum ParsedAST<'a> {
+pub struct ParsedAST<'a> {
+    pub kind: ParsedASTKind<'a>,
+    // the span of source this node was parsed from
+    pub position: Position,
+    // the type of the expression, filled in by the type checker. none if it isn't an
+    // expression or its type couldn't be worked out
+    pub typ: Option<Type>,
+}
+
+impl<'a> ParsedAST<'a> {
+    pub fn new(kind: ParsedASTKind<'a>, position: Position) -> ParsedAST<'a> {
+        ParsedAST {
+            kind,
+            position,
+            typ: None,
+        }
+    }
+}
+
+// the expression in a statement
+pub(crate) fn statement<'b, 'c>(ast: &'b mut ParsedAST<'c>) -> &'b mut ParsedAST<'c> {
+    if matches!(ast.kind, ParsedASTKind::STMT(_)) {
+        let ParsedASTKind::STMT(inner) = &mut ast.kind else {
+            unreachable!()
+        };
+        inner
+    } else {
+        ast
+    }
+}
+
+#[derive(Debug)]
+pub enum ParsedASTKind<'a> {
     PROGRAM(Program<'a>),
     STMT(Box<ParsedAST<'a>>),
     EXPRESSION_INSTRUCTION(ExpressionInstruction<'a>),
     BLOCK(Block<'a>),
     IF(If<'a>),
-    // FOR(For<'a>),
-    // RET(Option<Box<ParsedAST<'a>>>),
+    WHILE(While<'a>),
+    FOR(For<'a>),
+    BREAK,
+    CONTINUE,
+    RET(Option<Box<ParsedAST<'a>>>),
     DECL(Decl<'a>),
     ASSIGN(Assign<'a>),
     IDENTIFIER(String),
     STRING(String),
+    CHAR(char),
     FN(Fun<'a>),
     NUMBER(Number),
     LEFT_UNARY(LeftUnary<'a>),
     BINARY(Binary<'a>),
-    // GROUP(Group<'a>),
+    GROUP(Group<'a>),
+    // the empty tuple `()`
+    UNIT,
     CALL(Call<'a>),
     TYPE(Typ),
+    // `enum { Circle(f64) Empty }`, each variant and the types of its payload
+    ENUM(Vec<(String, Vec<Type>)>),
+    VARIANT(Variant<'a>),
+    MATCH(Match<'a>),
+    STRUCT_LITERAL(StructLiteral<'a>),
+    // `[1, 2, 3]`
+    ARRAY_LITERAL(Vec<ParsedAST<'a>>),
+    INDEX(Index<'a>),
+    NULL,
+    // `p.?`, the pointer in an optional, which must not be null
+    UNWRAP(Box<ParsedAST<'a>>),
+    // `unsafe { ... }`, pointers can be offset inside it
+    UNSAFE(Box<ParsedAST<'a>>),
     // STRUCT_TYPES_LIST(StructTypesList<'a>),
     LHS_ACCESS(LhsAccess<'a>),
     // DIRECTIVE(Directive<'a>),
+    // a statement with a syntax error (which has already been reported)
+    ERROR,
 }
@@ -1,42 +1,76 @@
 extern crate llvm_sys;
 use llvm_sys::core::{
-    LLVMAddGlobal, LLVMAppendBasicBlock, LLVMAppendBasicBlockInContext, LLVMArrayType,
-    LLVMArrayType2, LLVMBasicBlockAsValue, LLVMBuildAlloca, LLVMBuildBitCast, LLVMBuildBr,
-    LLVMBuildCall2, LLVMBuildCast, LLVMBuildCondBr, LLVMBuildGlobalString,
-    LLVMBuildGlobalStringPtr, LLVMBuildICmp, LLVMBuildIntCast, LLVMBuildIntCast2, LLVMBuildLoad2,
-    LLVMBuildRetVoid, LLVMBuildStore, LLVMBuildStructGEP2, LLVMConstArray2, LLVMConstInt,
-    LLVMConstPointerNull, LLVMConstStruct, LLVMCreateBasicBlockInContext, LLVMGetNamedGlobal,
-    LLVMGetParam, LLVMGetStructName, LLVMGetTypeByName2, LLVMInt1Type, LLVMInt32Type, LLVMInt8Type,
-    LLVMPointerType, LLVMPositionBuilder, LLVMPositionBuilderAtEnd, LLVMSetDataLayout,
-    LLVMStructCreateNamed, LLVMStructSetBody, LLVMStructType, LLVMStructTypeInContext,
-    LLVMVoidType,
+    LLVMAddGlobal, LLVMAddIncoming, LLVMAppendBasicBlockInContext, LLVMArrayType2, LLVMBuildAdd,
+    LLVMBuildAlloca, LLVMBuildAnd, LLVMBuildBr, LLVMBuildCall2, LLVMBuildCondBr,
+    LLVMBuildExtractValue, LLVMBuildFAdd, LLVMBuildFCmp, LLVMBuildFDiv, LLVMBuildFMul,
+    LLVMBuildFNeg, LLVMBuildFPCast, LLVMBuildFPExt, LLVMBuildFPToSI, LLVMBuildFRem, LLVMBuildFSub,
+    LLVMBuildGEP2, LLVMBuildGlobalString, LLVMBuildGlobalStringPtr, LLVMBuildICmp,
+    LLVMBuildInsertValue, LLVMBuildIntCast2, LLVMBuildLoad2, LLVMBuildMul, LLVMBuildNeg,
+    LLVMBuildPhi, LLVMBuildRet, LLVMBuildRetVoid, LLVMBuildSDiv, LLVMBuildSExt, LLVMBuildSIToFP,
+    LLVMBuildSRem, LLVMBuildStore, LLVMBuildStructGEP2, LLVMBuildSub, LLVMBuildUDiv, LLVMBuildURem,
+    LLVMBuildUnreachable, LLVMBuildZExt, LLVMConstInt, LLVMConstNull, LLVMConstPointerNull,
+    LLVMConstReal, LLVMConstStruct, LLVMCountParamTypes, LLVMCreateBuilder, LLVMDisposeBuilder,
+    LLVMDoubleType, LLVMFloatType, LLVMFunctionType, LLVMGetAllocatedType, LLVMGetArrayLength2,
+    LLVMGetBasicBlockTerminator, LLVMGetEntryBasicBlock, LLVMGetFirstInstruction,
+    LLVMGetGlobalContext, LLVMGetInsertBlock, LLVMGetIntTypeWidth, LLVMGetNamedFunction,
+    LLVMGetParam, LLVMGetParamTypes, LLVMGetReturnType, LLVMGetTypeByName2, LLVMGetTypeKind,
+    LLVMGetUndef, LLVMGlobalGetValueType, LLVMInt16Type, LLVMInt1Type, LLVMInt32Type,
+    LLVMInt64Type, LLVMInt8Type, LLVMIntType, LLVMIsAGlobalVariable, LLVMIsFunctionVarArg,
+    LLVMIsOpaqueStruct, LLVMPointerType, LLVMPositionBuilderAtEnd, LLVMPositionBuilderBefore,
+    LLVMSetInitializer, LLVMSizeOf, LLVMStructCreateNamed, LLVMStructGetTypeAtIndex,
+    LLVMStructSetBody, LLVMStructType, LLVMTypeOf, LLVMVoidType,
 };
-use llvm_sys::execution_engine::LLVMGetGlobalValueAddress;
 use llvm_sys::prelude::{LLVMModuleRef, LLVMValueRef};
-use llvm_sys::{LLVMBasicBlock, LLVMBuilder, LLVMContext, LLVMModule, LLVMType, LLVMValue};
-use log::{debug, error, info, warn};
+use llvm_sys::{
+    LLVMBasicBlock, LLVMBuilder, LLVMContext, LLVMIntPredicate, LLVMModule, LLVMRealPredicate,
+    LLVMType, LLVMTypeKind, LLVMValue,
+};
+use log::{debug, error};
 use std::ffi::{CStr, CString};
-use std::fmt::format;
 use std::fs::File;
 use std::io::Write;
 use std::time::Instant;
 use std::{fs, process::Command};
 
-use crate::ir::{IRValue, Instruction, Ref};
+use crate::diagnostic::{Diagnostic, DiagnosticSink, Severity};
+use crate::ir::{Comparison, IRValue, Instruction, Ref};
 use crate::symtable::SymTable;
-use crate::types::Type;
+use crate::token::Position;
+use crate::types::{enum_layout, Enums, Structs, Type};
 
 pub struct LLVMValueBundle {
     pub llvm_value: LLVMValueRef,
     pub is_ref: bool,
 }
 
-pub struct LLVMCodeGenerator {
+// where break and continue go in the loop we are generating
+pub struct LoopBlocks {
+    pub continue_block: *mut LLVMBasicBlock,
+    pub break_block: *mut LLVMBasicBlock,
+}
+
+// where the code we are generating goes, passed down to everything that adds instructions
+// or blocks rather than each part separately
+#[derive(Clone, Copy)]
+pub struct InsertPoint {
+    pub context: *mut LLVMContext,
+    pub module: *mut LLVMModule,
+    pub builder: *mut LLVMBuilder,
+    pub current_function: *mut LLVMValue,
+}
+
+pub struct LLVMCodeGenerator<'a> {
+    pub diagnostics: &'a DiagnosticSink,
+    // the position of the code we are currently generating (from LOC instructions)
+    pub position: Option<Position>,
     pub anon_local_counter: usize,
     pub anon_string_counter: usize,
     pub anon_local_block_counter: usize,
-    pub str_buffer: String,
     pub sym_table: SymTable<String, LLVMValueBundle>,
+    pub loops: Vec<LoopBlocks>,
+    // the types that have been declared, enums are laid out from them
+    pub structs: Structs,
+    pub enums: Enums,
 }
 
 const TYPE_STRUCT_NAME: &str = "Runtime_Type";
@@ -46,7 +80,7 @@ References:
 - https://github.com/lyledean1/calculon/blob/main/src/main.rs
 - https://medium.com/@jayphelps/using-llvm-from-rust-to-generate-webassembly-93e8c193fdb4
 */
-impl LLVMCodeGenerator {
+impl LLVMCodeGenerator<'_> {
     pub fn generate(&mut self, instruction: &Instruction) {
         let now = Instant::now();
 
@@ -60,37 +94,76 @@ impl LLVMCodeGenerator {
 
         // self.generate_instruction(instruction);
 
+        let Some(contents_str) = self.generate_module(instruction) else {
+            return;
+        };
+        let mut ir_file = File::create("./build/build.ir").expect("unable to create file");
+        if let Err(_) = ir_file.write_all(contents_str.as_bytes()) {
+            panic!("failed to write ir");
+        }
+
+        Command::new("llc")
+            .args(["./build/build.ir", "-o", "./build/build.s"])
+            .output()
+            .expect("failed to build ./build/build.ir");
+
+        Command::new("clang")
+            .args(["-c", "./build/build.s", "-o", "./build/build.o"])
+            .output()
+            .expect("failed to build ./build/build.s");
+
+        Command::new("clang")
+            .args(["./build/build.o", "-o", "./build/build.exe"])
+            .output()
+            .expect("failed to build ./build/build.o");
+
+        let elapsed = now.elapsed();
+        debug!(
+            "LLVM codegen time elapsed {:.2?}ms ({:.2?}s).",
+            elapsed.as_millis(),
+            elapsed.as_secs()
+        );
+    }
+
+    // generate the module for the program and give back its ir, or none if there were errors
+    pub fn generate_module(&mut self, instruction: &Instruction) -> Option<String> {
         unsafe {
             let context = llvm_sys::core::LLVMContextCreate();
-            let module =
-                llvm_sys::core::LLVMModuleCreateWithName(b"my_module\0".as_ptr() as *const _);
+            let module = llvm_sys::core::LLVMModuleCreateWithName(c"my_module".as_ptr());
             let builder = llvm_sys::core::LLVMCreateBuilderInContext(context);
 
-            self.generate_runtime(context, module, builder);
-            self.generate_builtins(module, builder);
+            self.generate_runtime(context);
+            self.generate_builtins(module);
 
             // Get the type signature for void nop(void);
             // Then create it in our module.
             let void = llvm_sys::core::LLVMVoidTypeInContext(context);
-            let i32_type = llvm_sys::core::LLVMInt32Type();
             let function_type = llvm_sys::core::LLVMFunctionType(void, std::ptr::null_mut(), 0, 0);
-            let function = llvm_sys::core::LLVMAddFunction(
-                module,
-                b"main\0".as_ptr() as *const _,
-                function_type,
-            );
+            let function = llvm_sys::core::LLVMAddFunction(module, c"main".as_ptr(), function_type);
 
             // Create a basic block in the function and set our builder to generate
             // code in it.
-            let bb = llvm_sys::core::LLVMAppendBasicBlockInContext(
-                context,
-                function,
-                b"entry\0".as_ptr() as *const _,
-            );
+            let bb =
+                llvm_sys::core::LLVMAppendBasicBlockInContext(context, function, c"entry".as_ptr());
 
             llvm_sys::core::LLVMPositionBuilderAtEnd(builder, bb);
 
-            self.generate_instruction(instruction, context, module, builder, bb, function);
+            self.generate_instruction(
+                instruction,
+                InsertPoint {
+                    context,
+                    module,
+                    builder,
+                    current_function: function,
+                },
+            );
+
+            if self.diagnostics.has_errors() {
+                llvm_sys::core::LLVMDisposeBuilder(builder);
+                llvm_sys::core::LLVMDisposeModule(module);
+                llvm_sys::core::LLVMContextDispose(context);
+                return None;
+            }
 
             // llvm_sys::core::LLVMPositionBuilderAtEnd(builder, bb);
 
@@ -98,47 +171,18 @@ impl LLVMCodeGenerator {
             llvm_sys::core::LLVMBuildRetVoid(builder);
 
             let s = llvm_sys::core::LLVMPrintModuleToString(module);
-            let contents_str = CStr::from_ptr(s).to_str().unwrap();
-            let mut ir_file = File::create("./build/build.ir").expect("unable to create file");
-            if let Err(_) = ir_file.write_all(contents_str.as_bytes()) {
-                panic!("failed to write ir");
-            }
+            let contents_str = CStr::from_ptr(s).to_string_lossy().into_owned();
+            llvm_sys::core::LLVMDisposeMessage(s);
 
             // Clean up. Values created in the context mostly get cleaned up there.
             llvm_sys::core::LLVMDisposeBuilder(builder);
             llvm_sys::core::LLVMDisposeModule(module);
             llvm_sys::core::LLVMContextDispose(context);
-
-            Command::new("llc")
-                .args(["./build/build.ir", "-o", "./build/build.s"])
-                .output()
-                .expect("failed to build ./build/build.ir");
-
-            Command::new("clang")
-                .args(["-c", "./build/build.s", "-o", "./build/build.o"])
-                .output()
-                .expect("failed to build ./build/build.s");
-
-            Command::new("clang")
-                .args(["./build/build.o", "-o", "./build/build.exe"])
-                .output()
-                .expect("failed to build ./build/build.o");
+            Some(contents_str)
         }
-
-        let elapsed = now.elapsed();
-        debug!(
-            "LLVM codegen time elapsed {:.2?}ms ({:.2?}s).",
-            elapsed.as_millis(),
-            elapsed.as_secs()
-        );
     }
 
-    fn generate_runtime(
-        &mut self,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-    ) {
+    fn generate_runtime(&mut self, context: *mut LLVMContext) {
         unsafe {
             let label_var = CString::new(TYPE_STRUCT_NAME.as_bytes()).expect("expected string");
             let label_var_ptr = label_var.as_ptr();
@@ -148,27 +192,51 @@ impl LLVMCodeGenerator {
         }
     }
 
-    fn generate_builtins(
+    fn generate_builtins(&mut self, module: LLVMModuleRef) {
+        unsafe {
+            let i8_ptr = LLVMPointerType(LLVMInt8Type(), 0);
+            self.declare_builtin(module, "printf", LLVMInt32Type(), vec![i8_ptr], true);
+            // used to build strings for format
+            self.declare_builtin(
+                module,
+                "snprintf",
+                LLVMInt32Type(),
+                vec![i8_ptr, LLVMInt64Type(), i8_ptr],
+                true,
+            );
+            self.declare_builtin(module, "malloc", i8_ptr, vec![LLVMInt64Type()], false);
+            // strings are compared by their contents
+            self.declare_builtin(
+                module,
+                "strcmp",
+                LLVMInt32Type(),
+                vec![i8_ptr, i8_ptr],
+                false,
+            );
+            // used when an index is out of bounds
+            self.declare_builtin(module, "exit", LLVMVoidType(), vec![LLVMInt32Type()], false);
+        }
+    }
+
+    fn declare_builtin(
         &mut self,
         module: LLVMModuleRef,
-        builder: *mut LLVMBuilder,
-        // current_block: *mut LLVMBasicBlock,
+        name: &str,
+        return_type: *mut LLVMType,
+        mut params: Vec<*mut LLVMType>,
+        variadic: bool,
     ) {
         unsafe {
             let function_type = llvm_sys::core::LLVMFunctionType(
-                LLVMInt32Type(),
-                // std::ptr::null_mut(),
-                &mut LLVMPointerType(LLVMInt8Type(), 0),
-                1,
-                0,
-            );
-            let function = llvm_sys::core::LLVMAddFunction(
-                module,
-                b"printf\0".as_ptr() as *const _,
-                function_type,
+                return_type,
+                params.as_mut_ptr(),
+                params.len() as u32,
+                variadic as i32,
             );
+            let c_name = CString::new(name).unwrap();
+            let function = llvm_sys::core::LLVMAddFunction(module, c_name.as_ptr(), function_type);
             self.sym_table.add(
-                "printf".to_string(),
+                name.to_string(),
                 LLVMValueBundle {
                     llvm_value: function,
                     is_ref: false,
@@ -180,118 +248,170 @@ impl LLVMCodeGenerator {
     fn generate_instruction(
         &mut self,
         instruction: &Instruction,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            module,
+            builder,
+            current_function,
+            ..
+        } = at;
         match instruction {
-            Instruction::PROGRAM(instructions) => self.generate_program(
-                instructions,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::BLOCK(name, instructions) => self.generate_block(
-                instructions,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::ADD(location, first, second) => self.generate_add(
-                location,
-                first,
-                second,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::STACK_VAR(location, typ, value) => self.generate_stack_var(
-                location,
-                typ,
-                value,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::LOAD(location, value) => self.generate_load(
-                location,
-                value,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::CALL(location, callee, args) => self.generate_call(
-                location,
-                callee,
-                args,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::STORE(storee, value) => self.generate_store(
-                storee,
-                value,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::FUNC(name, params, instruction) => self.generate_func(
-                name,
-                params,
-                instruction,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
+            Instruction::PROGRAM(instructions) => self.generate_program(instructions, at),
+            Instruction::BLOCK(_, instructions) => self.generate_block(instructions, at),
+            Instruction::ADD(location, first, second, typ)
+            | Instruction::SUB(location, first, second, typ)
+            | Instruction::MUL(location, first, second, typ)
+            | Instruction::DIV(location, first, second, typ)
+            | Instruction::MOD(location, first, second, typ) => {
+                self.generate_arithmetic(instruction, location, first, second, typ, builder)
+            }
+            Instruction::CMP(location, comparison, first, second, typ) => {
+                self.generate_cmp(location, comparison, first, second, typ, builder)
+            }
+            Instruction::NEG(location, value, typ) => {
+                self.generate_neg(location, value, typ, builder)
+            }
+            Instruction::NOT(location, value) => self.generate_not(location, value, builder),
+            Instruction::STACK_VAR(location, typ, value) => {
+                self.generate_stack_var(location, typ, value, at)
+            }
+            Instruction::LOAD(location, value) => self.generate_load(location, value, at),
+            Instruction::CALL(location, callee, args) => {
+                self.generate_call(location, callee, args, at)
+            }
+            Instruction::CALL_VALUE(location, callee, typ, args) => {
+                self.generate_call_value(location, callee, typ, args, builder)
+            }
+            Instruction::CLOSURE(location, function, values) => {
+                self.generate_closure(location, function, values, module, builder)
+            }
+            Instruction::STORE(storee, value) => self.generate_store(storee, value, at),
+            Instruction::FUNC(name, captures, params, return_type, instruction) => {
+                self.generate_func(name, captures, params, return_type, instruction, at)
+            }
             // Instruction::BLOCK(label, block) => self.generate_block(label, block),
             // Instruction::STACK_VAR(label, instruction_data) => {
             //     self.generate_stack_var(label, instruction_data)
             // }
-            Instruction::COND_BR(condition, body, else_body) => self.generate_cond_br(
-                condition,
-                body,
-                else_body,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            Instruction::TYPE(label, types) => self.generate_type(
-                label,
-                types,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            ),
-            _ => panic!("unsupported instruction {:?}", instruction),
+            Instruction::COND_BR(condition, body, else_body) => {
+                self.generate_cond_br(condition, body, else_body, at)
+            }
+            Instruction::IF(location, condition, body, body_value, else_body, else_value) => self
+                .generate_if(
+                    location,
+                    condition,
+                    (body, body_value),
+                    (else_body, else_value),
+                    at,
+                ),
+            Instruction::LOOP(condition_block, condition, body, step) => {
+                self.generate_loop(condition_block, condition, body, step, at)
+            }
+            Instruction::BREAK | Instruction::CONTINUE => self.generate_jump(instruction, at),
+            Instruction::RET(value) => self.generate_ret(value, at),
+            Instruction::TYPE(label, types) => self.generate_type(label, types),
+            Instruction::ENUM(label, variants) => self.generate_enum(label, variants),
+            Instruction::VARIANT(location, name, tag, values) => {
+                self.generate_variant(location, name, *tag, values, at)
+            }
+            Instruction::TAG(location, value) => self.generate_tag(location, value, builder),
+            Instruction::PAYLOAD(location, value, name, tag, index) => {
+                self.generate_payload(location, value, name, *tag, *index, at)
+            }
+            Instruction::STRUCT(location, name, values) => {
+                self.generate_struct(location, name, values, builder)
+            }
+            Instruction::FIELD(location, value, path) => {
+                self.generate_field(location, value, path, builder)
+            }
+            Instruction::STORE_FIELD(storee, path, value) => {
+                self.generate_store_field(storee, path, value, builder)
+            }
+            Instruction::ARRAY(location, values) => self.generate_array(location, values, builder),
+            Instruction::INDEX(location, value, index, typ) => {
+                self.generate_index(location, value, index, typ, at)
+            }
+            Instruction::SLICE(location, value, start, end, typ) => {
+                self.generate_slice(location, value, start, end, typ, at)
+            }
+            Instruction::LEN(location, value) => {
+                let (_, len) = self.elements(value, builder, current_function)?;
+                self.sym_table.add(
+                    location.to_string(),
+                    LLVMValueBundle {
+                        llvm_value: len,
+                        is_ref: false,
+                    },
+                );
+                Some(len)
+            }
+            Instruction::STORE_INDEX(storee, index, value, typ) => {
+                self.generate_store_index(storee, index, value, typ, at)
+            }
+            Instruction::ADDRESS(location, variable, index, typ) => {
+                self.generate_address(location, variable, index, typ, at)
+            }
+            Instruction::DEREF(location, pointer, typ) => {
+                self.generate_deref(location, pointer, typ, builder)
+            }
+            Instruction::STORE_DEREF(pointer, path, value, typ) => {
+                self.generate_store_deref(pointer, path, value, typ, builder)
+            }
+            Instruction::OFFSET(location, pointer, count, typ) => {
+                self.generate_offset(location, pointer, count, typ, builder)
+            }
+            Instruction::UNWRAP(location, value) => self.generate_unwrap(location, value, at),
+            // todo emit debug locations
+            Instruction::LOC(position) => {
+                self.position = Some(position.clone());
+                None
+            }
+            _ => {
+                self.error(
+                    "E0400",
+                    format!(
+                        "code generation does not support this yet ({})",
+                        instruction.to_string_for_writing().trim()
+                    ),
+                );
+                None
+            }
         }
     }
 
-    // todo this fundementally doesn't work as rust strings are awful
-    fn string_to_c_str(&self, string: &String) -> *const i8 {
-        let c = CString::new(string.to_string()).unwrap();
-        c.as_ptr()
+    // codegen errors point at the statement we are generating
+    fn error(&self, code: &'static str, message: String) {
+        let diagnostic = match &self.position {
+            Some(position) => Diagnostic::error(code, message, position.clone()),
+            None => Diagnostic::new(Severity::ERROR, code, message),
+        };
+        self.diagnostics.emit(diagnostic);
+    }
+
+    // report a name we can't find, the returned value lets us carry on generating
+    fn undefined(&self, name: &String) -> LLVMValueRef {
+        self.error("E0401", format!("could not find `{}`", name));
+        unsafe { LLVMGetUndef(LLVMInt32Type()) }
+    }
+
+    fn unsupported(&self, what: String) -> LLVMValueRef {
+        self.error(
+            "E0400",
+            format!("code generation does not support {} yet", what),
+        );
+        unsafe { LLVMGetUndef(LLVMInt32Type()) }
+    }
+
+    // llvm strings are null terminated so can't contain a \0
+    fn c_string(&self, s: &String) -> Option<CString> {
+        match CString::new(s.to_string()) {
+            Ok(c_str) => Some(c_str),
+            Err(_) => {
+                self.unsupported("strings containing `\\0`".to_string());
+                None
+            }
+        }
     }
 
     fn ir_value_to_llvm_value(
@@ -303,17 +423,30 @@ impl LLVMCodeGenerator {
 
         unsafe {
             match ir_value {
-                IRValue::STRUCT(_) => todo!(),
+                IRValue::STRUCT(_) => self.unsupported("struct values".to_string()),
+                IRValue::VARIANT(..) => self.unsupported("enum values".to_string()),
+                IRValue::ARRAY(_) | IRValue::SLICE(_) => {
+                    self.unsupported("array values".to_string())
+                }
+                // pointers are made by the instructions that take addresses, these only exist
+                // in the interpreter
+                IRValue::POINTER(_) | IRValue::CELL(_) => {
+                    self.unsupported("interpreter pointers".to_string())
+                }
+                IRValue::NULL => LLVMConstNull(LLVMPointerType(LLVMInt8Type(), 0)),
                 IRValue::INT(i) => LLVMConstInt(LLVMInt32Type(), *i as u64, 1),
-                IRValue::FLOAT(_) => todo!(),
+                // chars are their code point
+                IRValue::CHAR(c) => LLVMConstInt(LLVMInt32Type(), *c as u64, 0),
+                IRValue::FLOAT(f) => LLVMConstReal(LLVMDoubleType(), *f),
+                // unit has nothing in it, so it is the empty struct
+                IRValue::UNIT => LLVMConstStruct(std::ptr::null_mut(), 0, 0),
                 IRValue::REF(r) => {
-                    let value_bundle = self
-                        .sym_table
-                        .get(r.value.to_owned())
-                        .expect("expected value");
-                    // let c_str = CString::new(format!("{}_local", self.anon_local_counter)).unwrap();
-                    let c_string =
-                        self.string_to_c_str(&format!("{}_local", self.anon_local_counter));
+                    let Some(value_bundle) = self.sym_table.get(r.value.to_owned()) else {
+                        return self.undefined(&r.value);
+                    };
+                    // kept until the load is built, llvm copies the name
+                    let c_string = CString::new(format!("{}_local", self.anon_local_counter))
+                        .expect("i am a c string");
                     self.anon_local_counter += 1;
                     if value_bundle.is_ref {
                         // todo this is not always right. we don't want to load if its not an alloca instruction.
@@ -321,9 +454,9 @@ impl LLVMCodeGenerator {
                         // we need to store some information along with it.
                         return LLVMBuildLoad2(
                             builder,
-                            LLVMInt32Type(),
+                            self.variable_type(value_bundle.llvm_value),
                             value_bundle.llvm_value.clone(),
-                            c_string,
+                            c_string.as_ptr(),
                         );
                     }
 
@@ -331,17 +464,23 @@ impl LLVMCodeGenerator {
                 }
                 IRValue::STRING(s) => {
                     // todo make this better, helper function for creating llvm strings
-                    let c_str = CString::new(s.to_string()).expect("i am a c string");
+                    let Some(c_str) = self.c_string(s) else {
+                        return LLVMGetUndef(LLVMPointerType(LLVMInt8Type(), 0));
+                    };
                     let ptr = c_str.as_ptr();
                     let c_str_label =
                         CString::new(format!("{}_anon_string", self.anon_string_counter))
                             .expect("i am a c string");
                     self.anon_string_counter += 1;
                     let ptr_label = c_str_label.as_ptr();
-                    let mut llvm_string_value = LLVMBuildGlobalString(builder, ptr, ptr_label);
+                    let llvm_string_value = LLVMBuildGlobalString(builder, ptr, ptr_label);
                     llvm_string_value
                 }
-                IRValue::INTRINSIC(_) => todo!(),
+                IRValue::INTRINSIC(i) => self.unsupported(format!("intrinsic {} as a value", i)),
+                // closures are made by the CLOSURE instruction
+                IRValue::CLOSURE(function, _) => {
+                    self.unsupported(format!("closure of {} as a constant", function))
+                }
             }
         }
     }
@@ -349,21 +488,41 @@ impl LLVMCodeGenerator {
     fn generate_program(
         &mut self,
         instructions: &Box<Vec<Instruction>>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint { module, .. } = at;
+        // types and functions can be used before they are defined. all the struct types are
+        // declared before any are filled in, as their fields can be other structs. enums are
+        // filled in last, as their layout depends on every type in them
         for instruction in instructions.iter() {
-            self.generate_instruction(
-                instruction,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            );
+            match instruction {
+                Instruction::TYPE(name, _) => {
+                    self.declare_struct(name);
+                }
+                Instruction::ENUM(name, variants) => {
+                    self.declare_struct(name);
+                    self.enums.insert(name.to_string(), variants.clone());
+                }
+                _ => {}
+            }
+        }
+        for instruction in instructions.iter() {
+            if let Instruction::TYPE(name, types) = instruction {
+                self.generate_type(name, types);
+            }
+        }
+        for instruction in instructions.iter() {
+            if let Instruction::ENUM(name, variants) = instruction {
+                self.generate_enum(name, variants);
+            }
+        }
+        for instruction in instructions.iter() {
+            if let Instruction::FUNC(name, captures, params, return_type, _) = instruction {
+                self.declare_func(name, captures, params, return_type, module);
+            }
+        }
+        for instruction in instructions.iter() {
+            self.generate_instruction(instruction, at);
         }
         None
     }
@@ -371,21 +530,10 @@ impl LLVMCodeGenerator {
     fn generate_block(
         &mut self,
         instructions: &Box<Vec<Instruction>>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
         for instruction in instructions.iter() {
-            self.generate_instruction(
-                instruction,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            );
+            self.generate_instruction(instruction, at);
         }
         None
     }
@@ -394,20 +542,17 @@ impl LLVMCodeGenerator {
         &mut self,
         label: &String,
         value: &Ref,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
         unsafe {
             // LLVMBuildLoad2(builder, Ty, PointerVal, Name)>
             let label_var = CString::new(label.as_bytes()).expect("i am a c string");
             let label_var_ptr = label_var.as_ptr();
-            let llvm_ptr_val = self
-                .sym_table
-                .get(value.value.to_string())
-                .expect("expected value");
+            let Some(llvm_ptr_val) = self.sym_table.get(value.value.to_string()) else {
+                self.undefined(&value.value);
+                return None;
+            };
             if llvm_ptr_val.is_ref {
                 let load_value = LLVMBuildLoad2(
                     builder,
@@ -422,112 +567,757 @@ impl LLVMCodeGenerator {
                         is_ref: false, // todo this may be wrong! check the llvm_ptr_val
                     },
                 );
+            } else {
+                self.error(
+                    "E0402",
+                    format!("`{}` must be a reference to load it", value.value),
+                );
+            }
+        }
+        None
+    }
+
+    // the module and the other types we use are in the global context, so structs are too
+    fn declare_struct(&self, name: &String) -> *mut LLVMType {
+        unsafe {
+            let context = LLVMGetGlobalContext();
+            let c_name = CString::new(name.as_bytes()).expect("expected string");
+            let struct_type = LLVMGetTypeByName2(context, c_name.as_ptr());
+            if !struct_type.is_null() {
+                return struct_type;
+            }
+            LLVMStructCreateNamed(context, c_name.as_ptr())
+        }
+    }
+
+    fn generate_type(&mut self, label: &String, types: &Vec<Type>) -> Option<*mut LLVMValue> {
+        // the field names don't matter for the layout
+        let fields = types.iter().map(|typ| (String::new(), typ.clone()));
+        self.structs.insert(label.to_string(), fields.collect());
+        unsafe {
+            let struct_type = self.declare_struct(label);
+            // top level types already have their fields (see generate_program)
+            if LLVMIsOpaqueStruct(struct_type) == 0 {
+                return None;
             }
-            panic!("must be ref to do a load!");
+            let mut fields: Vec<*mut LLVMType> = types
+                .iter()
+                .map(|typ| self.type_to_llvm_type(typ))
+                .collect();
+            LLVMStructSetBody(struct_type, fields.as_mut_ptr(), fields.len() as u32, 0);
         }
         None
     }
 
-    fn generate_type(
+    // an enum is its tag, then room for the largest payload. the room is made of integers as
+    // wide as the payloads' alignment so it starts where types::enum_layout says. each
+    // variant reads it as a struct of its payload
+    fn generate_enum(
         &mut self,
         label: &String,
-        types: &Vec<Type>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        variants: &Vec<(String, Vec<Type>)>,
     ) -> Option<*mut LLVMValue> {
+        self.enums.insert(label.to_string(), variants.clone());
         unsafe {
-            let mut typs_vec: Vec<*mut LLVMType> = vec![];
-            for typ in types {
-                typs_vec.push(LLVMInt32Type());
+            let enum_type = self.declare_struct(label);
+            if LLVMIsOpaqueStruct(enum_type) == 0 {
+                return None;
             }
+            // only an enum that holds itself or an unknown type has no layout, which the type
+            // checker reports before we get here
+            let Some((offset, align, size)) = enum_layout(variants, &self.structs, &self.enums)
+            else {
+                self.error("E0403", format!("`{}` has no size", label));
+                return None;
+            };
+            let room = LLVMArrayType2(
+                LLVMIntType((align * 8) as u32),
+                ((size - offset) / align) as u64,
+            );
+            let mut fields = vec![LLVMInt32Type(), room];
+            LLVMStructSetBody(enum_type, fields.as_mut_ptr(), 2, 0);
+        }
+        None
+    }
 
-            let label_var =
-                CString::new(format!("{}_struct", label).as_bytes()).expect("expected string");
-            let label_var_ptr = label_var.as_ptr();
-            let struct_type = LLVMStructCreateNamed(context, label_var_ptr);
+    // the struct a variant's payload is read as
+    fn payload_type(&self, name: &String, tag: usize) -> Option<*mut LLVMType> {
+        let Some((_, payload)) = self.enums.get(name).and_then(|variants| variants.get(tag)) else {
+            self.undefined(name);
+            return None;
+        };
+        unsafe {
+            let mut fields: Vec<*mut LLVMType> = payload
+                .iter()
+                .map(|typ| self.type_to_llvm_type(typ))
+                .collect();
+            Some(LLVMStructType(fields.as_mut_ptr(), fields.len() as u32, 0))
+        }
+    }
 
-            LLVMStructSetBody(
-                struct_type,
-                typs_vec.as_mut_ptr(),
-                typs_vec.len().try_into().unwrap(),
-                0,
+    fn generate_variant(
+        &mut self,
+        label: &String,
+        name: &String,
+        tag: usize,
+        values: &Vec<IRValue>,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            builder,
+            current_function,
+            ..
+        } = at;
+        let payload_type = self.payload_type(name, tag)?;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let enum_type = self.type_to_llvm_type(&Type::ENUM(name.to_string()));
+            // built in memory, as the payload is read as a different type to the room for it
+            let slot = self.entry_alloca(current_function, enum_type, unnamed);
+            LLVMBuildStore(builder, LLVMConstNull(enum_type), slot);
+            let tag_pointer = LLVMBuildStructGEP2(builder, enum_type, slot, 0, unnamed);
+            LLVMBuildStore(
+                builder,
+                LLVMConstInt(LLVMInt32Type(), tag as u64, 0),
+                tag_pointer,
             );
+            let payload = LLVMBuildStructGEP2(builder, enum_type, slot, 1, unnamed);
+            for (i, value) in values.iter().enumerate() {
+                let value = self.ir_value_to_llvm_value(value, builder);
+                let field_type = LLVMStructGetTypeAtIndex(payload_type, i as u32);
+                let value = self.convert(value, field_type, builder);
+                let field = LLVMBuildStructGEP2(builder, payload_type, payload, i as u32, unnamed);
+                LLVMBuildStore(builder, value, field);
+            }
+            let value = LLVMBuildLoad2(builder, enum_type, slot, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
 
-            let type_struct_name =
-                CString::new(TYPE_STRUCT_NAME.as_bytes()).expect("expected string");
-            let type_struct_name_ptr = type_struct_name.as_ptr();
-            let type_struct_type = LLVMGetTypeByName2(context, type_struct_name_ptr);
-
-            // let tmp = CString::new("tmp".as_bytes()).expect("expected string");
-            // let tmp_ptr = tmp.as_ptr();
-            // LLVMBuildAlloca(builder, type_struct_type, tmp_ptr);
+    fn generate_tag(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let value = self.ir_value_to_llvm_value(value, builder);
+            let tag = LLVMBuildExtractValue(builder, value, 0, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: tag,
+                    is_ref: false,
+                },
+            );
+            Some(tag)
+        }
+    }
 
-            // todo we need to return an LLVM value to the actual type struct!
+    fn generate_payload(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        name: &String,
+        tag: usize,
+        index: usize,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            builder,
+            current_function,
+            ..
+        } = at;
+        let payload_type = self.payload_type(name, tag)?;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let enum_type = self.type_to_llvm_type(&Type::ENUM(name.to_string()));
+            // a variable is read from its memory, any other value is put in memory first
+            let variable = match value {
+                IRValue::REF(r) => self
+                    .sym_table
+                    .get(r.value.to_string())
+                    .filter(|bundle| bundle.is_ref)
+                    .map(|bundle| bundle.llvm_value),
+                _ => None,
+            };
+            let pointer = match variable {
+                Some(variable) => variable,
+                None => {
+                    let value = self.ir_value_to_llvm_value(value, builder);
+                    let slot = self.entry_alloca(current_function, enum_type, unnamed);
+                    LLVMBuildStore(builder, value, slot);
+                    slot
+                }
+            };
+            let payload = LLVMBuildStructGEP2(builder, enum_type, pointer, 1, unnamed);
+            let field = LLVMBuildStructGEP2(builder, payload_type, payload, index as u32, unnamed);
+            let field_type = LLVMStructGetTypeAtIndex(payload_type, index as u32);
+            let value = LLVMBuildLoad2(builder, field_type, field, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
         }
-        None
     }
 
-    fn generate_cond_br(
+    fn generate_struct(
         &mut self,
-        condition: &IRValue,
-        body: &Box<Instruction>,
-        else_body: &Option<Box<Instruction>>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
+        label: &String,
+        name: &String,
+        values: &Vec<IRValue>,
         builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
     ) -> Option<*mut LLVMValue> {
         unsafe {
-            let int_cast_c_str = CString::new(format!("{}", self.anon_local_counter)).unwrap();
-            self.anon_local_counter += 1;
-            let int_cast_c_str_ptr = int_cast_c_str.as_ptr();
-            let mut cond_value: *mut LLVMValue;
+            let unnamed = c"".as_ptr();
+            let struct_type = self.type_to_llvm_type(&Type::STRUCT(name.to_string()));
+            let mut value = LLVMGetUndef(struct_type);
+            for (i, field) in values.iter().enumerate() {
+                let field = self.ir_value_to_llvm_value(field, builder);
+                let field_type = LLVMStructGetTypeAtIndex(struct_type, i as u32);
+                let field = self.convert(field, field_type, builder);
+                value = LLVMBuildInsertValue(builder, value, field, i as u32, unnamed);
+            }
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
 
-            match condition {
-                IRValue::INT(i) => {
-                    cond_value = LLVMBuildIntCast(
-                        builder,
-                        LLVMConstInt(LLVMInt32Type(), *i as u64, 1),
-                        LLVMInt1Type(),
-                        int_cast_c_str_ptr,
-                    );
+    fn generate_field(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        path: &Vec<usize>,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            // the field of a variable is loaded from its memory, rather than loading the
+            // whole struct to get one field
+            let variable = match value {
+                IRValue::REF(r) => self
+                    .sym_table
+                    .get(r.value.to_string())
+                    .filter(|bundle| bundle.is_ref)
+                    .map(|bundle| bundle.llvm_value),
+                _ => None,
+            };
+            let field = match variable {
+                Some(variable) => {
+                    let struct_type = self.variable_type(variable);
+                    let (field, field_type) =
+                        self.field_pointer(variable, struct_type, path, builder);
+                    LLVMBuildLoad2(builder, field_type, field, unnamed)
                 }
-                IRValue::REF(r) => {
-                    let int_val = self.sym_table.get(r.value.to_string()).unwrap();
-                    let load_c_str: CString =
-                        CString::new(format!("{}", self.anon_local_counter)).unwrap();
-                    self.anon_local_counter += 1;
-                    let load_c_str_ptr = load_c_str.as_ptr();
-                    if int_val.is_ref {
-                        let load_instr = LLVMBuildLoad2(
-                            builder,
-                            LLVMInt32Type(),
-                            int_val.llvm_value.clone(),
-                            load_c_str_ptr,
-                        );
-                        cond_value = LLVMBuildICmp(
-                            builder,
-                            llvm_sys::LLVMIntPredicate::LLVMIntNE,
-                            load_instr,
-                            LLVMConstInt(LLVMInt32Type(), 0 as u64, 1),
-                            int_cast_c_str_ptr,
-                        );
-                    } else {
-                        cond_value = LLVMBuildICmp(
-                            builder,
-                            llvm_sys::LLVMIntPredicate::LLVMIntNE,
-                            int_val.llvm_value,
-                            LLVMConstInt(LLVMInt32Type(), 0 as u64, 1),
-                            int_cast_c_str_ptr,
-                        );
+                None => {
+                    let mut field = self.ir_value_to_llvm_value(value, builder);
+                    for index in path.iter() {
+                        field = LLVMBuildExtractValue(builder, field, *index as u32, unnamed);
                     }
+                    field
                 }
-                _ => todo!(),
+            };
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: field,
+                    is_ref: false,
+                },
+            );
+            Some(field)
+        }
+    }
+
+    fn generate_store_field(
+        &mut self,
+        storee: &Ref,
+        path: &Vec<usize>,
+        value: &IRValue,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let value = self.ir_value_to_llvm_value(value, builder);
+            let Some(variable) = self.sym_table.get(storee.value.to_string()) else {
+                self.undefined(&storee.value);
+                return None;
+            };
+            if !variable.is_ref {
+                self.error(
+                    "E0402",
+                    format!("`{}` must be a reference to store in it", storee.value),
+                );
+                return None;
+            }
+            let struct_type = self.variable_type(variable.llvm_value);
+            let (field, field_type) =
+                self.field_pointer(variable.llvm_value, struct_type, path, builder);
+            let value = self.convert(value, field_type, builder);
+            LLVMBuildStore(builder, value, field);
+        }
+        None
+    }
+
+    // a pointer to the first element and the number of elements
+    fn slice_type(&self) -> *mut LLVMType {
+        unsafe {
+            let mut fields = vec![LLVMPointerType(LLVMInt8Type(), 0), LLVMInt64Type()];
+            LLVMStructType(fields.as_mut_ptr(), 2, 0)
+        }
+    }
+
+    fn generate_array(
+        &mut self,
+        label: &String,
+        values: &Vec<IRValue>,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let values: Vec<LLVMValueRef> = values
+                .iter()
+                .map(|value| self.ir_value_to_llvm_value(value, builder))
+                .collect();
+            // the elements take the type of the first one
+            let element_type = LLVMTypeOf(values[0]);
+            let mut array = LLVMGetUndef(LLVMArrayType2(element_type, values.len() as u64));
+            for (i, value) in values.iter().enumerate() {
+                let value = self.convert(*value, element_type, builder);
+                array = LLVMBuildInsertValue(builder, array, value, i as u32, unnamed);
+            }
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: array,
+                    is_ref: false,
+                },
+            );
+            Some(array)
+        }
+    }
+
+    // a pointer to the first element of an array or slice and how many elements there are.
+    // arrays that aren't in a variable are put in memory so they can be indexed
+    fn elements(
+        &mut self,
+        value: &IRValue,
+        builder: *mut LLVMBuilder,
+        current_function: *mut LLVMValue,
+    ) -> Option<(LLVMValueRef, LLVMValueRef)> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let variable = match value {
+                IRValue::REF(r) => self
+                    .sym_table
+                    .get(r.value.to_string())
+                    .filter(|bundle| bundle.is_ref)
+                    .map(|bundle| bundle.llvm_value),
+                _ => None,
+            };
+            let (pointer, typ) = match variable {
+                Some(variable) => (variable, self.variable_type(variable)),
+                None => {
+                    let value = self.ir_value_to_llvm_value(value, builder);
+                    let typ = LLVMTypeOf(value);
+                    let pointer = self.entry_alloca(current_function, typ, unnamed);
+                    LLVMBuildStore(builder, value, pointer);
+                    (pointer, typ)
+                }
+            };
+            if LLVMGetTypeKind(typ) == LLVMTypeKind::LLVMArrayTypeKind {
+                let zero = LLVMConstInt(LLVMInt32Type(), 0, 0);
+                let mut indices = vec![zero, zero];
+                let first = LLVMBuildGEP2(builder, typ, pointer, indices.as_mut_ptr(), 2, unnamed);
+                let len = LLVMConstInt(LLVMInt64Type(), LLVMGetArrayLength2(typ), 0);
+                return Some((first, len));
+            }
+            if typ != self.slice_type() {
+                self.unsupported("indexing values that aren't arrays or slices".to_string());
+                return None;
+            }
+            let slice = LLVMBuildLoad2(builder, typ, pointer, unnamed);
+            let first = LLVMBuildExtractValue(builder, slice, 0, unnamed);
+            let len = LLVMBuildExtractValue(builder, slice, 1, unnamed);
+            Some((first, len))
+        }
+    }
+
+    // indexes are i64 like lengths, negative ones become large so one unsigned comparison
+    // checks both ends
+    fn index_value(&mut self, index: &IRValue, builder: *mut LLVMBuilder) -> LLVMValueRef {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let index = self.ir_value_to_llvm_value(index, builder);
+            LLVMBuildIntCast2(builder, index, LLVMInt64Type(), 1, unnamed)
+        }
+    }
+
+    // carry on if the condition holds, otherwise print the message and stop the program
+    fn check(&mut self, holds: LLVMValueRef, message: &str, at: InsertPoint) {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let fail_block =
+                LLVMAppendBasicBlockInContext(context, current_function, c"check_failed".as_ptr());
+            let ok_block =
+                LLVMAppendBasicBlockInContext(context, current_function, c"check_passed".as_ptr());
+            LLVMBuildCondBr(builder, holds, ok_block, fail_block);
+
+            LLVMPositionBuilderAtEnd(builder, fail_block);
+            let message = IRValue::STRING(format!("{}\n", message));
+            let mut args = vec![self.ir_value_to_llvm_value(&message, builder)];
+            if let Some(printf) = self.sym_table.get("printf".to_string()) {
+                let printf = printf.llvm_value;
+                let typ = LLVMGlobalGetValueType(printf);
+                LLVMBuildCall2(builder, typ, printf, args.as_mut_ptr(), 1, unnamed);
+            }
+            if let Some(exit) = self.sym_table.get("exit".to_string()) {
+                let exit = exit.llvm_value;
+                let mut args = vec![LLVMConstInt(LLVMInt32Type(), 1, 0)];
+                LLVMBuildCall2(
+                    builder,
+                    LLVMGlobalGetValueType(exit),
+                    exit,
+                    args.as_mut_ptr(),
+                    1,
+                    unnamed,
+                );
+            }
+            LLVMBuildUnreachable(builder);
+
+            LLVMPositionBuilderAtEnd(builder, ok_block);
+        }
+    }
+
+    // a pointer to the element at the index, after checking it is in bounds
+    fn element_pointer(
+        &mut self,
+        value: &IRValue,
+        index: &IRValue,
+        element_type: *mut LLVMType,
+        at: InsertPoint,
+    ) -> Option<LLVMValueRef> {
+        let InsertPoint {
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let (first, len) = self.elements(value, builder, current_function)?;
+            let index = self.index_value(index, builder);
+            let in_bounds =
+                LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULT, index, len, unnamed);
+            self.check(in_bounds, "index out of bounds", at);
+            let mut indices = vec![index];
+            Some(LLVMBuildGEP2(
+                builder,
+                element_type,
+                first,
+                indices.as_mut_ptr(),
+                1,
+                unnamed,
+            ))
+        }
+    }
+
+    fn generate_index(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        index: &IRValue,
+        typ: &Type,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let element_type = self.type_to_llvm_type(typ);
+            let element = self.element_pointer(value, index, element_type, at)?;
+            let element = LLVMBuildLoad2(builder, element_type, element, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: element,
+                    is_ref: false,
+                },
+            );
+            Some(element)
+        }
+    }
+
+    fn generate_slice(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        start: &IRValue,
+        end: &IRValue,
+        typ: &Type,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let (first, len) = self.elements(value, builder, current_function)?;
+            let start = self.index_value(start, builder);
+            let end = self.index_value(end, builder);
+            // start <= end <= len
+            let ordered = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULE, start, end, unnamed);
+            let fits = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULE, end, len, unnamed);
+            let in_bounds = LLVMBuildAnd(builder, ordered, fits, unnamed);
+            self.check(in_bounds, "index out of bounds", at);
+
+            let mut indices = vec![start];
+            let element_type = self.type_to_llvm_type(typ);
+            let first = LLVMBuildGEP2(
+                builder,
+                element_type,
+                first,
+                indices.as_mut_ptr(),
+                1,
+                unnamed,
+            );
+            let len = LLVMBuildSub(builder, end, start, unnamed);
+            let slice = LLVMGetUndef(self.slice_type());
+            let slice = LLVMBuildInsertValue(builder, slice, first, 0, unnamed);
+            let slice = LLVMBuildInsertValue(builder, slice, len, 1, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: slice,
+                    is_ref: false,
+                },
+            );
+            Some(slice)
+        }
+    }
+
+    fn generate_store_index(
+        &mut self,
+        storee: &Ref,
+        index: &IRValue,
+        value: &IRValue,
+        typ: &Type,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
+        unsafe {
+            let value = self.ir_value_to_llvm_value(value, builder);
+            let element_type = self.type_to_llvm_type(typ);
+            let variable = IRValue::REF(storee.clone());
+            let element = self.element_pointer(&variable, index, element_type, at)?;
+            let value = self.convert(value, element_type, builder);
+            LLVMBuildStore(builder, value, element);
+        }
+        None
+    }
+
+    // the alloca of a variable is its address
+    fn generate_address(
+        &mut self,
+        label: &String,
+        variable: &Ref,
+        index: &Option<IRValue>,
+        typ: &Type,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let Some(bundle) = self.sym_table.get(variable.value.to_string()) else {
+            self.undefined(&variable.value);
+            return None;
+        };
+        if !bundle.is_ref {
+            self.error(
+                "E0402",
+                format!(
+                    "`{}` must be a reference to take its address",
+                    variable.value
+                ),
+            );
+            return None;
+        }
+        let address = match index {
+            Some(index) => {
+                let element_type = self.type_to_llvm_type(typ);
+                self.element_pointer(&IRValue::REF(variable.clone()), index, element_type, at)?
             }
+            None => bundle.llvm_value,
+        };
+        self.sym_table.add(
+            label.to_string(),
+            LLVMValueBundle {
+                llvm_value: address,
+                is_ref: false,
+            },
+        );
+        Some(address)
+    }
+
+    fn generate_deref(
+        &mut self,
+        label: &String,
+        pointer: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let pointer = self.ir_value_to_llvm_value(pointer, builder);
+            let value = LLVMBuildLoad2(builder, self.type_to_llvm_type(typ), pointer, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
+
+    fn generate_store_deref(
+        &mut self,
+        pointer: &IRValue,
+        path: &Vec<usize>,
+        value: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let value = self.ir_value_to_llvm_value(value, builder);
+            let pointer = self.ir_value_to_llvm_value(pointer, builder);
+            let typ = self.type_to_llvm_type(typ);
+            let (target, target_type) = self.field_pointer(pointer, typ, path, builder);
+            let value = self.convert(value, target_type, builder);
+            LLVMBuildStore(builder, value, target);
+        }
+        None
+    }
+
+    fn generate_offset(
+        &mut self,
+        label: &String,
+        pointer: &IRValue,
+        count: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let pointer = self.ir_value_to_llvm_value(pointer, builder);
+            let mut indices = vec![self.index_value(count, builder)];
+            let typ = self.type_to_llvm_type(typ);
+            let value = LLVMBuildGEP2(builder, typ, pointer, indices.as_mut_ptr(), 1, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
+
+    fn generate_unwrap(
+        &mut self,
+        label: &String,
+        value: &IRValue,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let value = self.ir_value_to_llvm_value(value, builder);
+            let null = LLVMConstNull(LLVMTypeOf(value));
+            let not_null =
+                LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, value, null, unnamed);
+            self.check(not_null, "attempt to unwrap a null pointer", at);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
+
+    // a pointer to the field at the end of the path in the struct the pointer points to, and
+    // its type
+    fn field_pointer(
+        &self,
+        pointer: LLVMValueRef,
+        struct_type: *mut LLVMType,
+        path: &Vec<usize>,
+        builder: *mut LLVMBuilder,
+    ) -> (LLVMValueRef, *mut LLVMType) {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            if path.is_empty() {
+                return (pointer, struct_type);
+            }
+            let mut field_type = struct_type;
+            let mut indices = vec![LLVMConstInt(LLVMInt32Type(), 0, 0)];
+            for index in path.iter() {
+                indices.push(LLVMConstInt(LLVMInt32Type(), *index as u64, 0));
+                field_type = LLVMStructGetTypeAtIndex(field_type, *index as u32);
+            }
+            let field = LLVMBuildGEP2(
+                builder,
+                struct_type,
+                pointer,
+                indices.as_mut_ptr(),
+                indices.len() as u32,
+                unnamed,
+            );
+            (field, field_type)
+        }
+    }
+
+    fn generate_cond_br(
+        &mut self,
+        condition: &IRValue,
+        body: &Box<Instruction>,
+        else_body: &Option<Box<Instruction>>,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let Some(cond_value) = self.condition_value(condition, builder) else {
+                return None;
+            };
 
             // Create a basic block in the function and set our builder to generate
             // code in it.
@@ -552,27 +1342,13 @@ impl LLVMCodeGenerator {
             LLVMBuildCondBr(builder, cond_value, then_block, else_block);
 
             LLVMPositionBuilderAtEnd(builder, then_block);
-            self.generate_instruction(
-                &body,
-                context,
-                module,
-                builder,
-                current_block,
-                current_function,
-            );
+            self.generate_instruction(&body, at);
             LLVMBuildBr(builder, done_block);
 
             // print positioning here!
             LLVMPositionBuilderAtEnd(builder, else_block);
             if let Some(else_body_instruction) = else_body {
-                self.generate_instruction(
-                    else_body_instruction,
-                    context,
-                    module,
-                    builder,
-                    current_block,
-                    current_function,
-                );
+                self.generate_instruction(else_body_instruction, at);
             }
             LLVMBuildBr(builder, done_block);
 
@@ -582,19 +1358,314 @@ impl LLVMCodeGenerator {
         None
     }
 
-    fn generate_store(
+    // like a cond_br, but the value each branch ends with meets in a phi
+    fn generate_if(
+        &mut self,
+        location: &String,
+        condition: &IRValue,
+        (body, body_value): (&Box<Instruction>, &IRValue),
+        (else_body, else_value): (&Box<Instruction>, &IRValue),
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let cond_value = self.condition_value(condition, builder)?;
+            let then_block = self.append_block(context, current_function, "then");
+            let else_block = self.append_block(context, current_function, "else");
+            let done_block = self.append_block(context, current_function, "done");
+            LLVMBuildCondBr(builder, cond_value, then_block, else_block);
+
+            LLVMPositionBuilderAtEnd(builder, then_block);
+            self.generate_instruction(body, at);
+            let then_value = self.ir_value_to_llvm_value(body_value, builder);
+            let typ = LLVMTypeOf(then_value);
+            // the branch may have added blocks, the phi needs the one that goes to done
+            let then_end = LLVMGetInsertBlock(builder);
+            LLVMBuildBr(builder, done_block);
+
+            LLVMPositionBuilderAtEnd(builder, else_block);
+            self.generate_instruction(else_body, at);
+            let else_value = self.ir_value_to_llvm_value(else_value, builder);
+            let else_value = self.convert(else_value, typ, builder);
+            let else_end = LLVMGetInsertBlock(builder);
+            LLVMBuildBr(builder, done_block);
+
+            LLVMPositionBuilderAtEnd(builder, done_block);
+            // calls to functions without a return type don't give anything to choose between
+            if LLVMGetTypeKind(typ) == LLVMTypeKind::LLVMVoidTypeKind {
+                return None;
+            }
+            let label = CString::new(location.to_string()).unwrap();
+            let phi = LLVMBuildPhi(builder, typ, label.as_ptr());
+            let mut values = [then_value, else_value];
+            let mut blocks = [then_end, else_end];
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+            self.sym_table.add(
+                location.to_string(),
+                LLVMValueBundle {
+                    llvm_value: phi,
+                    is_ref: false,
+                },
+            );
+            Some(phi)
+        }
+    }
+
+    // a loop is a block that works out the condition, which branches to the body or out
+    // of the loop. the body goes to the step, which goes back to the condition
+    fn generate_loop(
+        &mut self,
+        condition_block: &Box<Instruction>,
+        condition: &IRValue,
+        body: &Box<Instruction>,
+        step: &Option<Box<Instruction>>,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            let loop_block = self.append_block(context, current_function, "loop");
+            let body_block = self.append_block(context, current_function, "loop_body");
+            let step_block = self.append_block(context, current_function, "loop_step");
+            let done_block = self.append_block(context, current_function, "loop_done");
+
+            LLVMBuildBr(builder, loop_block);
+            LLVMPositionBuilderAtEnd(builder, loop_block);
+            self.generate_instruction(condition_block, at);
+            let cond_value = self.condition_value(condition, builder)?;
+            LLVMBuildCondBr(builder, cond_value, body_block, done_block);
+
+            LLVMPositionBuilderAtEnd(builder, body_block);
+            self.loops.push(LoopBlocks {
+                continue_block: step_block,
+                break_block: done_block,
+            });
+            self.generate_instruction(body, at);
+            self.loops.pop();
+            LLVMBuildBr(builder, step_block);
+
+            LLVMPositionBuilderAtEnd(builder, step_block);
+            if let Some(step) = step {
+                self.generate_instruction(step, at);
+            }
+            LLVMBuildBr(builder, loop_block);
+
+            LLVMPositionBuilderAtEnd(builder, done_block);
+        }
+        None
+    }
+
+    fn generate_ret(&mut self, value: &Option<IRValue>, at: InsertPoint) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        unsafe {
+            match value {
+                Some(value) => {
+                    let llvm_value = self.ir_value_to_llvm_value(value, builder);
+                    let return_type = LLVMGetReturnType(LLVMGlobalGetValueType(current_function));
+                    let llvm_value = self.convert(llvm_value, return_type, builder);
+                    LLVMBuildRet(builder, llvm_value);
+                }
+                None => {
+                    LLVMBuildRetVoid(builder);
+                }
+            }
+            // anything after the ret can't be reached, but it still needs a block to go in
+            let unreachable = self.append_block(context, current_function, "unreachable");
+            LLVMPositionBuilderAtEnd(builder, unreachable);
+        }
+        None
+    }
+
+    // convert a number to another number type, anything else is left as it is
+    fn convert(
+        &self,
+        value: LLVMValueRef,
+        typ: *mut LLVMType,
+        builder: *mut LLVMBuilder,
+    ) -> LLVMValueRef {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let from = LLVMTypeOf(value);
+            if from == typ {
+                return value;
+            }
+            let is_float = |typ| {
+                matches!(
+                    LLVMGetTypeKind(typ),
+                    LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind
+                )
+            };
+            let is_int = |typ| LLVMGetTypeKind(typ) == LLVMTypeKind::LLVMIntegerTypeKind;
+            if is_int(from) && is_int(typ) {
+                LLVMBuildIntCast2(builder, value, typ, 1, unnamed)
+            } else if is_int(from) && is_float(typ) {
+                LLVMBuildSIToFP(builder, value, typ, unnamed)
+            } else if is_float(from) && is_float(typ) {
+                LLVMBuildFPCast(builder, value, typ, unnamed)
+            } else if is_float(from) && is_int(typ) {
+                LLVMBuildFPToSI(builder, value, typ, unnamed)
+            } else {
+                value
+            }
+        }
+    }
+
+    // break and continue
+    fn generate_jump(
+        &mut self,
+        instruction: &Instruction,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            builder,
+            current_function,
+            ..
+        } = at;
+        let Some(blocks) = self.loops.last() else {
+            self.unsupported("jumping outside of a loop".to_string());
+            return None;
+        };
+        let target = match instruction {
+            Instruction::BREAK => blocks.break_block,
+            _ => blocks.continue_block,
+        };
+        unsafe {
+            LLVMBuildBr(builder, target);
+            // anything after the jump can't be reached, but it still needs a block to go in
+            let unreachable = self.append_block(context, current_function, "unreachable");
+            LLVMPositionBuilderAtEnd(builder, unreachable);
+        }
+        None
+    }
+
+    fn append_block(
         &mut self,
-        storee: &Ref,
-        value: &IRValue,
         context: *mut LLVMContext,
-        module: *mut LLVMModule,
+        function: *mut LLVMValue,
+        name: &str,
+    ) -> *mut LLVMBasicBlock {
+        let label = CString::new(format!("{}_{}", self.anon_local_block_counter, name)).unwrap();
+        self.anon_local_block_counter += 1;
+        unsafe { LLVMAppendBasicBlockInContext(context, function, label.as_ptr()) }
+    }
+
+    // branches need an i1 which is true when the value isn't zero
+    fn condition_value(
+        &mut self,
+        condition: &IRValue,
         builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
+    ) -> Option<LLVMValueRef> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let value = match condition {
+                IRValue::INT(_) | IRValue::REF(_) => {
+                    self.ir_value_to_llvm_value(condition, builder)
+                }
+                _ => {
+                    self.unsupported(format!("{:?} as a condition", condition));
+                    return None;
+                }
+            };
+            let typ = LLVMTypeOf(value);
+            match LLVMGetTypeKind(typ) {
+                LLVMTypeKind::LLVMIntegerTypeKind => Some(LLVMBuildICmp(
+                    builder,
+                    LLVMIntPredicate::LLVMIntNE,
+                    value,
+                    LLVMConstInt(typ, 0, 0),
+                    unnamed,
+                )),
+                LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind => {
+                    Some(LLVMBuildFCmp(
+                        builder,
+                        LLVMRealPredicate::LLVMRealUNE,
+                        value,
+                        LLVMConstReal(typ, 0.0),
+                        unnamed,
+                    ))
+                }
+                _ => {
+                    self.unsupported(format!("{:?} as a condition", condition));
+                    None
+                }
+            }
+        }
+    }
+
+    // allocas go at the start of the function, so variables declared in a loop don't grow
+    // the stack every iteration
+    fn entry_alloca(
+        &self,
         current_function: *mut LLVMValue,
+        typ: *mut LLVMType,
+        name: *const i8,
+    ) -> LLVMValueRef {
+        unsafe {
+            let builder = LLVMCreateBuilder();
+            let entry = LLVMGetEntryBasicBlock(current_function);
+            let first = LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(builder, first);
+            }
+            let alloca = LLVMBuildAlloca(builder, typ, name);
+            LLVMDisposeBuilder(builder);
+            alloca
+        }
+    }
+
+    // the variables of the program itself are globals, so the functions it declares can use
+    // them too. they start zeroed and are given their value where they are declared
+    fn variable_slot(&self, at: InsertPoint, typ: *mut LLVMType, name: *const i8) -> LLVMValueRef {
+        if self.sym_table.scope > 0 {
+            return self.entry_alloca(at.current_function, typ, name);
+        }
+        unsafe {
+            let global = LLVMAddGlobal(at.module, typ, name);
+            LLVMSetInitializer(global, LLVMConstNull(typ));
+            global
+        }
+    }
+
+    // the type of what a variable holds, whether it is a local or a global
+    fn variable_type(&self, variable: LLVMValueRef) -> *mut LLVMType {
+        unsafe {
+            match LLVMIsAGlobalVariable(variable).is_null() {
+                true => LLVMGetAllocatedType(variable),
+                false => LLVMGlobalGetValueType(variable),
+            }
+        }
+    }
+
+    fn generate_store(
+        &mut self,
+        storee: &Ref,
+        value: &IRValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
         unsafe {
             let llvm_value = self.ir_value_to_llvm_value(value, builder);
-            let storee_ptr = self.sym_table.get(storee.value.to_string()).unwrap();
+            let Some(storee_ptr) = self.sym_table.get(storee.value.to_string()) else {
+                self.undefined(&storee.value);
+                return None;
+            };
             // todo make a generic way to get an llvm value from an IRValue
             LLVMBuildStore(builder, llvm_value, storee_ptr.llvm_value.clone());
         }
@@ -604,9 +1675,38 @@ impl LLVMCodeGenerator {
     fn type_to_llvm_type(&self, typ: &Type) -> *mut LLVMType {
         unsafe {
             match typ {
-                Type::I32 => LLVMInt32Type(),
-                Type::U32 => LLVMInt32Type(),
-                _ => todo!(),
+                Type::I8 | Type::U8 => LLVMInt8Type(),
+                Type::I16 | Type::U16 => LLVMInt16Type(),
+                Type::I32 | Type::U32 => LLVMInt32Type(),
+                Type::I64 | Type::U64 => LLVMInt64Type(),
+                Type::BOOL => LLVMInt1Type(),
+                Type::CHAR => LLVMInt32Type(),
+                Type::F32 => LLVMFloatType(),
+                Type::F64 => LLVMDoubleType(),
+                Type::FN(_) => self.closure_type(),
+                Type::STRING => LLVMPointerType(LLVMInt8Type(), 0),
+                Type::ARRAY(element, len) => {
+                    LLVMArrayType2(self.type_to_llvm_type(element), *len as u64)
+                }
+                Type::SLICE(_) => self.slice_type(),
+                // llvm pointers don't have a pointee type, loads and stores say what they use
+                Type::POINTER(pointee) | Type::REFERENCE(pointee) => {
+                    LLVMPointerType(self.type_to_llvm_type(pointee), 0)
+                }
+                Type::OPTIONAL(inner) => self.type_to_llvm_type(inner),
+                Type::STRUCT(name) | Type::ENUM(name) => {
+                    let c_name = CString::new(name.as_bytes()).expect("expected string");
+                    let struct_type = LLVMGetTypeByName2(LLVMGetGlobalContext(), c_name.as_ptr());
+                    if struct_type.is_null() {
+                        self.undefined(name);
+                        return LLVMInt32Type();
+                    }
+                    struct_type
+                }
+                _ => {
+                    self.unsupported(format!("values of type {:?}", typ));
+                    LLVMInt32Type()
+                }
             }
         }
     }
@@ -614,56 +1714,303 @@ impl LLVMCodeGenerator {
     fn generate_func(
         &mut self,
         name: &String,
-        params: &Vec<Type>,
+        captures: &Option<Vec<String>>,
+        params: &Vec<(String, Type)>,
+        return_type: &Option<Type>,
         instruction: &Instruction,
-        context: *mut LLVMContext,
+        at: InsertPoint,
+    ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context,
+            module,
+            builder,
+            ..
+        } = at;
+        unsafe {
+            let function = self.declare_func(name, captures, params, return_type, module);
+            let function_type = LLVMGlobalGetValueType(function);
+            let mut param_types: Vec<*mut LLVMType> =
+                vec![std::ptr::null_mut(); LLVMCountParamTypes(function_type) as usize];
+            LLVMGetParamTypes(function_type, param_types.as_mut_ptr());
+            // the environment is worked out from the captured variables where the closure is
+            let env_type = match captures {
+                Some(captures) => Some(self.env_type(captures)?),
+                None => None,
+            };
+            let bb =
+                llvm_sys::core::LLVMAppendBasicBlockInContext(context, function, c"entry".as_ptr());
+
+            // carry on from the block the builder was at once the function is done
+            let outer_block = LLVMGetInsertBlock(builder);
+            LLVMPositionBuilderAtEnd(builder, bb);
+
+            // params are stored in locals so they can be assigned to like any other variable
+            self.sym_table.new_scope();
+            // closures get their environment first, which the captured variables are copied out of
+            let mut first_param = 0;
+            if let (Some(captures), Some(env_type)) = (captures, env_type) {
+                first_param = 1;
+                let env = LLVMGetParam(function, 0);
+                for (i, capture) in captures.iter().enumerate() {
+                    let capture_name = CString::new(capture.to_string()).unwrap();
+                    let field_type = LLVMStructGetTypeAtIndex(env_type, i as u32);
+                    let field = LLVMBuildStructGEP2(
+                        builder,
+                        env_type,
+                        env,
+                        i as u32,
+                        capture_name.as_ptr(),
+                    );
+                    let value = LLVMBuildLoad2(builder, field_type, field, capture_name.as_ptr());
+                    let alloca = self.entry_alloca(function, field_type, capture_name.as_ptr());
+                    LLVMBuildStore(builder, value, alloca);
+                    self.sym_table.add(
+                        capture.to_string(),
+                        LLVMValueBundle {
+                            llvm_value: alloca,
+                            is_ref: true,
+                        },
+                    );
+                }
+            }
+            for (i, (param, _)) in params.iter().enumerate() {
+                let i = i + first_param;
+                let param_name = CString::new(param.to_string()).unwrap();
+                let alloca = self.entry_alloca(function, param_types[i], param_name.as_ptr());
+                LLVMBuildStore(builder, LLVMGetParam(function, i as u32), alloca);
+                self.sym_table.add(
+                    param.to_string(),
+                    LLVMValueBundle {
+                        llvm_value: alloca,
+                        is_ref: true,
+                    },
+                );
+            }
+
+            self.generate_instruction(
+                instruction,
+                InsertPoint {
+                    current_function: function,
+                    ..at
+                },
+            );
+
+            // the body didn't end with a ret
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(builder)).is_null() {
+                match return_type {
+                    None => LLVMBuildRetVoid(builder),
+                    // every path ended in a ret, so this block is never reached
+                    Some(_) => LLVMBuildUnreachable(builder),
+                };
+            }
+            self.sym_table.leave_scope();
+
+            LLVMPositionBuilderAtEnd(builder, outer_block);
+        }
+        None
+    }
+
+    // add the function to the module, unless it already has been
+    fn declare_func(
+        &mut self,
+        name: &String,
+        captures: &Option<Vec<String>>,
+        params: &Vec<(String, Type)>,
+        return_type: &Option<Type>,
         module: *mut LLVMModule,
+    ) -> LLVMValueRef {
+        unsafe {
+            let fn_name = CString::new(name.to_string()).unwrap();
+            let ptr_fn_name = fn_name.as_ptr();
+            let existing = LLVMGetNamedFunction(module, ptr_fn_name);
+            if !existing.is_null() {
+                return existing;
+            }
+
+            let llvm_return_type = match return_type {
+                Some(typ) => self.type_to_llvm_type(typ),
+                None => LLVMVoidType(),
+            };
+
+            let mut param_types: Vec<*mut LLVMType> = vec![];
+            if captures.is_some() {
+                param_types.push(LLVMPointerType(LLVMInt8Type(), 0));
+            }
+            for (_, param) in params {
+                param_types.push(self.type_to_llvm_type(param));
+            }
+
+            let function_type = llvm_sys::core::LLVMFunctionType(
+                llvm_return_type,
+                param_types.as_mut_ptr(),
+                param_types.len().try_into().unwrap(),
+                0,
+            );
+
+            let function = llvm_sys::core::LLVMAddFunction(module, ptr_fn_name, function_type);
+            self.sym_table.add(
+                name.to_string(),
+                LLVMValueBundle {
+                    llvm_value: function,
+                    is_ref: false,
+                },
+            );
+            function
+        }
+    }
+
+    // the struct the captured variables of a closure are kept in
+    fn env_type(&mut self, captures: &Vec<String>) -> Option<*mut LLVMType> {
+        let mut types: Vec<*mut LLVMType> = vec![];
+        for capture in captures.iter() {
+            let Some(bundle) = self.sym_table.get(capture.to_string()) else {
+                self.undefined(capture);
+                return None;
+            };
+            unsafe {
+                types.push(match bundle.is_ref {
+                    true => self.variable_type(bundle.llvm_value),
+                    false => LLVMTypeOf(bundle.llvm_value),
+                });
+            }
+        }
+        unsafe { Some(LLVMStructType(types.as_mut_ptr(), types.len() as u32, 0)) }
+    }
+
+    // a function value is the function and a pointer to what it captured
+    fn closure_type(&self) -> *mut LLVMType {
+        unsafe {
+            let ptr = LLVMPointerType(LLVMInt8Type(), 0);
+            let mut fields = [ptr, ptr];
+            LLVMStructType(fields.as_mut_ptr(), 2, 0)
+        }
+    }
+
+    // the captured values are copied to the heap, so the closure can outlive them
+    fn generate_closure(
+        &mut self,
+        label: &String,
+        function: &String,
+        values: &Vec<IRValue>,
+        module: *mut LLVMModule,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let function_name = CString::new(function.to_string()).unwrap();
+            let function_value = LLVMGetNamedFunction(module, function_name.as_ptr());
+            if function_value.is_null() {
+                return Some(self.undefined(function));
+            }
+            let ptr = LLVMPointerType(LLVMInt8Type(), 0);
+            let env = if values.is_empty() {
+                LLVMConstPointerNull(ptr)
+            } else {
+                let captures: Vec<String> = values
+                    .iter()
+                    .filter_map(|value| match value {
+                        IRValue::REF(r) => Some(r.value.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                let env_type = self.env_type(&captures)?;
+                let malloc = self.sym_table.get("malloc".to_string())?.llvm_value;
+                let mut malloc_args = vec![LLVMSizeOf(env_type)];
+                let env = LLVMBuildCall2(
+                    builder,
+                    LLVMGlobalGetValueType(malloc),
+                    malloc,
+                    malloc_args.as_mut_ptr(),
+                    1,
+                    unnamed,
+                );
+                for (i, value) in values.iter().enumerate() {
+                    let value = self.ir_value_to_llvm_value(value, builder);
+                    let field = LLVMBuildStructGEP2(builder, env_type, env, i as u32, unnamed);
+                    LLVMBuildStore(builder, value, field);
+                }
+                env
+            };
+            let closure = LLVMGetUndef(self.closure_type());
+            let closure = LLVMBuildInsertValue(builder, closure, function_value, 0, unnamed);
+            let closure = LLVMBuildInsertValue(builder, closure, env, 1, unnamed);
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: closure,
+                    is_ref: false,
+                },
+            );
+            Some(closure)
+        }
+    }
+
+    // call the function in a closure, passing it the environment before the args
+    fn generate_call_value(
+        &mut self,
+        label: &String,
+        callee: &IRValue,
+        typ: &Type,
+        args: &Vec<IRValue>,
         builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
     ) -> Option<*mut LLVMValue> {
+        let Type::FN(fn_type) = typ else {
+            self.unsupported(format!("calling a value of type {}", typ));
+            return None;
+        };
         unsafe {
-            let void = LLVMVoidType();
-
-            let mut param_types: Vec<*mut LLVMType> = vec![];
-            for param in params {
-                param_types.push(self.type_to_llvm_type(param));
+            let unnamed = c"".as_ptr();
+            let closure = self.ir_value_to_llvm_value(callee, builder);
+            let function = LLVMBuildExtractValue(builder, closure, 0, unnamed);
+            let env = LLVMBuildExtractValue(builder, closure, 1, unnamed);
+
+            let mut param_types = vec![LLVMPointerType(LLVMInt8Type(), 0)];
+            for arg in fn_type.args.iter() {
+                param_types.push(self.type_to_llvm_type(arg));
             }
-
-            let function_type = llvm_sys::core::LLVMFunctionType(
-                void,
+            let return_type = match &fn_type.return_type {
+                Some(typ) => self.type_to_llvm_type(typ),
+                None => LLVMVoidType(),
+            };
+            let function_type = LLVMFunctionType(
+                return_type,
                 param_types.as_mut_ptr(),
-                param_types.len().try_into().unwrap(),
+                param_types.len() as u32,
                 0,
             );
+            if args.len() + 1 != param_types.len() {
+                self.unsupported(format!(
+                    "calling {:?} with {} argument(s), it takes {}",
+                    callee,
+                    args.len(),
+                    param_types.len() - 1
+                ));
+                return None;
+            }
 
-            let fn_name = CString::new(name.to_string()).unwrap();
-            let ptr_fn_name = fn_name.as_ptr();
-
-            let function = llvm_sys::core::LLVMAddFunction(module, ptr_fn_name, function_type);
-            let bb = llvm_sys::core::LLVMAppendBasicBlockInContext(
-                context,
+            let mut args_vec = vec![env];
+            for (arg, typ) in args.iter().zip(param_types.iter().skip(1)) {
+                let value = self.ir_value_to_llvm_value(arg, builder);
+                args_vec.push(self.convert(value, *typ, builder));
+            }
+            let result = LLVMBuildCall2(
+                builder,
+                function_type,
                 function,
-                b"entry\0".as_ptr() as *const _,
+                args_vec.as_mut_ptr(),
+                args_vec.len() as u32,
+                // void calls can't be named
+                c"".as_ptr(),
             );
-
             self.sym_table.add(
-                name.to_string(),
+                label.to_string(),
                 LLVMValueBundle {
-                    llvm_value: function,
+                    llvm_value: result,
                     is_ref: false,
                 },
             );
-
-            LLVMPositionBuilderAtEnd(builder, bb);
-
-            self.generate_instruction(instruction, context, module, builder, bb, function);
-
-            LLVMBuildRetVoid(builder);
-
-            LLVMPositionBuilderAtEnd(builder, current_block);
+            Some(result)
         }
-        None
     }
 
     fn generate_call(
@@ -671,39 +2018,53 @@ impl LLVMCodeGenerator {
         label: &String,
         callee: &String,
         args: &Vec<IRValue>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint { builder, .. } = at;
+        if callee == "format" {
+            return self.generate_format(label, args, builder);
+        }
         unsafe {
-            let func_value = self
-                .sym_table
-                .get(callee.to_owned())
-                .expect("expected printf")
-                .llvm_value;
+            let Some(func_bundle) = self.sym_table.get(callee.to_owned()) else {
+                self.undefined(callee);
+                return None;
+            };
+            let func_value = func_bundle.llvm_value;
 
-            let function_type = llvm_sys::core::LLVMFunctionType(
-                LLVMInt32Type(),
-                // &mut LLVMPointerType(LLVMInt8Type(), 0),
-                &mut LLVMVoidType(),
-                1,
-                0,
-            );
+            let function_type = LLVMGlobalGetValueType(func_value);
 
-            let printf_var = CString::new("call_result").expect("i am a c string");
+            // void calls can't be named
+            let printf_var = CString::new("").expect("i am a c string");
             let printf_var_ptr = printf_var.as_ptr();
 
             // todo the callee shouldn't always be a string
-            let func = self.sym_table.get(callee.to_string()).unwrap().llvm_value;
+            let func = func_value;
+
+            let param_count = LLVMCountParamTypes(function_type) as usize;
+            let variadic = LLVMIsFunctionVarArg(function_type) != 0;
+            if args.len() < param_count || (args.len() > param_count && !variadic) {
+                self.unsupported(format!(
+                    "calling `{}` with {} argument(s), it takes {}",
+                    callee,
+                    args.len(),
+                    param_count
+                ));
+                return None;
+            }
+            let mut param_types: Vec<*mut LLVMType> = vec![std::ptr::null_mut(); param_count];
+            LLVMGetParamTypes(function_type, param_types.as_mut_ptr());
 
             let mut args_vec: Vec<*mut LLVMValue> = vec![];
-            for arg in args {
-                // todo i need to get the value of that string!
-                args_vec.push(self.ir_value_to_llvm_value(arg, builder));
+            for (i, arg) in args.iter().enumerate() {
+                let value = self.ir_value_to_llvm_value(arg, builder);
+                // the args to a variadic function are passed as they are
+                let value = match param_types.get(i) {
+                    Some(typ) => self.convert(value, *typ, builder),
+                    None => value,
+                };
+                args_vec.push(value);
             }
-            LLVMBuildCall2(
+            let result = LLVMBuildCall2(
                 builder,
                 function_type,
                 func,
@@ -712,6 +2073,13 @@ impl LLVMCodeGenerator {
                 args_vec.len() as u32,
                 printf_var_ptr,
             );
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: result,
+                    is_ref: false,
+                },
+            );
 
             // match arg {
             //     IRValue::INT(i) => {
@@ -765,62 +2133,154 @@ impl LLVMCodeGenerator {
         None
     }
 
+    // format(...) builds a new string from its args with snprintf. literal strings go
+    // straight into the format string and the other args are formatted by their llvm type
+    fn generate_format(
+        &mut self,
+        label: &String,
+        args: &Vec<IRValue>,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let mut format_string = String::from("");
+            let mut values: Vec<LLVMValueRef> = vec![];
+            for arg in args.iter() {
+                match arg {
+                    IRValue::STRING(s) => {
+                        format_string += &s.replace('%', "%%");
+                        continue;
+                    }
+                    IRValue::CHAR(c) => {
+                        format_string += &c.to_string().replace('%', "%%");
+                        continue;
+                    }
+                    _ => {}
+                }
+                let value = self.ir_value_to_llvm_value(arg, builder);
+                let typ = LLVMTypeOf(value);
+                match LLVMGetTypeKind(typ) {
+                    LLVMTypeKind::LLVMIntegerTypeKind if LLVMGetIntTypeWidth(typ) > 32 => {
+                        format_string += "%lld";
+                        values.push(value);
+                    }
+                    LLVMTypeKind::LLVMIntegerTypeKind => {
+                        // varargs smaller than an int are passed as an int
+                        format_string += "%d";
+                        values.push(LLVMBuildIntCast2(
+                            builder,
+                            value,
+                            LLVMInt32Type(),
+                            1,
+                            unnamed,
+                        ));
+                    }
+                    LLVMTypeKind::LLVMFloatTypeKind => {
+                        format_string += "%f";
+                        values.push(LLVMBuildFPExt(builder, value, LLVMDoubleType(), unnamed));
+                    }
+                    LLVMTypeKind::LLVMDoubleTypeKind => {
+                        format_string += "%f";
+                        values.push(value);
+                    }
+                    LLVMTypeKind::LLVMPointerTypeKind => {
+                        format_string += "%s";
+                        values.push(value);
+                    }
+                    _ => {
+                        self.unsupported(format!("formatting {:?}", arg));
+                        return None;
+                    }
+                }
+            }
+
+            let snprintf = self.sym_table.get("snprintf".to_string())?.llvm_value;
+            let malloc = self.sym_table.get("malloc".to_string())?.llvm_value;
+
+            let c_format = self.c_string(&format_string)?;
+            let c_format_label = CString::new(format!("{}_anon_string", self.anon_string_counter))
+                .expect("i am a c string");
+            self.anon_string_counter += 1;
+            let format_value =
+                LLVMBuildGlobalStringPtr(builder, c_format.as_ptr(), c_format_label.as_ptr());
+
+            // snprintf with no buffer tells us how long the string will be
+            let mut length_args = vec![
+                LLVMConstPointerNull(LLVMPointerType(LLVMInt8Type(), 0)),
+                LLVMConstInt(LLVMInt64Type(), 0, 0),
+                format_value,
+            ];
+            length_args.extend(values.iter());
+            let length = LLVMBuildCall2(
+                builder,
+                LLVMGlobalGetValueType(snprintf),
+                snprintf,
+                length_args.as_mut_ptr(),
+                length_args.len() as u32,
+                unnamed,
+            );
+            let length = LLVMBuildSExt(builder, length, LLVMInt64Type(), unnamed);
+            let size = LLVMBuildAdd(
+                builder,
+                length,
+                LLVMConstInt(LLVMInt64Type(), 1, 0),
+                unnamed,
+            );
+
+            let mut malloc_args = vec![size];
+            let buffer = LLVMBuildCall2(
+                builder,
+                LLVMGlobalGetValueType(malloc),
+                malloc,
+                malloc_args.as_mut_ptr(),
+                1,
+                unnamed,
+            );
+
+            let mut format_args = vec![buffer, size, format_value];
+            format_args.extend(values.iter());
+            LLVMBuildCall2(
+                builder,
+                LLVMGlobalGetValueType(snprintf),
+                snprintf,
+                format_args.as_mut_ptr(),
+                format_args.len() as u32,
+                unnamed,
+            );
+
+            self.sym_table.add(
+                label.to_string(),
+                LLVMValueBundle {
+                    llvm_value: buffer,
+                    is_ref: false,
+                },
+            );
+            Some(buffer)
+        }
+    }
+
     fn generate_stack_var(
         &mut self,
         label: &String,
         typ: &Type,
         value: &Option<IRValue>,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
-        builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
+        at: InsertPoint,
     ) -> Option<*mut LLVMValue> {
+        let InsertPoint {
+            context, builder, ..
+        } = at;
         unsafe {
             let c_str = CString::new(label.as_str()).unwrap();
             let ptr = c_str.as_ptr();
             if let Some(val) = value {
                 match val {
-                    IRValue::REF(r) => {
-                        let alloca_instruction = llvm_sys::core::LLVMBuildAlloca(
-                            builder,
-                            llvm_sys::core::LLVMInt32Type(),
-                            ptr,
-                        );
-                        let initializer_value = self
-                            .sym_table
-                            .get(r.value.to_string())
-                            .unwrap()
-                            .llvm_value
-                            .clone();
-                        let store_instruction = llvm_sys::core::LLVMBuildStore(
-                            builder,
-                            initializer_value,
-                            alloca_instruction,
-                        );
-                        self.sym_table.add(
-                            label.to_string(),
-                            LLVMValueBundle {
-                                llvm_value: alloca_instruction,
-                                is_ref: true,
-                            },
-                        );
-                    }
-                    IRValue::INT(i) => {
-                        let alloca_instruction = llvm_sys::core::LLVMBuildAlloca(
-                            builder,
-                            llvm_sys::core::LLVMInt32Type(),
-                            ptr,
-                        );
-                        let store_instruction = llvm_sys::core::LLVMBuildStore(
-                            builder,
-                            LLVMConstInt(
-                                llvm_sys::core::LLVMInt32Type(),
-                                (*i).try_into().unwrap(),
-                                1,
-                            ),
-                            alloca_instruction,
-                        );
+                    IRValue::REF(_) | IRValue::INT(_) | IRValue::FLOAT(_) | IRValue::CHAR(_) => {
+                        // variables take the type of the value they are initialised with, a
+                        // literal is as wide as the type of the variable
+                        let initializer_value = self.typed_value(val, typ, builder);
+                        let alloca_instruction =
+                            self.variable_slot(at, LLVMTypeOf(initializer_value), ptr);
+                        LLVMBuildStore(builder, initializer_value, alloca_instruction);
                         self.sym_table.add(
                             label.to_string(),
                             LLVMValueBundle {
@@ -831,37 +2291,48 @@ impl LLVMCodeGenerator {
                     }
                     IRValue::STRING(s) => {
                         // first allocate space for the global string
-                        let c_str = CString::new(s.to_string()).expect("i am a c string");
-                        let ptr = c_str.as_ptr();
+                        let Some(contents) = self.c_string(s) else {
+                            return None;
+                        };
                         let c_str_label =
                             CString::new(format!("{}_anon_string", self.anon_string_counter))
                                 .expect("i am a c string");
                         self.anon_string_counter += 1;
                         let ptr_label = c_str_label.as_ptr();
-                        let mut llvm_string_value = LLVMBuildGlobalString(builder, ptr, ptr_label);
-
-                        // then we need to load the string
-                        let label_str = CString::new(label.to_string()).unwrap();
-                        let label_str_ptr = label_str.as_ptr();
-                        // allocate space for a pointer
-                        let tmp_name = CString::new(format!("{}.0", label)).unwrap();
-                        let tmp_ptr = tmp_name.as_ptr();
+                        let llvm_string_value =
+                            LLVMBuildGlobalString(builder, contents.as_ptr(), ptr_label);
+
+                        // then keep the pointer to it in the variable
                         let alloca_instruction =
-                            LLVMBuildAlloca(builder, LLVMPointerType(LLVMInt8Type(), 0), tmp_ptr);
-                        // then store the pointer to the str in that pointer
+                            self.variable_slot(at, LLVMPointerType(LLVMInt8Type(), 0), ptr);
                         LLVMBuildStore(builder, llvm_string_value, alloca_instruction);
-                        // then actually load the pointer value onto the stack
-                        let mut load = LLVMBuildLoad2(
-                            builder,
-                            LLVMPointerType(LLVMInt8Type(), 0),
-                            alloca_instruction,
-                            label_str_ptr,
+                        self.sym_table.add(
+                            label.to_string(),
+                            LLVMValueBundle {
+                                llvm_value: alloca_instruction,
+                                is_ref: true,
+                            },
                         );
-
+                    }
+                    IRValue::NULL => {
+                        // an optional pointer that holds nothing is the null pointer
+                        let typ = self.type_to_llvm_type(typ);
+                        let alloca_instruction = self.variable_slot(at, typ, ptr);
+                        LLVMBuildStore(builder, LLVMConstPointerNull(typ), alloca_instruction);
+                        self.sym_table.add(
+                            label.to_string(),
+                            LLVMValueBundle {
+                                llvm_value: alloca_instruction,
+                                is_ref: true,
+                            },
+                        );
+                    }
+                    IRValue::UNIT => {
+                        // there is nothing to store, so the label just names the value
                         self.sym_table.add(
                             label.to_string(),
                             LLVMValueBundle {
-                                llvm_value: load,
+                                llvm_value: LLVMConstStruct(std::ptr::null_mut(), 0, 0),
                                 is_ref: false,
                             },
                         );
@@ -874,8 +2345,7 @@ impl LLVMCodeGenerator {
                         let label_var_ptr = label_var.as_ptr();
                         let runtime_type = LLVMGetTypeByName2(context, label_var_ptr);
 
-                        let alloca_instruction =
-                            llvm_sys::core::LLVMBuildAlloca(builder, runtime_type, ptr);
+                        let alloca_instruction = self.variable_slot(at, runtime_type, ptr);
 
                         let s = LLVMConstStruct(
                             vec![LLVMConstInt(LLVMInt32Type(), 23, 1)].as_mut_ptr(),
@@ -884,44 +2354,491 @@ impl LLVMCodeGenerator {
                         );
                         LLVMBuildStore(builder, s, alloca_instruction);
                     }
-                    _ => todo!(),
+                    _ => {
+                        self.unsupported(format!("variables holding {:?}", val));
+                    }
                 }
+            } else {
+                // declared without a value, so it starts zeroed
+                let typ = self.type_to_llvm_type(typ);
+                let alloca_instruction = self.variable_slot(at, typ, ptr);
+                LLVMBuildStore(builder, LLVMConstNull(typ), alloca_instruction);
+                self.sym_table.add(
+                    label.to_string(),
+                    LLVMValueBundle {
+                        llvm_value: alloca_instruction,
+                        is_ref: true,
+                    },
+                );
             }
             None
         }
     }
 
-    fn generate_add(
+    // ints and floats use different instructions, so they are picked by the llvm type
+    // of the operands
+    fn generate_arithmetic(
         &mut self,
+        instruction: &Instruction,
         location: &String,
         first: &IRValue,
         second: &IRValue,
-        context: *mut LLVMContext,
-        module: *mut LLVMModule,
+        typ: &Type,
         builder: *mut LLVMBuilder,
-        current_block: *mut LLVMBasicBlock,
-        current_function: *mut LLVMValue,
     ) -> Option<*mut LLVMValue> {
         unsafe {
-            let left = self.ir_value_to_llvm_value(first, builder);
-            let right = self.ir_value_to_llvm_value(second, builder);
+            let unnamed = c"".as_ptr();
+            let (left, right, float) = self.numeric_operands(first, second, typ, builder)?;
+            let value = match (instruction, float) {
+                (Instruction::ADD(..), false) => LLVMBuildAdd(builder, left, right, unnamed),
+                (Instruction::ADD(..), true) => LLVMBuildFAdd(builder, left, right, unnamed),
+                (Instruction::SUB(..), false) => LLVMBuildSub(builder, left, right, unnamed),
+                (Instruction::SUB(..), true) => LLVMBuildFSub(builder, left, right, unnamed),
+                (Instruction::MUL(..), false) => LLVMBuildMul(builder, left, right, unnamed),
+                (Instruction::MUL(..), true) => LLVMBuildFMul(builder, left, right, unnamed),
+                (Instruction::DIV(..), false) if typ.is_unsigned() => {
+                    LLVMBuildUDiv(builder, left, right, unnamed)
+                }
+                (Instruction::DIV(..), false) => LLVMBuildSDiv(builder, left, right, unnamed),
+                (Instruction::DIV(..), true) => LLVMBuildFDiv(builder, left, right, unnamed),
+                (_, false) if typ.is_unsigned() => LLVMBuildURem(builder, left, right, unnamed),
+                (_, false) => LLVMBuildSRem(builder, left, right, unnamed),
+                (_, true) => LLVMBuildFRem(builder, left, right, unnamed),
+            };
+            self.sym_table.add(
+                location.to_string(),
+                LLVMValueBundle {
+                    llvm_value: value,
+                    is_ref: false,
+                },
+            );
+            Some(value)
+        }
+    }
 
-            let add_instr = llvm_sys::core::LLVMBuildAdd(
-                builder,
-                left,
-                right,
-                location.to_owned().as_bytes().as_ptr() as *const i8,
+    fn generate_cmp(
+        &mut self,
+        location: &String,
+        comparison: &Comparison,
+        first: &IRValue,
+        second: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let (left, right, float) = if *typ == Type::STRING {
+                // strcmp orders the strings, so its result is compared with 0 instead
+                let strcmp = self.sym_table.get("strcmp".to_string())?.llvm_value;
+                let mut args = vec![
+                    self.ir_value_to_llvm_value(first, builder),
+                    self.ir_value_to_llvm_value(second, builder),
+                ];
+                let order = LLVMBuildCall2(
+                    builder,
+                    LLVMGlobalGetValueType(strcmp),
+                    strcmp,
+                    args.as_mut_ptr(),
+                    2,
+                    unnamed,
+                );
+                (order, LLVMConstInt(LLVMInt32Type(), 0, 1), false)
+            } else {
+                self.numeric_operands(first, second, typ, builder)?
+            };
+            let holds = if float {
+                let predicate = match comparison {
+                    Comparison::EQ => LLVMRealPredicate::LLVMRealOEQ,
+                    // unordered so nan != nan
+                    Comparison::NE => LLVMRealPredicate::LLVMRealUNE,
+                    Comparison::LT => LLVMRealPredicate::LLVMRealOLT,
+                    Comparison::LE => LLVMRealPredicate::LLVMRealOLE,
+                    Comparison::GT => LLVMRealPredicate::LLVMRealOGT,
+                    Comparison::GE => LLVMRealPredicate::LLVMRealOGE,
+                };
+                LLVMBuildFCmp(builder, predicate, left, right, unnamed)
+            } else {
+                let predicate = match (comparison, typ.is_unsigned()) {
+                    (Comparison::EQ, _) => LLVMIntPredicate::LLVMIntEQ,
+                    (Comparison::NE, _) => LLVMIntPredicate::LLVMIntNE,
+                    (Comparison::LT, false) => LLVMIntPredicate::LLVMIntSLT,
+                    (Comparison::LE, false) => LLVMIntPredicate::LLVMIntSLE,
+                    (Comparison::GT, false) => LLVMIntPredicate::LLVMIntSGT,
+                    (Comparison::GE, false) => LLVMIntPredicate::LLVMIntSGE,
+                    (Comparison::LT, true) => LLVMIntPredicate::LLVMIntULT,
+                    (Comparison::LE, true) => LLVMIntPredicate::LLVMIntULE,
+                    (Comparison::GT, true) => LLVMIntPredicate::LLVMIntUGT,
+                    (Comparison::GE, true) => LLVMIntPredicate::LLVMIntUGE,
+                };
+                LLVMBuildICmp(builder, predicate, left, right, unnamed)
+            };
+            self.add_truth_value(location, holds, builder)
+        }
+    }
+
+    fn generate_neg(
+        &mut self,
+        location: &String,
+        value: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let operand = self.typed_value(value, typ, builder);
+            let negated = match LLVMGetTypeKind(LLVMTypeOf(operand)) {
+                LLVMTypeKind::LLVMIntegerTypeKind => LLVMBuildNeg(builder, operand, unnamed),
+                LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind => {
+                    LLVMBuildFNeg(builder, operand, unnamed)
+                }
+                _ => {
+                    self.unsupported(format!("negating {:?}", value));
+                    return None;
+                }
+            };
+            self.sym_table.add(
+                location.to_string(),
+                LLVMValueBundle {
+                    llvm_value: negated,
+                    is_ref: false,
+                },
             );
+            Some(negated)
+        }
+    }
+
+    fn generate_not(
+        &mut self,
+        location: &String,
+        value: &IRValue,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let operand = self.ir_value_to_llvm_value(value, builder);
+            let typ = LLVMTypeOf(operand);
+            let is_zero = match LLVMGetTypeKind(typ) {
+                LLVMTypeKind::LLVMIntegerTypeKind => LLVMBuildICmp(
+                    builder,
+                    LLVMIntPredicate::LLVMIntEQ,
+                    operand,
+                    LLVMConstInt(typ, 0, 0),
+                    unnamed,
+                ),
+                LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind => {
+                    LLVMBuildFCmp(
+                        builder,
+                        LLVMRealPredicate::LLVMRealOEQ,
+                        operand,
+                        LLVMConstReal(typ, 0.0),
+                        unnamed,
+                    )
+                }
+                _ => {
+                    self.unsupported(format!("`!` on {:?}", value));
+                    return None;
+                }
+            };
+            self.add_truth_value(location, is_zero, builder)
+        }
+    }
 
+    // comparisons give an i1 but the rest of the generated code treats truth values as an
+    // i32 (the same as `true` and `false`)
+    fn add_truth_value(
+        &mut self,
+        location: &String,
+        value: LLVMValueRef,
+        builder: *mut LLVMBuilder,
+    ) -> Option<*mut LLVMValue> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let extended = LLVMBuildZExt(builder, value, LLVMInt32Type(), unnamed);
             self.sym_table.add(
                 location.to_string(),
                 LLVMValueBundle {
-                    llvm_value: add_instr,
+                    llvm_value: extended,
                     is_ref: false,
                 },
             );
+            Some(extended)
+        }
+    }
 
-            None
+    // the llvm values of two operands, ints are converted to floats if the other operand
+    // is a float. the bool is whether they are floats
+    fn numeric_operands(
+        &mut self,
+        first: &IRValue,
+        second: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> Option<(LLVMValueRef, LLVMValueRef, bool)> {
+        unsafe {
+            let unnamed = c"".as_ptr();
+            let mut left = self.typed_value(first, typ, builder);
+            let mut right = self.typed_value(second, typ, builder);
+            let left_type = LLVMTypeOf(left);
+            let right_type = LLVMTypeOf(right);
+            let is_float = |typ| {
+                matches!(
+                    LLVMGetTypeKind(typ),
+                    LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind
+                )
+            };
+            let is_int = |typ| LLVMGetTypeKind(typ) == LLVMTypeKind::LLVMIntegerTypeKind;
+            let is_pointer = |typ| LLVMGetTypeKind(typ) == LLVMTypeKind::LLVMPointerTypeKind;
+            match (is_float(left_type), is_float(right_type)) {
+                (true, true) => {
+                    // f32 and f64 meet at f64
+                    if left_type != right_type {
+                        left = LLVMBuildFPCast(builder, left, LLVMDoubleType(), unnamed);
+                        right = LLVMBuildFPCast(builder, right, LLVMDoubleType(), unnamed);
+                    }
+                    Some((left, right, true))
+                }
+                (true, false) if is_int(right_type) => {
+                    right = LLVMBuildSIToFP(builder, right, left_type, unnamed);
+                    Some((left, right, true))
+                }
+                (false, true) if is_int(left_type) => {
+                    left = LLVMBuildSIToFP(builder, left, right_type, unnamed);
+                    Some((left, right, true))
+                }
+                (false, false) if is_int(left_type) && is_int(right_type) => {
+                    // widen the narrower int so both sides have the same type
+                    if LLVMGetIntTypeWidth(left_type) < LLVMGetIntTypeWidth(right_type) {
+                        left = LLVMBuildIntCast2(builder, left, right_type, 1, unnamed);
+                    } else if LLVMGetIntTypeWidth(right_type) < LLVMGetIntTypeWidth(left_type) {
+                        right = LLVMBuildIntCast2(builder, right, left_type, 1, unnamed);
+                    }
+                    Some((left, right, false))
+                }
+                // pointers can be compared, i.e. `p == null`
+                _ if is_pointer(left_type) && is_pointer(right_type) => Some((left, right, false)),
+                _ => {
+                    self.unsupported(format!("operators on {:?} and {:?}", first, second));
+                    None
+                }
+            }
+        }
+    }
+
+    // literals in the ir don't have a type, so they take the type of the instruction that
+    // uses them. anything else already has one
+    fn typed_value(
+        &mut self,
+        value: &IRValue,
+        typ: &Type,
+        builder: *mut LLVMBuilder,
+    ) -> LLVMValueRef {
+        unsafe {
+            match value {
+                IRValue::INT(i) if typ.integer_bounds().is_some() => {
+                    LLVMConstInt(self.type_to_llvm_type(typ), *i as u64, 1)
+                }
+                IRValue::INT(i) if typ.is_float() => {
+                    LLVMConstReal(self.type_to_llvm_type(typ), *i as f64)
+                }
+                IRValue::FLOAT(f) if typ.is_float() => {
+                    LLVMConstReal(self.type_to_llvm_type(typ), *f)
+                }
+                _ => self.ir_value_to_llvm_value(value, builder),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::{CStr, CString};
+
+    use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyModule};
+    use llvm_sys::core::{
+        LLVMContextCreate, LLVMContextDispose, LLVMCreateMemoryBufferWithMemoryRangeCopy,
+        LLVMDisposeMessage, LLVMDisposeModule,
+    };
+    use llvm_sys::ir_reader::LLVMParseIRInContext;
+
+    use crate::codegen::llvm::LLVMCodeGenerator;
+    use crate::compiler::CompilerOptions;
+    use crate::diagnostic::DiagnosticSink;
+    use crate::ir_parse::IRParser;
+    use crate::lex::Lexer;
+    use crate::parse::Parser;
+    use crate::resolve::Resolver;
+    use crate::symtable::SymTable;
+    use crate::typeck::TypeChecker;
+
+    // compile a program to llvm ir, checking that llvm accepts the module
+    fn ir(program: &str) -> String {
+        let compiler_options = CompilerOptions {
+            optimization: 0,
+            current_file: "test.sy".to_string(),
+        };
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let mut ast = parser.parse();
+        Resolver::new(&diagnostics).resolve(&mut ast);
+        TypeChecker::new(&diagnostics).check(&mut ast);
+        let mut ir_parser = IRParser::new(&compiler_options, &diagnostics);
+        let program = ir_parser.parse(ast);
+        let mut code_generator = LLVMCodeGenerator {
+            diagnostics: &diagnostics,
+            position: None,
+            anon_local_counter: 0,
+            anon_string_counter: 0,
+            anon_local_block_counter: 0,
+            sym_table: SymTable::new(),
+            loops: vec![],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        };
+        let module = code_generator.generate_module(&program);
+        assert!(!diagnostics.has_errors(), "{:?}", diagnostics.diagnostics());
+        let module = module.expect("a module");
+
+        // read the ir back in and verify it
+        unsafe {
+            let context = LLVMContextCreate();
+            let source = CString::new(module.clone()).unwrap();
+            let buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(
+                source.as_ptr(),
+                module.len(),
+                c"test.ir".as_ptr(),
+            );
+            let mut parsed = std::ptr::null_mut();
+            let mut message = std::ptr::null_mut();
+            if LLVMParseIRInContext(context, buffer, &mut parsed, &mut message) != 0 {
+                panic!("{}\n{}", CStr::from_ptr(message).to_string_lossy(), module);
+            }
+            if LLVMVerifyModule(
+                parsed,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            ) != 0
+            {
+                panic!("{}\n{}", CStr::from_ptr(message).to_string_lossy(), module);
+            }
+            LLVMDisposeMessage(message);
+            LLVMDisposeModule(parsed);
+            LLVMContextDispose(context);
         }
+        module
+    }
+
+    #[test]
+    fn calls_recursive_functions_with_their_signatures() {
+        let module = ir("var f = fib(n=15)
+fn fib(n: i32) -> i32 {
+    if n < 2 { n } else { fib(n=n - 1) + fib(n=n - 2) }
+}
+fn sub(a: i32, b: i32) -> i32 {
+    a = a - b
+    a
+}
+var difference = sub(b=8, a=50)");
+        assert!(module.contains("define i32 @fib(i32"), "{}", module);
+        assert!(
+            module.contains("define i32 @sub(i32 %0, i32 %1)"),
+            "{}",
+            module
+        );
+        assert!(module.contains("call i32 @fib(i32 15)"), "{}", module);
+        // fib calls itself twice
+        assert_eq!(module.matches("call i32 @fib(").count(), 3);
+        // named args are passed in the order of the parameters
+        assert!(
+            module.contains("call i32 @sub(i32 50, i32 8)"),
+            "{}",
+            module
+        );
+    }
+
+    #[test]
+    fn wraps_named_functions_used_as_values() {
+        let module = ir("fn inc(x: i32) -> i32 { x + 1 }
+var g = inc
+var a = g(x=3)");
+        // the closure holds a lambda that takes the environment and calls the function
+        assert!(module.contains("call i32 @inc(i32"), "{}", module);
+        assert!(module.contains("define i32 @\"0_lambda\"("), "{}", module);
+    }
+
+    #[test]
+    fn calls_functions_given_by_any_expression() {
+        let module = ir("fn double(n: i32) -> i32 { n * 2 }
+fn adder(by: i32) -> fn(n: i32) -> i32 { fn(n: i32) -> i32 { n + by } }
+var fns: [2]fn(n: i32) -> i32 = [double, adder(by=10)]
+var a = fns[1](n=3)
+var b = adder(by=1)(n=41)");
+        // neither callee has a name, so both are called through the closure they give
+        assert_eq!(module.matches("call i32 %").count(), 2, "{}", module);
+    }
+
+    #[test]
+    fn keeps_the_variables_of_the_program_in_globals() {
+        let module = ir("var total = 5
+var name = \"total\"
+fn bump(n: i32) -> i32 {
+    total = total + n
+    printf(\"%s\\n\", name)
+    total
+}
+var r = bump(n=2)");
+        assert!(module.contains("@total = global i32 0"), "{}", module);
+        assert!(module.contains("load i32, ptr @total"), "{}", module);
+        assert!(module.contains("store i32 %1, ptr @total"), "{}", module);
+        assert!(module.contains("load ptr, ptr @name"), "{}", module);
+    }
+
+    #[test]
+    fn compares_strings_by_their_contents() {
+        let module = ir("var a = \"abc\"
+var b = format(\"ab\", \"c\")
+var same = a == b
+var before = a < \"abd\"");
+        assert_eq!(module.matches("call i32 @strcmp").count(), 2);
+    }
+
+    #[test]
+    fn stores_negative_initializers() {
+        let module = ir("var neg = -5");
+        assert!(module.contains("store i32 -5"), "{}", module);
+    }
+
+    #[test]
+    fn stores_null_in_optional_pointers() {
+        let module = ir("var n = 1
+var maybe: ?&i32 = null
+maybe = &n");
+        assert!(module.contains("store ptr null"), "{}", module);
+    }
+
+    #[test]
+    fn uses_the_width_and_signedness_of_integer_types() {
+        let module = ir("var wide: i64 = 5000000000
+var small: u8 = 255
+var big = 0xFFFFFFFFFFFFFFFFu64
+var half = big / 2u64
+var above = half > 1u64
+var rest = small % 7u8
+var n = -7
+var signed = n / 2
+var flag: bool = true");
+        assert!(module.contains("store i64 5000000000"), "{}", module);
+        assert!(module.contains("store i8 -1"), "{}", module);
+        assert!(module.contains("udiv i64"), "{}", module);
+        assert!(module.contains("icmp ugt i64"), "{}", module);
+        assert!(module.contains("urem i8"), "{}", module);
+        assert!(module.contains("sdiv i32"), "{}", module);
+        // bools are held as wide as comparisons give them
+        assert!(module.contains("store i32 1, ptr @flag"), "{}", module);
     }
 }
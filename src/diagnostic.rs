@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+
+use crate::token::Position;
+
+// error codes are grouped by the stage that reports them
+// E00xx lexing
+// E01xx parsing
+// E02xx ir generation
+// E03xx interpreting
+// E04xx codegen
+// E05xx type checking
+// E06xx name resolution
+// warnings use the same groups with a W prefix
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    ERROR,
+    WARNING,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::ERROR => "error",
+            Severity::WARNING => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub position: Position,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    // not every diagnostic can point at source (i.e. code we generated ourselves)
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            primary: None,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(code: &'static str, message: String, position: Position) -> Diagnostic {
+        Diagnostic::new(Severity::ERROR, code, message).with_primary(position, "".to_string())
+    }
+
+    pub fn with_primary(mut self, position: Position, message: String) -> Diagnostic {
+        self.primary = Some(Label { position, message });
+        self
+    }
+
+    // set the message shown under the primary span
+    pub fn with_label(mut self, message: String) -> Diagnostic {
+        if let Some(primary) = self.primary.as_mut() {
+            primary.message = message;
+        }
+        self
+    }
+
+    pub fn with_secondary(mut self, position: Position, message: String) -> Diagnostic {
+        self.secondary.push(Label { position, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    // render in the same style as rustc, i.e.
+    //
+    // error[E0100]: expected identifier found `(`
+    //  --> examples/dev.sy:3:4
+    //   |
+    // 3 | fn (x: i32) {
+    //   |    ^ expected identifier
+    //   = note: ...
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!(
+            "{}[{}]: {}\n",
+            self.severity.name(),
+            self.code,
+            self.message
+        );
+
+        let mut labels: Vec<(&Label, char)> = vec![];
+        if let Some(primary) = &self.primary {
+            labels.push((primary, '^'));
+        }
+        for secondary in self.secondary.iter() {
+            labels.push((secondary, '-'));
+        }
+
+        let gutter = labels
+            .iter()
+            .map(|(label, _)| label.position.line_start.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        for (i, (label, marker)) in labels.iter().enumerate() {
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            out += &format!("{}{} {}\n", pad, arrow, label.position);
+            out += &format!("{} |\n", pad);
+            if let Some(line) = source_line(source, &label.position) {
+                let col_start = label.position.col_start.max(1) as usize;
+                let width = if label.position.line_start == label.position.line_end {
+                    (label.position.col_end as usize).saturating_sub(col_start)
+                } else {
+                    line.chars().count().saturating_sub(col_start - 1)
+                };
+                out += &format!(
+                    "{:>gutter$} | {}\n",
+                    label.position.line_start,
+                    line,
+                    gutter = gutter
+                );
+                let underline = format!(
+                    "{} | {}{} {}",
+                    pad,
+                    " ".repeat(col_start - 1),
+                    marker.to_string().repeat(width.max(1)),
+                    label.message
+                );
+                out += underline.trim_end();
+                out += "\n";
+            }
+        }
+
+        for note in self.notes.iter() {
+            out += &format!("{} = note: {}\n", pad, note);
+        }
+        out
+    }
+}
+
+// the full line of source the position starts on
+fn source_line<'a>(source: &'a str, position: &Position) -> Option<&'a str> {
+    if position.start > source.len() || !source.is_char_boundary(position.start) {
+        return None;
+    }
+    let line_start = match source[..position.start].rfind('\n') {
+        Some(i) => i + 1,
+        None => 0,
+    };
+    let line_end = match source[line_start..].find('\n') {
+        Some(i) => line_start + i,
+        None => source.len(),
+    };
+    Some(source[line_start..line_end].trim_end_matches('\r'))
+}
+
+// collects diagnostics from every stage of the compiler, stages report problems here
+// and carry on where they can so we can show the user as much as possible at once
+#[derive(Debug, Default)]
+pub struct DiagnosticSink {
+    diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+impl DiagnosticSink {
+    pub fn new() -> DiagnosticSink {
+        DiagnosticSink {
+            diagnostics: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .borrow()
+            .iter()
+            .filter(|d| d.severity == Severity::ERROR)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.diagnostics.borrow_mut().clear();
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        let mut out = "".to_string();
+        for diagnostic in self.diagnostics.borrow().iter() {
+            out += &diagnostic.render(source);
+            out += "\n";
+        }
+        let errors = self.error_count();
+        if errors == 1 {
+            out += "error: aborting due to previous error\n";
+        } else if errors > 1 {
+            out += &format!("error: aborting due to {} previous errors\n", errors);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::diagnostic::Diagnostic;
+    use crate::token::Position;
+
+    #[test]
+    fn renders_snippet_with_carets() {
+        let source = "const x = 1\nconst y = x ~ 2\n";
+        let position = Position {
+            file: Rc::from("test.sy"),
+            start: 24,
+            end: 25,
+            col_start: 13,
+            col_end: 14,
+            line_start: 2,
+            line_end: 2,
+        };
+        let rendered = Diagnostic::error("E0001", "unexpected character `~`".to_string(), position)
+            .with_label("here".to_string())
+            .with_note("a note".to_string())
+            .render(source);
+        assert_eq!(
+            rendered,
+            "error[E0001]: unexpected character `~`\n --> test.sy:2:13\n  |\n2 | const y = x ~ 2\n  |             ^ here\n  = note: a note\n"
+        );
+    }
+}
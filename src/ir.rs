@@ -1,4 +1,4 @@
-use crate::types::Type;
+use crate::{token::Position, types::Type};
 
 // a ref refers to a location in memory (this is abstracted away, it could be a register, the stack etc. it's up to the code-generator to decide that)
 #[derive(Debug, Clone)]
@@ -6,6 +6,26 @@ pub struct Ref {
     pub value: String,
 }
 
+// the interpreter's slices see part of an array variable, so writes through them change
+// it. the frame is the id of the function call the variable belongs to
+#[derive(Debug, Clone)]
+pub struct Slice {
+    pub frame: usize,
+    pub variable: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+// the interpreter keeps variables that have had their address taken in heap cells, so
+// pointers to them stay valid after the function they belong to returns. a pointer sees
+// the whole cell, or an element of the array in it. the offset is how far it has been moved
+#[derive(Debug, Clone)]
+pub struct Pointer {
+    pub cell: usize,
+    pub element: Option<usize>,
+    pub offset: i64,
+}
+
 // #[derive(Debug, Clone)]
 // pub struct Bin {
 //     pub left: IRValue,
@@ -15,14 +35,51 @@ pub struct Ref {
 #[derive(Debug, Clone)]
 pub enum IRValue {
     REF(Ref),
-    INT(i32),
-    FLOAT(f32),
+    INT(i64),
+    FLOAT(f64),
     STRING(String),
+    CHAR(char),
+    // the value of `()`
+    UNIT,
     STRUCT(Vec<IRValue>),
+    // the index of an enum variant and its payload
+    VARIANT(usize, Vec<IRValue>),
+    ARRAY(Vec<IRValue>),
+    SLICE(Slice),
+    POINTER(Pointer),
+    // the value of an optional that holds nothing
+    NULL,
+    // a variable that has moved to the heap cell with this index (only used by the interpreter)
+    CELL(usize),
+    // a function and the values of the variables it captured
+    CLOSURE(String, Vec<IRValue>),
     // todo this is a hack
     INTRINSIC(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+}
+
+impl Comparison {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::EQ => "==",
+            Comparison::NE => "!=",
+            Comparison::LT => "<",
+            Comparison::LE => "<=",
+            Comparison::GT => ">",
+            Comparison::GE => ">=",
+        }
+    }
+}
+
 // todo this should definitely be an enum, or maybe not :')
 #[derive(Debug, Clone)]
 pub enum Instruction {
@@ -33,10 +90,21 @@ pub enum Instruction {
     NONE,
     PROGRAM(Box<Vec<Instruction>>),
     BLOCK(String, Box<Vec<Instruction>>),
-    // integer addition
-    ADD(String, IRValue, IRValue),
-    // integer subtraction
-    SUB(String, IRValue, IRValue),
+    // arithmetic on two numbers, the type is the type of the result which both are
+    // converted to
+    ADD(String, IRValue, IRValue, Type),
+    SUB(String, IRValue, IRValue, Type),
+    MUL(String, IRValue, IRValue, Type),
+    DIV(String, IRValue, IRValue, Type),
+    // the remainder of the division
+    MOD(String, IRValue, IRValue, Type),
+    // compare two values, the result is 1 if the comparison holds and 0 if it doesn't. the
+    // type is the type both values are converted to
+    CMP(String, Comparison, IRValue, IRValue, Type),
+    // arithmetic negation
+    NEG(String, IRValue, Type),
+    // logical not, 1 if the value is 0 and 0 otherwise
+    NOT(String, IRValue),
     // load instruction (todo this should depend on the type?)
     LOAD(String, Ref),
     // store a value in a ref value
@@ -49,12 +117,92 @@ pub enum Instruction {
     // conditional branch (as we are branching to other blocks this should be the last)
     // first arg is the condition, second is the body, third is the else
     COND_BR(IRValue, Box<Instruction>, Option<Box<Instruction>>),
+    // a conditional branch with a value. the first arg is where the value goes, then the
+    // condition, then each branch followed by the value it ends with
+    IF(
+        String,
+        IRValue,
+        Box<Instruction>,
+        IRValue,
+        Box<Instruction>,
+        IRValue,
+    ),
+    // loop until the condition is false. first arg is the block that works out the condition,
+    // second is the condition, third is the body and the fourth is ran after every iteration
+    // (including ones that continue)
+    LOOP(
+        Box<Instruction>,
+        IRValue,
+        Box<Instruction>,
+        Option<Box<Instruction>>,
+    ),
+    // leave the innermost loop
+    BREAK,
+    // go to the next iteration of the innermost loop
+    CONTINUE,
     // first arg is the function to call, the second is the first param (todo support more params)
     CALL(String, String, Vec<IRValue>),
-    FUNC(String, Vec<Type>, Box<Instruction>),
+    // call the closure in the second arg, the third is its type (a Type::FN)
+    CALL_VALUE(String, IRValue, Type, Vec<IRValue>),
+    // name, the variables it captures if it is a closure, parameter names and types, return
+    // type (none if it doesn't return a value) and body. closures are passed what they
+    // capture before their params
+    FUNC(
+        String,
+        Option<Vec<String>>,
+        Vec<(String, Type)>,
+        Option<Type>,
+        Box<Instruction>,
+    ),
+    // make a closure of the function in the second arg, capturing the values in the third
+    CLOSURE(String, String, Vec<IRValue>),
+    // make a value of the struct named in the second arg, the fields are in declaration order
+    STRUCT(String, String, Vec<IRValue>),
+    // read a field of the struct in the second arg. the path is the index of the field, then
+    // the index of the field within that one and so on, i.e. `a.b.c`
+    FIELD(String, IRValue, Vec<usize>),
+    // store the value in the field of a struct variable at the end of the path
+    STORE_FIELD(Ref, Vec<usize>, IRValue),
+    // make a value of the enum named in the second arg, holding the variant at the index in
+    // the third with the payload in the fourth
+    VARIANT(String, String, usize, Vec<IRValue>),
+    // the index of the variant an enum value holds
+    TAG(String, IRValue),
+    // read the value at the last index of the payload of an enum value, which must hold the
+    // variant at the index before it. the enum is named in the third arg
+    PAYLOAD(String, IRValue, String, usize, usize),
+    // make an array of the values
+    ARRAY(String, Vec<IRValue>),
+    // read the element at the index of an array or slice, the type is the element type.
+    // indexes are checked against the length when the program runs
+    INDEX(String, IRValue, IRValue, Type),
+    // slice an array or slice from the first index up to (not including) the second
+    SLICE(String, IRValue, IRValue, IRValue, Type),
+    // the length of a slice
+    LEN(String, IRValue),
+    // store the value at the index of an array or slice variable
+    STORE_INDEX(Ref, IRValue, IRValue, Type),
+    // the address of a variable, or of the element at the index of an array or slice
+    // variable. the type is what the address points to
+    ADDRESS(String, Ref, Option<IRValue>, Type),
+    // read what a pointer points to, the type is what it points to
+    DEREF(String, IRValue, Type),
+    // store the value in what a pointer points to, or in the field at the end of the path
+    // if it points to a struct
+    STORE_DEREF(IRValue, Vec<usize>, IRValue, Type),
+    // move a pointer along by a number of the values it points to
+    OFFSET(String, IRValue, IRValue, Type),
+    // the pointer in an optional, which is checked against null when the program runs
+    UNWRAP(String, IRValue),
+    // return from the current function
+    RET(Option<IRValue>),
     // todo need to decide if this is typed/untyped!
     // todo for now this is just the type but we may want the identifier?
     TYPE(String, Vec<Type>),
+    // an enum type, its variants and the types of their payloads
+    ENUM(String, Vec<(String, Vec<Type>)>),
+    // the source position of the instructions that follow
+    LOC(Position),
 }
 
 impl Instruction {
@@ -84,8 +232,40 @@ impl Instruction {
                 "{:<15} = {:<10} {:?} {:?}",
                 location, "stack_var", typ, instruction_data
             ),
-            Instruction::ADD(location, left, right) => {
-                format!("{:<15} = {:<10} {:?} + {:?}", location, "add", left, right)
+            Instruction::ADD(location, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} + {:?}",
+                location, "add", typ, left, right
+            ),
+            Instruction::SUB(location, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} - {:?}",
+                location, "sub", typ, left, right
+            ),
+            Instruction::MUL(location, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} * {:?}",
+                location, "mul", typ, left, right
+            ),
+            Instruction::DIV(location, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} / {:?}",
+                location, "div", typ, left, right
+            ),
+            Instruction::MOD(location, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} % {:?}",
+                location, "mod", typ, left, right
+            ),
+            Instruction::CMP(location, comparison, left, right, typ) => format!(
+                "{:<15} = {:<10} {:?} {:?} {} {:?}",
+                location,
+                "cmp",
+                typ,
+                left,
+                comparison.symbol(),
+                right
+            ),
+            Instruction::NEG(location, value, typ) => {
+                format!("{:<15} = {:<10} {:?} {:?}", location, "neg", typ, value)
+            }
+            Instruction::NOT(location, value) => {
+                format!("{:<15} = {:<10} {:?}", location, "not", value)
             }
             Instruction::CALL(location, callee, arg) => {
                 format!(
@@ -93,12 +273,89 @@ impl Instruction {
                     location, "call", callee, arg
                 )
             }
-            Instruction::FUNC(name, params, instructions) => {
-                format!("def {:<15} ({:?}) = {:?}", name, params, instructions)
+            Instruction::CALL_VALUE(location, callee, _, args) => {
+                format!(
+                    "{:<15} = {:<10} {:?} args [{:?}]",
+                    location, "call", callee, args
+                )
+            }
+            Instruction::FUNC(name, captures, params, return_type, instructions) => {
+                format!(
+                    "def {:<15} [{:?}] ({:?}) -> {:?} = {:?}",
+                    name, captures, params, return_type, instructions
+                )
             }
+            Instruction::CLOSURE(location, function, values) => {
+                format!(
+                    "{:<15} = {:<10} {} [{:?}]",
+                    location, "closure", function, values
+                )
+            }
+            Instruction::STRUCT(location, name, values) => {
+                format!("{:<15} = {:<10} {} {:?}", location, "struct", name, values)
+            }
+            Instruction::FIELD(location, value, path) => {
+                format!("{:<15} = {:<10} {:?} {:?}", location, "field", value, path)
+            }
+            Instruction::STORE_FIELD(the_storee, path, value) => format!(
+                "         {:<10} {:?} {:?} {:?}",
+                "store", the_storee, path, value
+            ),
+            Instruction::VARIANT(location, name, tag, values) => format!(
+                "{:<15} = {:<10} {}.{} {:?}",
+                location, "variant", name, tag, values
+            ),
+            Instruction::TAG(location, value) => {
+                format!("{:<15} = {:<10} {:?}", location, "tag", value)
+            }
+            Instruction::PAYLOAD(location, value, name, tag, index) => format!(
+                "{:<15} = {:<10} {:?} {}.{} [{}]",
+                location, "payload", value, name, tag, index
+            ),
+            Instruction::ARRAY(location, values) => {
+                format!("{:<15} = {:<10} {:?}", location, "array", values)
+            }
+            Instruction::INDEX(location, value, index, typ) => format!(
+                "{:<15} = {:<10} {:?} [{:?}] {}",
+                location, "index", value, index, typ
+            ),
+            Instruction::SLICE(location, value, start, end, typ) => format!(
+                "{:<15} = {:<10} {:?} [{:?}..{:?}] {}",
+                location, "slice", value, start, end, typ
+            ),
+            Instruction::LEN(location, value) => {
+                format!("{:<15} = {:<10} {:?}", location, "len", value)
+            }
+            Instruction::STORE_INDEX(the_storee, index, value, typ) => format!(
+                "         {:<10} {:?} [{:?}] {:?} {}",
+                "store", the_storee, index, value, typ
+            ),
+            Instruction::ADDRESS(location, variable, index, typ) => format!(
+                "{:<15} = {:<10} {:?} [{:?}] {}",
+                location, "address", variable, index, typ
+            ),
+            Instruction::DEREF(location, pointer, typ) => {
+                format!("{:<15} = {:<10} {:?} {}", location, "deref", pointer, typ)
+            }
+            Instruction::STORE_DEREF(pointer, path, value, typ) => format!(
+                "         {:<10} {:?} {:?} {:?} {}",
+                "store_deref", pointer, path, value, typ
+            ),
+            Instruction::OFFSET(location, pointer, count, typ) => format!(
+                "{:<15} = {:<10} {:?} + {:?} {}",
+                location, "offset", pointer, count, typ
+            ),
+            Instruction::UNWRAP(location, value) => {
+                format!("{:<15} = {:<10} {:?}", location, "unwrap", value)
+            }
+            Instruction::RET(value) => format!("{:<15} {:?}", "ret", value),
             Instruction::TYPE(label, types) => {
                 format!("{:<15} = type {:?}", label, types)
             }
+            Instruction::ENUM(label, variants) => {
+                format!("{:<15} = enum {:?}", label, variants)
+            }
+            Instruction::LOC(position) => format!("{:<15} {}", "loc", position),
             Instruction::COND_BR(condition, body, else_body) => {
                 if let Some(else_body_unwrapped) = else_body {
                     format!(
@@ -117,6 +374,33 @@ impl Instruction {
                     )
                 }
             }
+            Instruction::IF(location, condition, body, body_value, else_body, else_value) => {
+                format!(
+                    "{:<15} = {:<10} {:?} then {}{:?} else {}{:?}",
+                    location,
+                    "if",
+                    condition,
+                    body.to_string_for_writing(),
+                    body_value,
+                    else_body.to_string_for_writing(),
+                    else_value
+                )
+            }
+            Instruction::LOOP(condition_block, condition, body, step) => {
+                let mut s = format!(
+                    "{:<15} {} {:?} do {}",
+                    "loop",
+                    condition_block.to_string_for_writing(),
+                    condition,
+                    body.to_string_for_writing()
+                );
+                if let Some(step) = step {
+                    s += &format!("then {}", step.to_string_for_writing());
+                }
+                s
+            }
+            Instruction::BREAK => "break".to_string(),
+            Instruction::CONTINUE => "continue".to_string(),
             _ => panic!(),
         }
     }
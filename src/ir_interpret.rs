@@ -1,33 +1,83 @@
-use std::{collections::HashMap, string, time::Instant};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{self, Write},
+    time::Instant,
+};
 
-use log::{debug, info};
+use log::debug;
 
 use crate::{
     compiler::CompilerOptions,
-    ir::{IRValue, Instruction, Ref},
+    diagnostic::{Diagnostic, DiagnosticSink, Severity},
+    ir::{Comparison, IRValue, Instruction, Pointer, Ref, Slice},
+    token::Position,
     types::Type,
 };
 
+// where control goes when it doesn't go to the next instruction
+#[derive(Debug, Clone)]
+pub enum Jump {
+    BREAK,
+    CONTINUE,
+    // leave the function with the value it returns
+    RET(IRValue),
+}
+
 pub struct IRInterpreter<'a> {
     pub compiler_options: &'a CompilerOptions,
-    pub counter: usize,
+    pub diagnostics: &'a DiagnosticSink,
+    // the position of the code we are currently executing (from LOC instructions)
+    pub position: Option<Position>,
     // pub instruction: Instruction,
     // todo for now this is an i32 but should be a generic 'value'
     // this should be a symtable
     pub variables_map: HashMap<String, IRValue>,
+    // the id of the current function call, slices use it to find the variable they see
+    pub frame: usize,
+    pub frame_counter: usize,
+    // the ids and variables of the calls waiting on the current one, the outermost first
+    pub frames: Vec<(usize, HashMap<String, IRValue>)>,
+    // variables that have had their address taken, see ir::Pointer
+    pub heap: Vec<IRValue>,
+    // set by break, continue and ret, blocks stop executing until the loop or call handles it
+    pub jump: Option<Jump>,
+    // the parameter names and bodies of the functions that have been defined
+    pub functions: HashMap<String, (Vec<String>, Instruction)>,
+    // the field types of the structs that have been declared
+    pub structs: HashMap<String, Vec<Type>>,
+    // the payload types of each variant of the enums that have been declared
+    pub enums: HashMap<String, Vec<Vec<Type>>>,
 }
 
 /*
 This will walk through the instructions and evaluate them (no JIT yet :().
 Still a massive WIP as we need to decide on the instruction model (SSA [Single Static Assignment] etc).
 */
-impl IRInterpreter<'_> {
+impl<'a> IRInterpreter<'a> {
+    pub fn new(
+        compiler_options: &'a CompilerOptions,
+        diagnostics: &'a DiagnosticSink,
+    ) -> IRInterpreter<'a> {
+        IRInterpreter {
+            compiler_options,
+            diagnostics,
+            position: None,
+            variables_map: HashMap::new(),
+            frame: 0,
+            frame_counter: 0,
+            frames: vec![],
+            heap: vec![],
+            jump: None,
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        }
+    }
+
     pub fn execute(&mut self, instruction: &Instruction) -> Option<IRValue> {
         let now = Instant::now();
-        self.variables_map.insert(
-            "printf".to_string(),
-            IRValue::INTRINSIC("printf".to_string()),
-        );
+        self.variables_map.extend(new_frame());
         let result = self.execute_instruction(instruction);
         debug!("vars {:?}", self.variables_map);
         let elapsed = now.elapsed();
@@ -45,22 +95,365 @@ impl IRInterpreter<'_> {
             Instruction::BLOCK(_, instructions) => self.excecute_block(instructions.clone()),
             Instruction::STACK_VAR(label, typ, value) => self.execute_stack_var(label, typ, value),
             Instruction::LOAD(label, value) => self.execute_load(label, value),
-            Instruction::ADD(label, left, right) => self.execute_add(label, left, right),
+            Instruction::ADD(label, left, right, typ)
+            | Instruction::SUB(label, left, right, typ)
+            | Instruction::MUL(label, left, right, typ)
+            | Instruction::DIV(label, left, right, typ)
+            | Instruction::MOD(label, left, right, typ) => {
+                self.execute_arithmetic(instruction, label, left, right, typ)
+            }
+            Instruction::CMP(label, comparison, left, right, typ) => {
+                self.execute_cmp(label, comparison, left, right, typ)
+            }
+            Instruction::NEG(label, value, typ) => self.execute_neg(label, value, typ),
+            Instruction::NOT(label, value) => {
+                let result =
+                    IRValue::INT(!self.evaluate_instruction_data_for_booleanness(value) as i64);
+                self.variables_map.insert(label.to_string(), result.clone());
+                Some(result)
+            }
+            Instruction::STORE(storee, value) => self.execute_store(storee, value),
             Instruction::CALL(label, callee, args) => self.execute_call(label, callee, args),
+            Instruction::CALL_VALUE(label, callee, _, args) => {
+                let Some(IRValue::CLOSURE(function, captured)) = self.resolve(callee) else {
+                    self.error("E0301", format!("cannot call {:?}", callee));
+                    return None;
+                };
+                // the captured values go before the args
+                let args = captured.iter().chain(args.iter()).cloned().collect();
+                self.call_function(label, &function, &args)
+            }
+            Instruction::CLOSURE(label, function, values) => {
+                let mut captured = vec![];
+                for value in values.iter() {
+                    captured.push(self.resolve(value)?);
+                }
+                let closure = IRValue::CLOSURE(function.to_string(), captured);
+                self.variables_map
+                    .insert(label.to_string(), closure.clone());
+                Some(closure)
+            }
+            Instruction::TYPE(name, types) => {
+                self.structs.insert(name.to_string(), types.clone());
+                None
+            }
+            Instruction::ENUM(name, variants) => {
+                let payloads = variants.iter().map(|(_, payload)| payload.clone());
+                self.enums.insert(name.to_string(), payloads.collect());
+                None
+            }
+            Instruction::VARIANT(label, _, tag, values) => {
+                let mut payload = vec![];
+                for value in values.iter() {
+                    payload.push(self.resolve(value)?);
+                }
+                let value = IRValue::VARIANT(*tag, payload);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::TAG(label, value) => {
+                let IRValue::VARIANT(tag, _) = self.resolve(value)? else {
+                    self.error("E0301", format!("{:?} is not an enum", value));
+                    return None;
+                };
+                let value = IRValue::INT(tag as i64);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::PAYLOAD(label, value, name, tag, index) => {
+                let value = match self.resolve(value)? {
+                    IRValue::VARIANT(held, mut payload) if held == *tag => {
+                        payload.swap_remove(*index)
+                    }
+                    value => {
+                        let message =
+                            format!("{:?} doesn't hold variant {} of {}", value, tag, name);
+                        self.error("E0301", message);
+                        return None;
+                    }
+                };
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::STRUCT(label, _, values) => {
+                let mut fields = vec![];
+                for value in values.iter() {
+                    fields.push(self.resolve(value)?);
+                }
+                let value = IRValue::STRUCT(fields);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::FIELD(label, value, path) => {
+                let mut value = self.resolve(value)?;
+                for index in path.iter() {
+                    let IRValue::STRUCT(mut fields) = value else {
+                        self.error("E0301", format!("{:?} has no fields", value));
+                        return None;
+                    };
+                    value = fields.swap_remove(*index);
+                }
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::STORE_FIELD(storee, path, value) => {
+                let value = self.resolve(value)?;
+                let frame = self.frame_of(&storee.value);
+                let variables = match self.frames.iter_mut().find(|(id, _)| *id == frame) {
+                    Some((_, variables)) if frame != self.frame => variables,
+                    _ => &mut self.variables_map,
+                };
+                let Some(field) = variables.get_mut(&storee.value) else {
+                    self.error("E0300", format!("could not find `{}`", storee.value));
+                    return None;
+                };
+                let mut field = stored_mut(field, &mut self.heap);
+                for index in path.iter() {
+                    let IRValue::STRUCT(fields) = field else {
+                        let message = format!("`{}` is not a struct", storee.value);
+                        self.error("E0301", message);
+                        return None;
+                    };
+                    field = &mut fields[*index];
+                }
+                *field = value;
+                None
+            }
+            Instruction::ARRAY(label, values) => {
+                let mut elements = vec![];
+                for value in values.iter() {
+                    elements.push(self.resolve(value)?);
+                }
+                let value = IRValue::ARRAY(elements);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::INDEX(label, value, index, _) => {
+                let slice = self.slice(value)?;
+                let index = self.index(index, slice.len)?;
+                let value = self.elements(&slice)?[index].clone();
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::SLICE(label, value, start, end, _) => {
+                let slice = self.slice(value)?;
+                let start = self.index(start, slice.len + 1)?;
+                let end = self.index(end, slice.len + 1)?;
+                if start > end {
+                    self.error(
+                        "E0306",
+                        format!("slice starts at {} but ends at {}", start, end),
+                    );
+                    return None;
+                }
+                let value = IRValue::SLICE(Slice {
+                    start: slice.start + start,
+                    len: end - start,
+                    ..slice
+                });
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::LEN(label, value) => {
+                let value = IRValue::INT(self.slice(value)?.len as i64);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::STORE_INDEX(storee, index, value, _) => {
+                let slice = self.slice(&IRValue::REF(storee.clone()))?;
+                let index = self.index(index, slice.len)?;
+                let value = self.resolve(value)?;
+                // make sure the variable is still there before changing it
+                self.elements(&slice)?;
+                let variables = match self.frames.iter_mut().find(|(id, _)| *id == slice.frame) {
+                    Some((_, variables)) => variables,
+                    None => &mut self.variables_map,
+                };
+                if let Some(variable) = variables.get_mut(&slice.variable) {
+                    if let IRValue::ARRAY(elements) = stored_mut(variable, &mut self.heap) {
+                        elements[slice.start + index] = value;
+                    }
+                }
+                None
+            }
+            Instruction::ADDRESS(label, variable, index, _) => {
+                let pointer = match index {
+                    Some(index) => {
+                        let slice = self.slice(&IRValue::REF(variable.clone()))?;
+                        let index = self.index(index, slice.len)?;
+                        Pointer {
+                            cell: self.cell(slice.frame, &slice.variable)?,
+                            element: Some(slice.start + index),
+                            offset: 0,
+                        }
+                    }
+                    None => Pointer {
+                        cell: self.cell(self.frame_of(&variable.value), &variable.value)?,
+                        element: None,
+                        offset: 0,
+                    },
+                };
+                let value = IRValue::POINTER(pointer);
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::DEREF(label, pointer, _) => {
+                let (cell, element) = self.target(pointer)?;
+                let value = match element {
+                    Some(element) => match &self.heap[cell] {
+                        IRValue::ARRAY(elements) => elements[element].clone(),
+                        _ => return None,
+                    },
+                    None => self.heap[cell].clone(),
+                };
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::STORE_DEREF(pointer, path, value, _) => {
+                let value = self.resolve(value)?;
+                let (cell, element) = self.target(pointer)?;
+                let mut target = match (element, &mut self.heap[cell]) {
+                    (Some(element), IRValue::ARRAY(elements)) => &mut elements[element],
+                    (_, target) => target,
+                };
+                for index in path.iter() {
+                    let IRValue::STRUCT(fields) = target else {
+                        self.error("E0301", "only structs have fields".to_string());
+                        return None;
+                    };
+                    target = &mut fields[*index];
+                }
+                *target = value;
+                None
+            }
+            Instruction::OFFSET(label, pointer, count, _) => {
+                let IRValue::INT(count) = self.resolve(count)? else {
+                    self.error("E0301", format!("cannot offset a pointer by {:?}", count));
+                    return None;
+                };
+                let value = match self.resolve(pointer)? {
+                    IRValue::POINTER(pointer) => IRValue::POINTER(Pointer {
+                        offset: pointer.offset.wrapping_add(count),
+                        ..pointer
+                    }),
+                    IRValue::NULL => {
+                        self.error("E0308", "attempt to offset a null pointer".to_string());
+                        return None;
+                    }
+                    value => {
+                        self.error("E0301", format!("cannot offset {:?}", value));
+                        return None;
+                    }
+                };
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
+            Instruction::UNWRAP(label, value) => {
+                let value = self.resolve(value)?;
+                if let IRValue::NULL = value {
+                    self.error("E0308", "attempt to unwrap a null pointer".to_string());
+                    return None;
+                }
+                self.variables_map.insert(label.to_string(), value.clone());
+                Some(value)
+            }
             Instruction::COND_BR(condition, body, else_body) => {
                 self.execute_cond_br(condition, body, else_body)
             }
+            Instruction::IF(label, condition, body, body_value, else_body, else_value) => {
+                let (branch, value) = if self.evaluate_instruction_data_for_booleanness(condition) {
+                    (body, body_value)
+                } else {
+                    (else_body, else_value)
+                };
+                self.execute_instruction(branch);
+                // the branch jumped out before reaching its value
+                if self.jump.is_some() {
+                    return None;
+                }
+                let result = self.resolve(value)?;
+                self.variables_map.insert(label.to_string(), result.clone());
+                Some(result)
+            }
+            Instruction::LOOP(condition_block, condition, body, step) => {
+                self.execute_loop(condition_block, condition, body, step)
+            }
+            Instruction::BREAK => {
+                self.jump = Some(Jump::BREAK);
+                None
+            }
+            Instruction::CONTINUE => {
+                self.jump = Some(Jump::CONTINUE);
+                None
+            }
+            Instruction::RET(value) => {
+                let value = match value {
+                    // resolve it now, as the ref may not mean the same thing after the call
+                    Some(value) => self.resolve(value)?,
+                    None => IRValue::UNIT,
+                };
+                self.jump = Some(Jump::RET(value));
+                None
+            }
+            Instruction::FUNC(name, captures, params, _, body) => {
+                let names = captures
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .chain(params.iter().map(|(name, _)| name.to_string()))
+                    .collect();
+                self.functions
+                    .insert(name.to_string(), (names, *body.clone()));
+                None
+            }
+            Instruction::LOC(position) => {
+                self.position = Some(position.clone());
+                None
+            }
             // InstructionType::INT => self.execute_int(instruction),
             // InstructionType::ADD => self.execute_add(instruction),
             // InstructionType::STACK_VAR => self.execute_var(instruction),
-            _ => panic!(),
+            _ => {
+                self.error(
+                    "E0303",
+                    format!(
+                        "the interpreter does not support this yet ({})",
+                        instruction.to_string_for_writing().trim()
+                    ),
+                );
+                None
+            }
         }
     }
 
+    // runtime errors point at the statement we are executing
+    fn error(&self, code: &'static str, message: String) {
+        let diagnostic = match &self.position {
+            Some(position) => Diagnostic::error(code, message, position.clone()),
+            None => Diagnostic::new(Severity::ERROR, code, message),
+        };
+        self.diagnostics.emit(diagnostic);
+    }
+
     fn execute_program(&mut self, instructions: Box<Vec<Instruction>>) -> Option<IRValue> {
+        // functions and types can be used before they are defined
+        for instruction in instructions.iter() {
+            if let Instruction::FUNC(..) | Instruction::TYPE(..) | Instruction::ENUM(..) =
+                instruction
+            {
+                self.execute_instruction(instruction);
+            }
+        }
         let mut result: Option<IRValue> = None;
         for instruction in instructions.to_vec() {
+            if self.diagnostics.has_errors() {
+                // stop at the first runtime error
+                return None;
+            }
             result = self.execute_instruction(&instruction);
+            if self.jump.is_some() {
+                break;
+            }
         }
         result
     }
@@ -68,13 +461,20 @@ impl IRInterpreter<'_> {
     fn excecute_block(&mut self, instructions: Box<Vec<Instruction>>) -> Option<IRValue> {
         let mut result: Option<IRValue> = None;
         for instruction in instructions.to_vec() {
+            if self.diagnostics.has_errors() {
+                // stop at the first runtime error
+                return None;
+            }
             result = self.execute_instruction(&instruction);
+            if self.jump.is_some() {
+                break;
+            }
         }
         result
     }
 
     fn execute_load(&mut self, label: &String, ref_value: &Ref) -> Option<IRValue> {
-        if let Some(val) = self.variables_map.get(&ref_value.value) {
+        if let Some(val) = self.variable(&ref_value.value) {
             self.variables_map.insert(label.to_string(), val.clone());
         } else if ref_value.value == "printf" {
             self.variables_map
@@ -85,7 +485,7 @@ impl IRInterpreter<'_> {
                 IRValue::STRING(self.compiler_options.current_file.to_string()),
             );
         } else {
-            panic!("couldn't find var");
+            self.error("E0300", format!("could not find `{}`", ref_value.value));
         }
         None
     }
@@ -98,34 +498,237 @@ impl IRInterpreter<'_> {
     ) -> Option<IRValue> {
         if let Some(data) = value {
             match data {
-                IRValue::INT(i) => self.variables_map.insert(label.to_string(), data.clone()),
-                IRValue::FLOAT(f) => self.variables_map.insert(label.to_string(), data.clone()),
-                IRValue::STRING(s) => self.variables_map.insert(label.to_string(), data.clone()),
+                IRValue::INT(_)
+                | IRValue::FLOAT(_)
+                | IRValue::STRING(_)
+                | IRValue::CHAR(_)
+                | IRValue::UNIT
+                | IRValue::NULL
+                | IRValue::POINTER(_) => self.variables_map.insert(label.to_string(), data.clone()),
                 IRValue::REF(r) => {
-                    if let Some(v) = self.variables_map.get(&r.value) {
+                    if let Some(v) = self.variable(&r.value) {
                         self.variables_map.insert(label.to_string(), v.clone());
                     } else {
-                        panic!("couldn't find var");
+                        self.error("E0300", format!("could not find `{}`", r.value));
                     };
                     None
                 }
-                _ => panic!("unsupported type for execution"),
+                _ => {
+                    self.error("E0301", format!("cannot store {:?} in `{}`", data, label));
+                    None
+                }
             };
-        };
+        } else if let Some(value) = self.zero(typ) {
+            self.variables_map.insert(label.to_string(), value);
+        }
         None
     }
 
+    // the value of a variable, wherever it is kept
+    fn variable(&self, name: &str) -> Option<&IRValue> {
+        self.lookup(name).map(|value| self.stored(value))
+    }
+
+    // what a variable holds, which may be the cell its value was moved to
+    fn lookup(&self, name: &str) -> Option<&IRValue> {
+        self.frame_variables(self.frame_of(name))?.get(name)
+    }
+
+    // the frame a variable is in. a call has its own variables, and can use the ones at the
+    // top level
+    fn frame_of(&self, name: &str) -> usize {
+        match self.frames.first() {
+            Some((top, variables))
+                if !self.variables_map.contains_key(name) && variables.contains_key(name) =>
+            {
+                *top
+            }
+            _ => self.frame,
+        }
+    }
+
+    // a variable's value is in the heap once its address has been taken
+    fn stored<'v>(&'v self, value: &'v IRValue) -> &'v IRValue {
+        match value {
+            IRValue::CELL(cell) => &self.heap[*cell],
+            value => value,
+        }
+    }
+
+    // the heap cell of a variable in a call that hasn't returned yet, it is moved to the
+    // heap the first time its address is taken
+    fn cell(&mut self, frame: usize, variable: &str) -> Option<usize> {
+        if !self.frame_variables(frame)?.contains_key(variable) {
+            self.error("E0300", format!("could not find `{}`", variable));
+            return None;
+        }
+        let variables = match self.frames.iter_mut().find(|(id, _)| *id == frame) {
+            Some((_, variables)) if frame != self.frame => variables,
+            _ => &mut self.variables_map,
+        };
+        let value = variables.get_mut(variable)?;
+        if let IRValue::CELL(cell) = value {
+            return Some(*cell);
+        }
+        let cell = self.heap.len();
+        self.heap
+            .push(std::mem::replace(value, IRValue::CELL(cell)));
+        Some(cell)
+    }
+
+    // the cell a pointer points into and the element of it, if the pointer is to one.
+    // moving a pointer outside of what it pointed to is allowed but using it isn't
+    fn target(&self, pointer: &IRValue) -> Option<(usize, Option<usize>)> {
+        let pointer = match self.resolve(pointer)? {
+            IRValue::POINTER(pointer) => pointer,
+            IRValue::NULL => {
+                self.error("E0308", "attempt to dereference a null pointer".to_string());
+                return None;
+            }
+            value => {
+                self.error("E0301", format!("cannot dereference {:?}", value));
+                return None;
+            }
+        };
+        let (index, len) = match (pointer.element, &self.heap[pointer.cell]) {
+            (Some(element), IRValue::ARRAY(elements)) => {
+                (element as i64 + pointer.offset, elements.len())
+            }
+            _ => (pointer.offset, 1),
+        };
+        if index < 0 || index as usize >= len {
+            self.error(
+                "E0306",
+                "pointer is outside of the variable it points into".to_string(),
+            );
+            return None;
+        }
+        Some((pointer.cell, pointer.element.map(|_| index as usize)))
+    }
+
+    // the elements an array or slice value sees. an array is seen as a slice of the variable
+    // holding it
+    fn slice(&self, value: &IRValue) -> Option<Slice> {
+        let IRValue::REF(r) = value else {
+            self.error("E0301", format!("cannot index {:?}", value));
+            return None;
+        };
+        match self.variable(&r.value) {
+            Some(IRValue::ARRAY(elements)) => Some(Slice {
+                frame: self.frame_of(&r.value),
+                variable: r.value.to_string(),
+                start: 0,
+                len: elements.len(),
+            }),
+            Some(IRValue::SLICE(slice)) => Some(slice.clone()),
+            Some(value @ IRValue::REF(_)) => self.slice(value),
+            Some(value) => {
+                self.error("E0301", format!("cannot index {:?}", value));
+                None
+            }
+            None => {
+                self.error("E0300", format!("could not find `{}`", r.value));
+                None
+            }
+        }
+    }
+
+    // the elements a slice sees
+    fn elements(&self, slice: &Slice) -> Option<&[IRValue]> {
+        let variable = self.frame_variables(slice.frame)?.get(&slice.variable);
+        match variable.map(|value| self.stored(value)) {
+            Some(IRValue::ARRAY(elements)) => Some(&elements[slice.start..slice.start + slice.len]),
+            _ => {
+                self.error("E0301", format!("`{}` is not an array", slice.variable));
+                None
+            }
+        }
+    }
+
+    // the variables of a call that hasn't returned yet
+    fn frame_variables(&self, frame: usize) -> Option<&HashMap<String, IRValue>> {
+        if frame == self.frame {
+            return Some(&self.variables_map);
+        }
+        match self.frames.iter().find(|(id, _)| *id == frame) {
+            Some((_, variables)) => Some(variables),
+            None => {
+                self.error(
+                    "E0307",
+                    "a slice of a variable in a function that has returned was used".to_string(),
+                );
+                None
+            }
+        }
+    }
+
+    // an index must be less than the length
+    fn index(&self, index: &IRValue, len: usize) -> Option<usize> {
+        let IRValue::INT(index) = self.resolve(index)? else {
+            self.error("E0301", format!("cannot index with {:?}", index));
+            return None;
+        };
+        if index < 0 || index as usize >= len {
+            self.error(
+                "E0306",
+                format!("index {} is out of bounds for a length of {}", index, len),
+            );
+            return None;
+        }
+        Some(index as usize)
+    }
+
+    // the value of a variable declared without one
+    fn zero(&self, typ: &Type) -> Option<IRValue> {
+        match typ {
+            Type::F32 | Type::F64 => Some(IRValue::FLOAT(0.0)),
+            Type::STRING => Some(IRValue::STRING(String::new())),
+            Type::CHAR => Some(IRValue::CHAR('\0')),
+            Type::STRUCT(name) => {
+                let mut fields = vec![];
+                for field in self.structs.get(name)?.iter() {
+                    fields.push(self.zero(field)?);
+                }
+                Some(IRValue::STRUCT(fields))
+            }
+            // the first variant
+            Type::ENUM(name) => {
+                let mut payload = vec![];
+                for typ in self.enums.get(name)?.first()?.iter() {
+                    payload.push(self.zero(typ)?);
+                }
+                Some(IRValue::VARIANT(0, payload))
+            }
+            Type::ARRAY(element, len) => Some(IRValue::ARRAY(vec![self.zero(element)?; *len])),
+            Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_) => Some(IRValue::NULL),
+            // a slice of nothing
+            Type::SLICE(_) => Some(IRValue::SLICE(Slice {
+                frame: self.frame,
+                variable: String::new(),
+                start: 0,
+                len: 0,
+            })),
+            Type::FN(_) | Type::BLOCK | Type::TYPE => None,
+            _ => Some(IRValue::INT(0)),
+        }
+    }
+
     fn execute_call(
         &mut self,
         label: &String,
         callee: &String,
         args: &Vec<IRValue>,
     ) -> Option<IRValue> {
-        let mut callee_data = self
-            .variables_map
-            .get(callee)
-            .expect("could not find var")
-            .clone();
+        if self.functions.contains_key(callee) {
+            return self.call_function(label, callee, args);
+        }
+        let Some(callee_data) = self.variable(callee).cloned() else {
+            self.error("E0302", format!("could not find function `{}`", callee));
+            return None;
+        };
+        if let IRValue::INTRINSIC(intrinsic) = callee_data {
+            return self.execute_intrinsic(label, &intrinsic, args);
+        }
 
         //todo
         // match callee_data {
@@ -161,48 +764,317 @@ impl IRInterpreter<'_> {
         None
     }
 
-    fn execute_add(&mut self, label: &String, left: &IRValue, right: &IRValue) -> Option<IRValue> {
-        let mut lhs = 0;
-        let mut rhs = 0;
-        match left {
-            IRValue::INT(i) => lhs = *i,
-            IRValue::REF(r) => {
-                if let Some(v) = self.variables_map.get(&r.value) {
-                    match v {
-                        IRValue::INT(i) => lhs = *i,
-                        _ => panic!("unsupported type"),
-                    }
+    fn call_function(
+        &mut self,
+        label: &String,
+        function: &String,
+        args: &Vec<IRValue>,
+    ) -> Option<IRValue> {
+        let Some((params, body)) = self.functions.get(function).cloned() else {
+            self.error("E0302", format!("could not find function `{}`", function));
+            return None;
+        };
+        if params.len() != args.len() {
+            self.error(
+                "E0305",
+                format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    function,
+                    params.len(),
+                    args.len()
+                ),
+            );
+            return None;
+        }
+        // the args are worked out in the caller's frame, then bound to the params in a new one
+        let mut frame = new_frame();
+        for (param, arg) in params.iter().zip(args.iter()) {
+            frame.insert(param.to_string(), self.resolve(arg)?);
+        }
+        let caller = std::mem::replace(&mut self.variables_map, frame);
+        self.frames.push((self.frame, caller));
+        self.frame_counter += 1;
+        self.frame = self.frame_counter;
+        self.execute_instruction(&body);
+        let (frame, caller) = self.frames.pop().expect("expected the caller's frame");
+        self.frame = frame;
+        self.variables_map = caller;
+        let result = match self.jump.take() {
+            Some(Jump::RET(value)) => value,
+            // falling off the end returns nothing
+            _ => IRValue::UNIT,
+        };
+        self.variables_map.insert(label.to_string(), result.clone());
+        Some(result)
+    }
+
+    // functions built into the interpreter, they all work on the text of their args
+    fn execute_intrinsic(
+        &mut self,
+        label: &String,
+        intrinsic: &str,
+        args: &Vec<IRValue>,
+    ) -> Option<IRValue> {
+        let mut s = String::from("");
+        for arg in args.iter() {
+            s += &self.display(arg)?;
+        }
+        let result = match intrinsic {
+            // what interpolated strings are turned into
+            "format" => IRValue::STRING(s),
+            "printf" => {
+                print!("{}", s);
+                io::stdout().flush().ok();
+                IRValue::INT(s.len() as i64)
+            }
+            _ => {
+                self.error("E0302", format!("unknown intrinsic `{}`", intrinsic));
+                return None;
+            }
+        };
+        self.variables_map.insert(label.to_string(), result.clone());
+        Some(result)
+    }
+
+    // how a value is shown when it is formatted
+    fn display(&self, value: &IRValue) -> Option<String> {
+        match value {
+            IRValue::INT(i) => Some(i.to_string()),
+            IRValue::FLOAT(f) => Some(f.to_string()),
+            IRValue::STRING(s) => Some(s.to_string()),
+            IRValue::CHAR(c) => Some(c.to_string()),
+            IRValue::UNIT => Some("()".to_string()),
+            IRValue::NULL => Some("null".to_string()),
+            IRValue::ARRAY(elements) => self.display_elements(elements),
+            IRValue::SLICE(slice) => self.display_elements(self.elements(slice)?),
+            IRValue::REF(r) => match self.variable(&r.value) {
+                Some(v) => self.display(v),
+                None => {
+                    self.error("E0300", format!("could not find `{}`", r.value));
+                    None
                 }
+            },
+            _ => {
+                self.error("E0301", format!("cannot format {:?}", value));
+                None
             }
-            _ => panic!("unsupported type for execution"),
         }
-        match right {
-            IRValue::INT(i) => rhs = *i,
-            IRValue::REF(r) => {
-                if let Some(v) = self.variables_map.get(&r.value) {
-                    match v {
-                        IRValue::INT(i) => rhs = *i,
-                        _ => panic!("unsupported type"),
-                    }
+    }
+
+    fn display_elements(&self, elements: &[IRValue]) -> Option<String> {
+        let mut shown = vec![];
+        for element in elements.iter() {
+            shown.push(self.display(element)?);
+        }
+        Some(format!("[{}]", shown.join(", ")))
+    }
+
+    fn execute_arithmetic(
+        &mut self,
+        instruction: &Instruction,
+        label: &String,
+        left: &IRValue,
+        right: &IRValue,
+        typ: &Type,
+    ) -> Option<IRValue> {
+        let lhs = self.resolve(left)?;
+        let rhs = self.resolve(right)?;
+        let result = match (lhs, rhs) {
+            (IRValue::INT(lhs), IRValue::INT(rhs)) => {
+                if rhs == 0 && matches!(instruction, Instruction::DIV(..) | Instruction::MOD(..)) {
+                    self.error("E0304", "attempt to divide by zero".to_string());
+                    return None;
+                }
+                // the result wraps around like it would in a register of the type's width.
+                // only division cares about the sign, adding and multiplying the bits is the
+                // same either way
+                IRValue::INT(typ.wrap(match instruction {
+                    Instruction::ADD(..) => lhs.wrapping_add(rhs),
+                    Instruction::SUB(..) => lhs.wrapping_sub(rhs),
+                    Instruction::MUL(..) => lhs.wrapping_mul(rhs),
+                    Instruction::DIV(..) if typ.is_unsigned() => (lhs as u64 / rhs as u64) as i64,
+                    Instruction::DIV(..) => lhs.wrapping_div(rhs),
+                    _ if typ.is_unsigned() => (lhs as u64 % rhs as u64) as i64,
+                    _ => lhs.wrapping_rem(rhs),
+                }))
+            }
+            (lhs, rhs) => {
+                let (Some(lhs), Some(rhs)) = (self.evaluate_float(&lhs), self.evaluate_float(&rhs))
+                else {
+                    return None;
+                };
+                IRValue::FLOAT(match instruction {
+                    Instruction::ADD(..) => lhs + rhs,
+                    Instruction::SUB(..) => lhs - rhs,
+                    Instruction::MUL(..) => lhs * rhs,
+                    Instruction::DIV(..) => lhs / rhs,
+                    _ => lhs % rhs,
+                })
+            }
+        };
+        self.variables_map.insert(label.to_string(), result.clone());
+        Some(result)
+    }
+
+    fn execute_cmp(
+        &mut self,
+        label: &String,
+        comparison: &Comparison,
+        left: &IRValue,
+        right: &IRValue,
+        typ: &Type,
+    ) -> Option<IRValue> {
+        let lhs = self.resolve(left)?;
+        let rhs = self.resolve(right)?;
+        let ordering = match (&lhs, &rhs) {
+            // a u64 above i64::MAX is held as a negative i64
+            (IRValue::INT(l), IRValue::INT(r)) if typ.is_unsigned() => {
+                (*l as u64).partial_cmp(&(*r as u64))
+            }
+            (IRValue::INT(l), IRValue::INT(r)) => l.partial_cmp(r),
+            (IRValue::CHAR(l), IRValue::CHAR(r)) => l.partial_cmp(r),
+            (IRValue::STRING(l), IRValue::STRING(r)) => l.partial_cmp(r),
+            (IRValue::UNIT, IRValue::UNIT) => Some(Ordering::Equal),
+            (IRValue::NULL, IRValue::NULL) => Some(Ordering::Equal),
+            (IRValue::POINTER(_), IRValue::NULL) => Some(Ordering::Greater),
+            (IRValue::NULL, IRValue::POINTER(_)) => Some(Ordering::Less),
+            // pointers into different variables aren't ordered
+            (IRValue::POINTER(l), IRValue::POINTER(r)) if l.cell == r.cell => {
+                let position = |p: &Pointer| p.element.unwrap_or(0) as i64 + p.offset;
+                position(l).partial_cmp(&position(r))
+            }
+            (IRValue::POINTER(_), IRValue::POINTER(_)) => None,
+            _ => {
+                let (Some(l), Some(r)) = (self.evaluate_float(&lhs), self.evaluate_float(&rhs))
+                else {
+                    return None;
+                };
+                l.partial_cmp(&r)
+            }
+        };
+        // nan isn't ordered so every comparison with it is false, except !=
+        let holds = match (comparison, ordering) {
+            (Comparison::NE, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (Comparison::EQ, Some(ordering)) => ordering == Ordering::Equal,
+            (Comparison::LT, Some(ordering)) => ordering == Ordering::Less,
+            (Comparison::LE, Some(ordering)) => ordering != Ordering::Greater,
+            (Comparison::GT, Some(ordering)) => ordering == Ordering::Greater,
+            (Comparison::GE, Some(ordering)) => ordering != Ordering::Less,
+        };
+        let result = IRValue::INT(holds as i64);
+        self.variables_map.insert(label.to_string(), result.clone());
+        Some(result)
+    }
+
+    fn execute_neg(&mut self, label: &String, value: &IRValue, typ: &Type) -> Option<IRValue> {
+        let result = match self.resolve(value)? {
+            IRValue::INT(i) => IRValue::INT(typ.wrap(i.wrapping_neg())),
+            IRValue::FLOAT(f) => IRValue::FLOAT(-f),
+            value => {
+                self.error("E0301", format!("cannot negate {:?}", value));
+                return None;
+            }
+        };
+        self.variables_map.insert(label.to_string(), result.clone());
+        Some(result)
+    }
+
+    fn execute_store(&mut self, storee: &Ref, value: &IRValue) -> Option<IRValue> {
+        let value = self.resolve(value)?;
+        let frame = self.frame_of(&storee.value);
+        let variables = match self.frames.iter_mut().find(|(id, _)| *id == frame) {
+            Some((_, variables)) if frame != self.frame => variables,
+            _ => &mut self.variables_map,
+        };
+        let Some(variable) = variables.get_mut(&storee.value) else {
+            self.error("E0300", format!("could not find `{}`", storee.value));
+            return None;
+        };
+        *stored_mut(variable, &mut self.heap) = value;
+        None
+    }
+
+    // follow refs until we get to an actual value
+    fn resolve(&self, value: &IRValue) -> Option<IRValue> {
+        match value {
+            IRValue::REF(r) => match self.lookup(&r.value) {
+                Some(v) => self.resolve(v),
+                None => {
+                    self.error("E0300", format!("could not find `{}`", r.value));
+                    None
                 }
+            },
+            IRValue::CELL(cell) => self.resolve(&self.heap[*cell]),
+            value => Some(value.clone()),
+        }
+    }
+
+    fn evaluate_float(&self, value: &IRValue) -> Option<f64> {
+        match value {
+            IRValue::FLOAT(f) => Some(*f),
+            IRValue::INT(i) => Some(*i as f64),
+            _ => {
+                self.error("E0301", format!("expected a number found {:?}", value));
+                None
             }
-            _ => panic!("unsupported type for execution"),
         }
-        self.variables_map
-            .insert(label.to_string(), IRValue::INT(lhs + rhs));
-        Some(IRValue::INT(lhs + rhs))
     }
 
     fn evaluate_instruction_data_for_booleanness(&self, value: &IRValue) -> bool {
         match value {
-            IRValue::REF(r) => {
-                let val = self.variables_map.get(&r.value).expect("couldn't find var");
-                self.evaluate_instruction_data_for_booleanness(val)
+            IRValue::REF(r) => match self.variable(&r.value) {
+                Some(val) => self.evaluate_instruction_data_for_booleanness(val),
+                None => {
+                    self.error("E0300", format!("could not find `{}`", r.value));
+                    false
+                }
+            },
+            IRValue::INT(i) => *i != 0,
+            IRValue::FLOAT(f) => *f != 0.0,
+            _ => {
+                self.error(
+                    "E0301",
+                    format!("{:?} cannot be used as a condition", value),
+                );
+                false
+            }
+        }
+    }
+
+    fn execute_loop(
+        &mut self,
+        condition_block: &Box<Instruction>,
+        condition: &IRValue,
+        body: &Box<Instruction>,
+        step: &Option<Box<Instruction>>,
+    ) -> Option<IRValue> {
+        loop {
+            self.execute_instruction(condition_block);
+            if self.diagnostics.has_errors()
+                || !self.evaluate_instruction_data_for_booleanness(condition)
+            {
+                break;
+            }
+            self.execute_instruction(body);
+            if self.diagnostics.has_errors() {
+                break;
+            }
+            match self.jump.take() {
+                Some(Jump::BREAK) => break,
+                // continue still runs the step
+                Some(Jump::CONTINUE) | None => {}
+                // returning leaves the loop and the function it is in
+                ret => {
+                    self.jump = ret;
+                    break;
+                }
+            }
+            if let Some(step) = step {
+                self.execute_instruction(step);
             }
-            IRValue::INT(i) => *i > 0,
-            IRValue::FLOAT(f) => *f > 0.0,
-            _ => panic!("unknown condition type"),
         }
+        None
     }
 
     fn execute_cond_br(
@@ -221,3 +1093,560 @@ impl IRInterpreter<'_> {
         None
     }
 }
+
+// where a variable's value is kept, see IRInterpreter::stored
+fn stored_mut<'v>(value: &'v mut IRValue, heap: &'v mut Vec<IRValue>) -> &'v mut IRValue {
+    match value {
+        IRValue::CELL(cell) => &mut heap[*cell],
+        value => value,
+    }
+}
+
+// the variables a function starts with, which are just the intrinsics
+fn new_frame() -> HashMap<String, IRValue> {
+    HashMap::from([
+        (
+            "printf".to_string(),
+            IRValue::INTRINSIC("printf".to_string()),
+        ),
+        (
+            "format".to_string(),
+            IRValue::INTRINSIC("format".to_string()),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::compiler::CompilerOptions;
+    use crate::diagnostic::DiagnosticSink;
+    use crate::ir::IRValue;
+    use crate::ir_interpret::IRInterpreter;
+    use crate::ir_parse::IRParser;
+    use crate::lex::Lexer;
+    use crate::parse::Parser;
+    use crate::resolve::Resolver;
+    use crate::typeck::TypeChecker;
+
+    // run a program, giving back the variables it ends with and the codes of any errors
+    fn run(program: &str) -> (HashMap<String, IRValue>, Vec<&'static str>) {
+        let compiler_options = CompilerOptions {
+            optimization: 0,
+            current_file: "test.sy".to_string(),
+        };
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let mut ast = parser.parse();
+        Resolver::new(&diagnostics).resolve(&mut ast);
+        TypeChecker::new(&diagnostics).check(&mut ast);
+        let mut ir_parser = IRParser::new(&compiler_options, &diagnostics);
+        let program = ir_parser.parse(ast);
+        let mut interpreter = IRInterpreter::new(&compiler_options, &diagnostics);
+        interpreter.execute(&program);
+        let codes = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        (interpreter.variables_map, codes)
+    }
+
+    #[test]
+    fn evaluates_arithmetic_comparison_and_logical_operators() {
+        let (vars, errors) = run("var a = 10 - 3 - 2
+var b = 7 / 2 * 3 % 4
+var c = -a < 0 == 1
+var d = 1.5 * 2
+var e = 'a' < 'b' && !(3 >= 3.5)
+var f = 0 && 1 / 0
+var g = 1 || 1 / 0
+var h = -7 % 3 != -1");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("a"), "INT(5)");
+        assert_eq!(value("b"), "INT(1)");
+        assert_eq!(value("c"), "INT(1)");
+        assert_eq!(value("d"), "FLOAT(3.0)");
+        assert_eq!(value("e"), "INT(1)");
+        // the right hand sides would divide by zero if they were evaluated
+        assert_eq!(value("f"), "INT(0)");
+        assert_eq!(value("g"), "INT(1)");
+        assert_eq!(value("h"), "INT(0)");
+
+        let (_, errors) = run("var x = 1 / (2 - 2)");
+        assert_eq!(errors, vec!["E0304"]);
+    }
+
+    #[test]
+    fn compares_strings_by_their_contents() {
+        let (vars, errors) = run("var a = \"abc\"
+var b = format(\"ab\", \"c\")
+var same = a == b
+var before = a < \"abd\"
+var neg = -5");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("same"), "INT(1)");
+        assert_eq!(value("before"), "INT(1)");
+        assert_eq!(value("neg"), "INT(-5)");
+    }
+
+    #[test]
+    fn wraps_integers_to_the_width_of_their_type() {
+        let (vars, errors) = run("var a = 10u8 - 20u8
+var b: u8 = 255
+var c = b + 1u8
+var d = 0xFFFFFFFFFFFFFFFFu64 / 2u64
+var e: i32 = 2147483647
+var f = e + 1
+var g = 0xFFFFFFFFFFFFFFFFu64 > 1u64
+var h = 200u8 % 7u8
+var i: i8 = -128
+var j = -i");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("a"), "INT(246)");
+        assert_eq!(value("c"), "INT(0)");
+        assert_eq!(value("d"), "INT(9223372036854775807)");
+        assert_eq!(value("f"), "INT(-2147483648)");
+        assert_eq!(value("g"), "INT(1)");
+        assert_eq!(value("h"), "INT(4)");
+        assert_eq!(value("j"), "INT(-128)");
+    }
+
+    #[test]
+    fn runs_loops_with_break_and_continue() {
+        let (vars, errors) = run("var total = 0
+for i in 0..10 {
+    if i == 3 { continue }
+    if i == 7 { break }
+    total = total + i
+}
+var n = 0
+var evens = 0
+while n < 10 {
+    n = n + 1
+    if n % 2 == 1 { continue }
+    evens = evens + 1
+}");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["total"]), "INT(18)");
+        assert_eq!(format!("{:?}", vars["n"]), "INT(10)");
+        assert_eq!(format!("{:?}", vars["evens"]), "INT(5)");
+
+        let (_, errors) = run("break");
+        assert_eq!(errors, vec!["E0207"]);
+    }
+
+    #[test]
+    fn returns_values_from_functions() {
+        let (vars, errors) = run("fn seven() -> i32 { ret 7 }
+fn first_multiple() -> i32 {
+    for i in 1..100 {
+        if i % 4 == 0 { ret i * 10 }
+    }
+    ret 0
+}
+fn nothing() { ret }
+var a = seven() + 1
+var b = first_multiple()
+var c = nothing()");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["a"]), "INT(8)");
+        assert_eq!(format!("{:?}", vars["b"]), "INT(40)");
+        assert_eq!(format!("{:?}", vars["c"]), "UNIT");
+
+        let (_, errors) = run("ret 1
+fn f() { ret 1 }
+fn g() -> i32 { ret }");
+        assert_eq!(errors, vec!["E0209", "E0210", "E0210"]);
+    }
+
+    #[test]
+    fn blocks_and_ifs_give_their_last_value() {
+        let (vars, errors) = run("fn double() -> i32 {
+    var x = 21
+    x * 2
+}
+var a = if 2 > 1 { 10 } else { 20 }
+var b = if 2 < 1 { 10 } else { var y = 5
+    y + 15 }
+var c = { var z = 3
+    z * z }
+var d = double()
+var n = 0
+var e = if n < 0 { -1 } else if n == 0 { 0 } else { 1 }");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["a"]), "INT(10)");
+        assert_eq!(format!("{:?}", vars["b"]), "INT(20)");
+        assert_eq!(format!("{:?}", vars["c"]), "INT(9)");
+        assert_eq!(format!("{:?}", vars["d"]), "INT(42)");
+        assert_eq!(format!("{:?}", vars["e"]), "INT(0)");
+
+        let (_, errors) = run("var x = if 1 { 1 } else { \"one\" }");
+        assert_eq!(errors, vec!["E0211"]);
+    }
+
+    #[test]
+    fn inner_declarations_shadow_without_changing_the_outer_ones() {
+        let (vars, errors) = run("var s = 1
+var t = { var s = s + 1
+    s * 10 }");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["s"]), "INT(1)");
+        assert_eq!(format!("{:?}", vars["t"]), "INT(20)");
+    }
+
+    #[test]
+    fn calls_functions_with_arguments() {
+        let (vars, errors) = run("var f = fib(n=15)
+fn fib(n: i32) -> i32 {
+    if n < 2 { n } else { fib(n=n - 1) + fib(n=n - 2) }
+}
+fn sub(a: i32, b: i32) -> i32 {
+    a = a - b
+    a
+}
+var a = 50
+var difference = sub(b=8, a=a)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["f"]), "INT(610)");
+        assert_eq!(format!("{:?}", vars["difference"]), "INT(42)");
+        // the callee has its own frame, so it can't change the caller's variables
+        assert_eq!(format!("{:?}", vars["a"]), "INT(50)");
+
+        let add = "fn add(a: i32, b: i32) -> i32 { a + b }\n";
+        let codes = |call: &str| run(&(add.to_string() + call)).1;
+        assert_eq!(codes("add(1, 2)"), vec!["E0212", "E0212"]);
+        assert_eq!(codes("add(a=1, c=2)"), vec!["E0213"]);
+        assert_eq!(codes("add(a=1, a=2)"), vec!["E0214"]);
+        assert_eq!(codes("add(b=1)"), vec!["E0215"]);
+        assert_eq!(codes("printf(format=1)"), vec!["E0213"]);
+    }
+
+    #[test]
+    fn functions_use_the_variables_at_the_top_level() {
+        let (vars, errors) = run("var total = 5
+fn bump(n: i32) -> i32 {
+    total = total + n
+    total * 3
+}
+var r = bump(n=2)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["total"]), "INT(7)");
+        assert_eq!(format!("{:?}", vars["r"]), "INT(21)");
+    }
+
+    #[test]
+    fn calls_lambdas_and_closures() {
+        let (vars, errors) = run("var offset = 10
+var add_offset = fn(x: i32) -> i32 { x + offset }
+offset = 100
+fn apply(f: fn(i32) -> i32, value: i32) -> i32 { f(value) }
+fn adder(n: i32) -> fn(i32) -> i32 {
+    fn(x: i32) -> i32 { x + n }
+}
+var a = add_offset(x=5)
+var b = apply(f=add_offset, value=1)
+var c = apply(f=fn(n: i32) -> i32 { n * 3 }, value=2)
+var add_two = adder(n=2)
+var d = apply(f=add_two, value=40)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        // the closure keeps the value offset had when it was made
+        assert_eq!(format!("{:?}", vars["a"]), "INT(15)");
+        assert_eq!(format!("{:?}", vars["b"]), "INT(11)");
+        assert_eq!(format!("{:?}", vars["c"]), "INT(6)");
+        assert_eq!(format!("{:?}", vars["d"]), "INT(42)");
+
+        let (_, errors) = run("var f = fn(x: i32) -> i32 { x }
+var y = f(1)");
+        assert_eq!(errors, vec!["E0212"]);
+    }
+
+    #[test]
+    fn calls_functions_through_values_with_the_names_of_their_type() {
+        let (vars, errors) = run("fn inc(x: i32) -> i32 { x + 1 }
+var g = inc
+var a = g(x=3)
+fn apply(f: fn(i32) -> i32, value: i32) -> i32 { f(value) }
+var b = apply(f=inc, value=10)
+fn apply_named(f: fn(n: i32) -> i32, value: i32) -> i32 { f(n=value) }
+var c = apply_named(f=fn(n: i32) -> i32 { n * 2 }, value=21)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["a"]), "INT(4)");
+        assert_eq!(format!("{:?}", vars["b"]), "INT(11)");
+        assert_eq!(format!("{:?}", vars["c"]), "INT(42)");
+
+        let codes = |program: &str| run(program).1;
+        assert_eq!(
+            codes("fn inc(x: i32) -> i32 { x + 1 }\nvar g = inc\nvar a = g(3)"),
+            vec!["E0212"]
+        );
+        assert_eq!(
+            codes("fn apply(f: fn(i32) -> i32) -> i32 { f(x=1) }"),
+            vec!["E0213"]
+        );
+        // the names of the params are part of what the variable holds
+        assert_eq!(
+            codes("var f = fn(x: i32) -> i32 { x }\nf = fn(y: i32) -> i32 { y }"),
+            vec!["E0500"]
+        );
+    }
+
+    #[test]
+    fn calls_functions_given_by_any_expression() {
+        let (vars, errors) = run("fn double(n: i32) -> i32 { n * 2 }
+fn adder(by: i32) -> fn(n: i32) -> i32 { fn(n: i32) -> i32 { n + by } }
+var fns: [2]fn(n: i32) -> i32 = [double, adder(by=10)]
+var a = fns[0](n=3)
+var b = fns[1](n=3)
+var c = adder(by=1)(n=41)
+var d = (fns[0])(n=5)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["a"]), "INT(6)");
+        assert_eq!(format!("{:?}", vars["b"]), "INT(13)");
+        assert_eq!(format!("{:?}", vars["c"]), "INT(42)");
+        assert_eq!(format!("{:?}", vars["d"]), "INT(10)");
+
+        let codes = |program: &str| run(program).1;
+        assert_eq!(codes("var a = [1, 2]\nvar b = a[0](1)"), vec!["E0204"]);
+        assert_eq!(
+            codes("fn adder(by: i32) -> fn(n: i32) -> i32 { fn(n: i32) -> i32 { n + by } }\nvar c = adder(by=1)(m=2)"),
+            vec!["E0213"]
+        );
+    }
+
+    #[test]
+    fn builds_structs_and_reads_and_writes_fields() {
+        let (vars, errors) = run("fn area(r: Rect) -> i32 {
+    (r.max.x - r.min.x) * (r.max.y - r.min.y)
+}
+const Point = type { x: i32 y: i32 }
+const Rect = type { min: Point, max: Point }
+var p = Point{y=2, x=1}
+var x = p.x
+p.y = 30
+var r = Rect{min=p, max=Point{x=4, y=35}}
+r.max.x = 11
+var a = area(r=r)
+var origin: Point
+var o = origin.x + origin.y
+var y = p.y");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["x"]), "INT(1)");
+        // structs are copied, changing r didn't change p
+        assert_eq!(format!("{:?}", vars["y"]), "INT(30)");
+        assert_eq!(format!("{:?}", vars["a"]), "INT(50)");
+        assert_eq!(format!("{:?}", vars["o"]), "INT(0)");
+
+        let point = "const Point = type { x: i32 y: i32 }\n";
+        let (_, errors) = run(&format!("{}var p = Point{{x=1}}", point));
+        assert_eq!(errors, vec!["E0215"]);
+        let (_, errors) = run(&format!("{}var p = Point{{x=1, y=2, z=3}}", point));
+        assert_eq!(errors, vec!["E0213"]);
+        let (_, errors) = run(&format!("{}var p = Point{{x=1, y=2}}\nvar z = p.z", point));
+        assert_eq!(errors, vec!["E0216"]);
+        let (_, errors) = run("var n = 1\nvar z = n.x");
+        assert_eq!(errors, vec!["E0217"]);
+        let (_, errors) = run("var p = Line{}");
+        assert_eq!(errors, vec!["E0602"]);
+    }
+
+    #[test]
+    fn indexes_slices_and_measures_arrays() {
+        let (vars, errors) = run("fn sum(xs: [i32]) -> i32 {
+    var total = 0
+    for i in 0..xs.len {
+        total = total + xs[i]
+    }
+    total
+}
+var a = [1, 2, 3, 4]
+a[1] = 20
+var second = a[1]
+var n = a.len
+var s = a[1..3]
+var m = s.len
+var t = sum(xs=a)
+var u = sum(xs=s)
+var z: [3]i32
+var w = z[2]");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(format!("{:?}", vars["second"]), "INT(20)");
+        assert_eq!(format!("{:?}", vars["n"]), "INT(4)");
+        assert_eq!(format!("{:?}", vars["m"]), "INT(2)");
+        assert_eq!(format!("{:?}", vars["t"]), "INT(28)");
+        assert_eq!(format!("{:?}", vars["u"]), "INT(23)");
+        assert_eq!(format!("{:?}", vars["w"]), "INT(0)");
+
+        let (_, errors) = run("var a = []");
+        assert_eq!(errors, vec!["E0219"]);
+        let (_, errors) = run("var a = [1, 'b']");
+        assert_eq!(errors, vec!["E0220"]);
+        let (_, errors) = run("var n = 1\nvar x = n[0]");
+        assert_eq!(errors, vec!["E0221"]);
+        let (_, errors) = run("var a = [1, 2]\nvar x = a[2]");
+        assert_eq!(errors, vec!["E0222"]);
+        let (_, errors) = run("var a = [1, 2]\nvar i = 5\nvar x = a[i]");
+        assert_eq!(errors, vec!["E0306"]);
+    }
+    #[test]
+    fn reads_and_writes_through_pointers() {
+        let (vars, errors) = run("fn bump(n: &i32) { *n = *n + 1 }
+fn move_right(p: &Point) { p.x = p.x + 10 }
+fn third(xs: *i32) -> i32 { unsafe { *(xs + 2) } }
+fn make() -> &i32 {
+    var n = 41
+    ret &n
+}
+const Point = type { x: i32 y: i32 }
+var a = 1
+bump(n=&a)
+var bumped = a
+var p = Point{x=1, y=2}
+move_right(p=&p)
+(*&p).y = 5
+var px = p.x
+var py = p.y
+var xs = [1, 2, 3, 4]
+var t = third(xs=&xs[0])
+var e = &xs[1]
+(*e) = 20
+var second = xs[1]
+var maybe: ?&i32 = null
+var none = maybe == null
+maybe = &a
+var got = *maybe.?
+var kept = *make()");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("bumped"), "INT(2)");
+        assert_eq!(value("px"), "INT(11)");
+        assert_eq!(value("py"), "INT(5)");
+        assert_eq!(value("t"), "INT(3)");
+        assert_eq!(value("second"), "INT(20)");
+        assert_eq!(value("none"), "INT(1)");
+        assert_eq!(value("got"), "INT(2)");
+        // the variable moved to the heap when its address was taken, so it outlives the call
+        assert_eq!(value("kept"), "INT(41)");
+
+        let (_, errors) = run("var n = 1\nvar p = &n\nvar q = p + 1");
+        assert_eq!(errors, vec!["E0226"]);
+        let (_, errors) = run("var xs = [1, 2]\nvar p: *i32 = &xs[0]\nvar q = p + 1");
+        assert_eq!(errors, vec!["E0226"]);
+        let (_, errors) = run("var p: *i32 = null");
+        assert_eq!(errors, vec!["E0227"]);
+        let (_, errors) = run("var p: *i32");
+        assert_eq!(errors, vec!["E0227"]);
+        let (_, errors) = run("var p: ?*i32 = null\nvar x = *p");
+        assert_eq!(errors, vec!["E0224"]);
+        let (_, errors) = run("var n = 1\nvar x = *n");
+        assert_eq!(errors, vec!["E0225"]);
+        let (_, errors) = run("var x = &(1 + 2)");
+        assert_eq!(errors, vec!["E0223"]);
+        let (_, errors) = run("var n = 1\nvar x = n.?");
+        assert_eq!(errors, vec!["E0228"]);
+        let (_, errors) = run("var p: ?i32 = null");
+        assert_eq!(errors, vec!["E0108"]);
+        let (_, errors) = run("var p: ?&i32 = null\nvar x = *p.?");
+        assert_eq!(errors, vec!["E0308"]);
+        let (_, errors) = run("var xs = [1, 2]\nvar p: *i32 = &xs[1]\nvar x = unsafe { *(p + 1) }");
+        assert_eq!(errors, vec!["E0306"]);
+    }
+
+    #[test]
+    fn reads_and_writes_fields_through_pointer_fields() {
+        let (vars, errors) = run("const Point = type { x: i32 y: i32 }
+const Line = type { start: &Point end: Point }
+var p = Point{x=1, y=2}
+var l = Line{start=&p, end=Point{x=3, y=4}}
+var before = l.start.x
+l.start.y = 7
+var after = p.y
+var w = &l
+w.start.x = 9
+var through = w.start.x + p.x");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("before"), "INT(1)");
+        assert_eq!(value("after"), "INT(7)");
+        assert_eq!(value("through"), "INT(18)");
+    }
+
+    #[test]
+    fn matches_enum_variants() {
+        let (vars, errors) = run("const Shape = enum {
+    Circle(f64)
+    Rect(f64, f64)
+    Empty
+}
+const Pair = enum { Both(Shape, i32), Neither }
+fn area(s: Shape) -> f64 {
+    match s {
+        Shape::Circle(r) => 3.0f64 * r * r,
+        Shape::Rect(w, h) => w * h,
+        Shape::Empty => 0.0f64,
+    }
+}
+fn describe(p: Pair) -> i32 {
+    match p {
+        Pair::Both(Shape::Empty, n) => n,
+        Pair::Both(Shape::Rect(_, _), 0) => 100,
+        Pair::Both(_, n) => n * 2,
+        Pair::Neither => -1,
+    }
+}
+var circle = area(s=Shape::Circle(2.0))
+var rect = area(s=Shape::Rect(2.0, 3.0))
+var empty = area(s=Shape::Empty)
+var a = describe(p=Pair::Both(Shape::Empty, 7))
+var b = describe(p=Pair::Both(Shape::Rect(1.0, 1.0), 0))
+var c = describe(p=Pair::Both(Shape::Rect(1.0, 1.0), 5))
+var d = describe(p=Pair::Neither)
+var n = 3
+var word = match n { 1 => 10, 3 => 30, _ => 0 }
+var big = match n > 2 { true => 1, false => 0 }
+var zeroed: Shape
+var z = match zeroed { Shape::Circle(r) => r, _ => 1.0f64 }");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let value = |name: &str| format!("{:?}", vars[name]);
+        assert_eq!(value("circle"), "FLOAT(12.0)");
+        assert_eq!(value("rect"), "FLOAT(6.0)");
+        assert_eq!(value("empty"), "FLOAT(0.0)");
+        assert_eq!(value("a"), "INT(7)");
+        assert_eq!(value("b"), "INT(100)");
+        assert_eq!(value("c"), "INT(10)");
+        assert_eq!(value("d"), "INT(-1)");
+        assert_eq!(value("word"), "INT(30)");
+        assert_eq!(value("big"), "INT(1)");
+        // an enum declared without a value holds its first variant
+        assert_eq!(value("z"), "FLOAT(0.0)");
+
+        let shape = "const Shape = enum { Circle(f64) Rect(f64, f64) Empty }\n";
+        let (_, errors) = run(&format!(
+            "{}var s = Shape::Empty\nvar x = match s {{ Shape::Circle(_) => 1, Shape::Empty => 2 }}",
+            shape
+        ));
+        assert_eq!(errors, vec!["E0229"]);
+        let (_, errors) = run("var n = 1\nvar x = match n { 1 => 1, 2 => 2 }");
+        assert_eq!(errors, vec!["E0229"]);
+        let (_, errors) = run(&format!("{}var s = Shape::Square", shape));
+        assert_eq!(errors, vec!["E0230"]);
+        let (_, errors) = run(&format!("{}var s = Shape::Circle(1.0, 2.0)", shape));
+        assert_eq!(errors, vec!["E0231"]);
+        let (_, errors) = run(&format!(
+            "{}var x = match 1 {{ Shape::Empty => 1, _ => 2 }}",
+            shape
+        ));
+        assert_eq!(errors, vec!["E0232"]);
+        let (_, errors) = run("var x = match 'a' { 1 => 1, _ => 2 }");
+        assert_eq!(errors, vec!["E0232"]);
+        let (_, errors) = run("var x = match 1 { 1 => 1, _ => \"one\" }");
+        assert_eq!(errors, vec!["E0233"]);
+        let (_, errors) = run("const E = enum { A A }");
+        assert_eq!(errors, vec!["E0109"]);
+    }
+}
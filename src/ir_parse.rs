@@ -4,22 +4,38 @@ use log::debug;
 
 use crate::{
     ast::{
-        Assign, Binary, Block, Call, Decl, Fun, If, LeftUnary, Number, ParsedAST, Program,
-        Qualifier, Typ,
+        statement, Arg, Arm, Assign, Binary, Block, Call, Decl, For, Fun, If, Index, LeftUnary,
+        LhsAccess, Match, Number, ParsedAST, ParsedASTKind, Pattern, PatternKind, Program,
+        Qualifier, StructLiteral, Typ, Variant, While,
     },
     compiler::CompilerOptions,
-    ir::{IRValue, Instruction, Ref},
+    diagnostic::{Diagnostic, DiagnosticSink},
+    ir::{Comparison, IRValue, Instruction, Ref},
     ir_interpret::IRInterpreter,
-    token::Token,
-    types::Type,
+    parse::describe,
+    token::{Position, Token},
+    typeck::{arithmetic_type, irrefutable},
+    types::{Enums, FnPrimative, Structs, Type},
 };
 
 pub struct IRParser<'a> {
     pub compiler_options: &'a CompilerOptions,
+    pub diagnostics: &'a DiagnosticSink,
     pub counter: usize,
     pub lambda_counter: usize,
     pub block_counter: usize,
     pub locals_counter: usize,
+    // how many loops we are inside, break and continue need at least one
+    pub loop_depth: usize,
+    // the return types of the functions we are inside, the innermost is last
+    pub return_types: Vec<Option<Type>>,
+    // the types of the functions declared with a name, args are matched to their params by
+    // name. anything else that is called is a function value and goes through its closure
+    pub signatures: HashMap<String, FnPrimative>,
+    // the fields of the struct types that have been declared
+    pub structs: Structs,
+    // the variants of the enum types that have been declared
+    pub enums: Enums,
 }
 
 // the following instructions
@@ -36,7 +52,26 @@ pub struct IRParser<'a> {
 //
 //
 
-impl IRParser<'_> {
+impl<'a> IRParser<'a> {
+    pub fn new(
+        compiler_options: &'a CompilerOptions,
+        diagnostics: &'a DiagnosticSink,
+    ) -> IRParser<'a> {
+        IRParser {
+            compiler_options,
+            diagnostics,
+            counter: 0,
+            lambda_counter: 0,
+            block_counter: 0,
+            locals_counter: 0,
+            loop_depth: 0,
+            return_types: vec![],
+            signatures: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        }
+    }
+
     pub fn parse(&mut self, mut ast: Box<ParsedAST>) -> Instruction {
         let mut instructions: Box<Vec<Instruction>> = Box::new(vec![]);
 
@@ -80,35 +115,93 @@ impl IRParser<'_> {
         // instructions: &mut Box<Vec<Instruction>>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        match ast {
-            ParsedAST::PROGRAM(program) => self.gen_program(program, current_block),
-            ParsedAST::STMT(stmt) => self.gen_stmt(stmt, current_block),
-            ParsedAST::BINARY(binary) => self.gen_binary(binary, current_block),
-            ParsedAST::NUMBER(num) => self.gen_num(num, current_block),
-            ParsedAST::STRING(s) => self.gen_string(s, current_block),
-            ParsedAST::DECL(decl) => self.gen_decl(decl, current_block),
-            ParsedAST::IDENTIFIER(identifier) => self.gen_identifier(identifier, current_block),
-            // ParsedAST::DIRECTIVE(directive) => self.type_check_directive(directive),
-            // ParsedAST::PROGRAM(program) => self.type_check_program(program),
-            ParsedAST::BLOCK(block) => self.gen_block(block, current_block),
-            ParsedAST::IF(iff) => self.gen_if(iff, current_block),
-            ParsedAST::CALL(call) => self.gen_call(call, current_block),
-            // ParsedAST::FOR(forr) => self.type_check_for(forr),
-            // ParsedAST::RET(ret) => self.type_check_ret(ret),
-            // ParsedAST::DECL(decl) => self.type_check_decl(decl),
-            ParsedAST::ASSIGN(assign) => self.gen_assign(assign, current_block),
-            ParsedAST::FN(func) => self.gen_func(func, current_block),
-            ParsedAST::TYPE(typ) => self.gen_type(typ, current_block),
-            // ParsedAST::NUMBER(num) => self.type_check_num(num),
-            ParsedAST::LEFT_UNARY(left_unary) => self.gen_left_unary(left_unary, current_block), //self.type_check_binary(binary),
-            // ParsedAST::BINARY(binary) => self.type_check_binary(binary),
-            // ParsedAST::CALL(call) => self.type_check_call(call), // todo
-            // ParsedAST::STRUCT_TYPES_LIST(s) => None, // todo
-            // ParsedAST::LHS_ACCESS(lhs_access) => None, // todo
-            // ParsedAST::GROUP(_) => None, // todo
-            ParsedAST::TYPE(typ) => self.gen_typ(typ, current_block),
-            _ => panic!(),
+        match &mut ast.kind {
+            ParsedASTKind::PROGRAM(program) => self.gen_program(program, current_block),
+            ParsedASTKind::STMT(stmt) => self.gen_stmt(stmt, current_block),
+            ParsedASTKind::BINARY(binary) => self.gen_binary(binary, current_block),
+            ParsedASTKind::NUMBER(num) => self.gen_num(num, current_block),
+            ParsedASTKind::STRING(s) => self.gen_string(s, current_block),
+            ParsedASTKind::CHAR(c) => (None, Some(IRValue::CHAR(*c))),
+            ParsedASTKind::DECL(decl) => self.gen_decl(decl, current_block),
+            ParsedASTKind::IDENTIFIER(identifier) => self.gen_identifier(identifier, current_block),
+            // ParsedASTKind::DIRECTIVE(directive) => self.type_check_directive(directive),
+            // ParsedASTKind::PROGRAM(program) => self.type_check_program(program),
+            ParsedASTKind::BLOCK(block) => self.gen_block(block, current_block),
+            ParsedASTKind::IF(iff) => self.gen_if(iff, current_block),
+            ParsedASTKind::WHILE(w) => self.gen_while(w, current_block),
+            ParsedASTKind::FOR(f) => self.gen_for(f, current_block),
+            ParsedASTKind::BREAK => self.gen_jump(Instruction::BREAK, "break", &ast.position),
+            ParsedASTKind::CONTINUE => {
+                self.gen_jump(Instruction::CONTINUE, "continue", &ast.position)
+            }
+            ParsedASTKind::CALL(call) => self.gen_call(call, current_block),
+            // ParsedASTKind::FOR(forr) => self.type_check_for(forr),
+            ParsedASTKind::RET(value) => self.gen_ret(value, &ast.position, current_block),
+            // ParsedASTKind::DECL(decl) => self.type_check_decl(decl),
+            ParsedASTKind::ASSIGN(assign) => self.gen_assign(assign, current_block),
+            ParsedASTKind::FN(func) => self.gen_func(func, current_block),
+            ParsedASTKind::TYPE(typ) => self.gen_type(typ, current_block),
+            // ParsedASTKind::NUMBER(num) => self.type_check_num(num),
+            ParsedASTKind::LEFT_UNARY(left_unary) => self.gen_left_unary(left_unary, current_block), //self.type_check_binary(binary),
+            // ParsedASTKind::BINARY(binary) => self.type_check_binary(binary),
+            // ParsedASTKind::CALL(call) => self.type_check_call(call), // todo
+            // ParsedASTKind::STRUCT_TYPES_LIST(s) => None, // todo
+            ParsedASTKind::LHS_ACCESS(_) => self.gen_field(ast, current_block),
+            ParsedASTKind::STRUCT_LITERAL(literal) => {
+                self.gen_struct_literal(literal, current_block)
+            }
+            ParsedASTKind::ARRAY_LITERAL(elements) => {
+                self.gen_array_literal(elements, current_block)
+            }
+            ParsedASTKind::INDEX(index) => self.gen_index(index, current_block),
+            ParsedASTKind::VARIANT(variant) => self.gen_variant(variant, current_block),
+            ParsedASTKind::MATCH(m) => self.gen_match(m, current_block),
+            ParsedASTKind::NULL => (None, Some(IRValue::NULL)),
+            ParsedASTKind::UNWRAP(operand) => self.gen_unwrap(operand, current_block),
+            ParsedASTKind::UNSAFE(body) => self.gen_ast(body, current_block),
+            ParsedASTKind::GROUP(group) => self.gen_ast(&mut group.expression, current_block),
+            ParsedASTKind::UNIT => (None, Some(IRValue::UNIT)),
+            // already reported by the parser
+            ParsedASTKind::ERROR => (None, None),
+            _ => {
+                self.diagnostics.emit(Diagnostic::error(
+                    "E0200",
+                    "this expression is not supported yet".to_string(),
+                    ast.position.clone(),
+                ));
+                (None, None)
+            }
+        }
+    }
+
+    // generate an expression for its value. anything it produces that isn't written to the
+    // block yet (i.e. an if or a block) is written first so the value exists before it's used
+    fn gen_value(
+        &mut self,
+        ast: &mut ParsedAST,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> Option<IRValue> {
+        let (instruction, value) = self.gen_ast(ast, current_block);
+        if let Some(instruction) = instruction {
+            self.write_instruction_to_block(instruction, current_block);
+        }
+        value
+    }
+
+    // report an expression that we needed a value from, but which didn't produce one.
+    // if we have already reported an error the missing value is likely caused by that
+    fn expect_value(&self, value: Option<IRValue>, position: &Position) -> Option<IRValue> {
+        if value.is_none() && !self.diagnostics.has_errors() {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0201",
+                    "expression does not produce a value".to_string(),
+                    position.clone(),
+                )
+                .with_label("a value is required here".to_string()),
+            );
         }
+        value
     }
 
     fn gen_program(
@@ -117,6 +210,42 @@ impl IRParser<'_> {
         // instructions: &mut Box<Vec<Instruction>>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
+        // types can be used before they are declared
+        for item in program.body.iter_mut() {
+            let ParsedASTKind::DECL(decl) = &statement(item).kind else {
+                continue;
+            };
+            match decl.value.as_ref().map(|v| &v.kind) {
+                Some(ParsedASTKind::TYPE(typ)) => {
+                    self.structs
+                        .insert(decl.identifier.clone(), typ.fields.clone());
+                }
+                Some(ParsedASTKind::ENUM(variants)) => {
+                    self.enums.insert(decl.identifier.clone(), variants.clone());
+                }
+                _ => {}
+            }
+        }
+        // and functions can be called before they are defined. now we know which names are
+        // enums, the types the declarations use can be resolved
+        for item in program.body.iter_mut() {
+            match &mut statement(item).kind {
+                ParsedASTKind::FN(func) => {
+                    self.resolve_fun_types(func);
+                    self.add_signature(func);
+                }
+                ParsedASTKind::DECL(decl) => match decl.value.as_mut().map(|v| &mut v.kind) {
+                    Some(ParsedASTKind::TYPE(typ)) => {
+                        self.gen_struct_type(&decl.identifier, typ);
+                    }
+                    Some(ParsedASTKind::ENUM(variants)) => {
+                        self.gen_enum_type(&decl.identifier, variants);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
         for item in program.body.iter_mut() {
             let (instruction, _) = self.gen_ast(item, current_block);
             if let Some(instruction_unwrapped) = instruction {
@@ -135,6 +264,8 @@ impl IRParser<'_> {
         // instructions: &mut Box<Vec<Instruction>>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
+        // record where the statement came from so later stages can point back at the source
+        self.write_instruction_to_block(Instruction::LOC(stmt.position.clone()), current_block);
         self.gen_ast(stmt, current_block)
     }
 
@@ -144,70 +275,178 @@ impl IRParser<'_> {
         // instructions: &mut Box<Vec<Instruction>>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        let (_, left_address) = self.gen_ast(&mut binary.left, current_block);
-        let (_, right_address) = self.gen_ast(&mut binary.right, current_block);
-
-        let l: IRValue;
-        match left_address {
-            Some(left) => {
-                l = left;
+        if let Token::AND | Token::OR = binary.op {
+            return self.gen_logical(binary, current_block);
+        }
+        if let Token::PLUS | Token::MINUS = binary.op {
+            if let Some(typ @ (Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_))) =
+                binary.left.typ.clone()
+            {
+                return self.gen_offset(binary, typ, current_block);
             }
-            _ => panic!(),
+        }
+
+        // the type both sides are converted to
+        let typ = arithmetic_type(&binary.left, &binary.right).unwrap_or(Type::I32);
+        let left_address = self.gen_value(&mut binary.left, current_block);
+        let right_address = self.gen_value(&mut binary.right, current_block);
+
+        let Some(l) = self.expect_value(left_address, &binary.left.position) else {
+            return (None, None);
         };
-        let r: IRValue;
-        match right_address {
-            Some(right) => {
-                r = right;
-            }
-            _ => panic!(),
+        let Some(r) = self.expect_value(right_address, &binary.right.position) else {
+            return (None, None);
         };
 
+        if self.compiler_options.optimization > 0 {
+            if let (IRValue::INT(lhs), IRValue::INT(rhs)) = (&l, &r) {
+                if let Some(folded) = fold_int(binary.op, *lhs, *rhs, &typ) {
+                    return (None, Some(IRValue::INT(folded)));
+                }
+            }
+        }
+
         let locals_id = self.locals_counter;
         self.locals_counter += 1;
+        let label = format!("{:?}", locals_id);
 
-        match binary.op {
-            Token::PLUS => {
-                // todo enable optimisation
-
-                let mut should_optimize = true;
-                match l {
-                    IRValue::INT(_) => {}
-                    _ => should_optimize = false,
-                };
-                match r {
-                    IRValue::INT(_) => {}
-                    _ => should_optimize = false,
-                };
-
-                if self.compiler_options.optimization > 0 && should_optimize {
-                    let mut lhs_value = 0;
-                    let mut rhs_value = 0;
-                    match l {
-                        IRValue::INT(i) => lhs_value = i,
-                        _ => todo!("unsupported type for add optimization"),
-                    };
-                    match r {
-                        IRValue::INT(i) => rhs_value = i,
-                        _ => todo!("unsupported type for add optimization"),
-                    };
+        let instruction = match binary.op {
+            Token::PLUS => Instruction::ADD(label.clone(), l, r, typ),
+            Token::MINUS => Instruction::SUB(label.clone(), l, r, typ),
+            Token::STAR => Instruction::MUL(label.clone(), l, r, typ),
+            Token::DIV => Instruction::DIV(label.clone(), l, r, typ),
+            Token::PERCENT => Instruction::MOD(label.clone(), l, r, typ),
+            op => match comparison(op) {
+                Some(comparison) => Instruction::CMP(label.clone(), comparison, l, r, typ),
+                None => {
+                    self.diagnostics.emit(Diagnostic::error(
+                        "E0202",
+                        format!("unsupported operator {}", describe(op)),
+                        binary.left.position.join(&binary.right.position),
+                    ));
+                    return (None, None);
+                }
+            },
+        };
+        self.write_instruction_to_block(instruction, current_block);
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
 
-                    (None, Some(IRValue::INT(lhs_value + rhs_value)))
-                } else {
+    // `p + n` moves a pointer n values along, like c this isn't checked so it needs `unsafe`
+    fn gen_offset(
+        &mut self,
+        binary: &mut Binary,
+        typ: Type,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // anything else is reported by the type checker
+        let Type::POINTER(pointee) = typ else {
+            return (None, None);
+        };
+        let pointer = self.gen_value(&mut binary.left, current_block);
+        let Some(pointer) = self.expect_value(pointer, &binary.left.position) else {
+            return (None, None);
+        };
+        let count_type = binary.right.typ.clone().unwrap_or(Type::I64);
+        let count = self.gen_value(&mut binary.right, current_block);
+        let Some(mut count) = self.expect_value(count, &binary.right.position) else {
+            return (None, None);
+        };
+        if *binary.op == Token::MINUS {
+            count = match count {
+                IRValue::INT(i) => IRValue::INT(i.wrapping_neg()),
+                count => {
+                    let label = self.new_local();
                     self.write_instruction_to_block(
-                        Instruction::ADD(format!("{:?}", locals_id), l, r),
+                        Instruction::NEG(label.clone(), count, count_type),
                         current_block,
                     );
-                    (
-                        // Some(Instruction::ADD(format!("{:?}", locals_id), l, r)),
-                        None,
-                        Some(IRValue::REF(Ref {
-                            value: format!("{:?}", locals_id),
-                        })),
-                    )
+                    IRValue::REF(Ref { value: label })
                 }
-            }
-            _ => panic!(),
+            };
         }
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::OFFSET(label.clone(), pointer, count, *pointee),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // `a && b` only evaluates b if a is true (and `a || b` only if a is false). the
+    // result is kept in a slot that starts as a, which the block for b overwrites
+    fn gen_logical(
+        &mut self,
+        binary: &mut Binary,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let left_type = binary.left.typ.clone().unwrap_or(Type::I32);
+        let right_type = binary.right.typ.clone().unwrap_or(Type::I32);
+        let left_address = self.gen_value(&mut binary.left, current_block);
+        let Some(l) = self.expect_value(left_address, &binary.left.position) else {
+            return (None, None);
+        };
+        let left = self.gen_truthiness(l, left_type, current_block);
+        let slot = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::STACK_VAR(slot.clone(), Type::BOOL, Some(left)),
+            current_block,
+        );
+
+        let mut right_block: Box<Vec<Instruction>> = Box::new(vec![]);
+        let right_address = self.gen_value(&mut binary.right, &mut right_block);
+        let Some(r) = self.expect_value(right_address, &binary.right.position) else {
+            return (None, None);
+        };
+        let right = self.gen_truthiness(r, right_type, &mut right_block);
+        right_block.push(Instruction::STORE(
+            Ref {
+                value: slot.clone(),
+            },
+            right,
+        ));
+        let right_block = Box::new(self.new_block(right_block));
+
+        let condition = IRValue::REF(Ref {
+            value: slot.clone(),
+        });
+        let branch = match binary.op {
+            Token::AND => Instruction::COND_BR(condition, right_block, None),
+            _ => Instruction::COND_BR(
+                condition,
+                Box::new(self.new_block(Box::new(vec![]))),
+                Some(right_block),
+            ),
+        };
+        self.write_instruction_to_block(branch, current_block);
+        (None, Some(IRValue::REF(Ref { value: slot })))
+    }
+
+    // turn a value into 1 or 0
+    fn gen_truthiness(
+        &mut self,
+        value: IRValue,
+        typ: Type,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> IRValue {
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::CMP(label.clone(), Comparison::NE, value, IRValue::INT(0), typ),
+            current_block,
+        );
+        IRValue::REF(Ref { value: label })
+    }
+
+    fn new_local(&mut self) -> String {
+        let label = format!("{:?}", self.locals_counter);
+        self.locals_counter += 1;
+        label
+    }
+
+    fn new_block(&mut self, instructions: Box<Vec<Instruction>>) -> Instruction {
+        let block_id = self.block_counter;
+        self.block_counter += 1;
+        Instruction::BLOCK(format!("{:?}", block_id), instructions)
     }
 
     fn gen_num(
@@ -218,8 +457,9 @@ impl IRParser<'_> {
     ) -> (Option<Instruction>, Option<IRValue>) {
         self.counter += 1;
         match num {
-            Number::INTEGER(i) => (None, Some(IRValue::INT(i.clone()))),
-            Number::FLOAT(f) => (None, Some(IRValue::FLOAT(f.clone()))),
+            // u64 values above i64::MAX keep their bits
+            Number::INTEGER(i, _) => (None, Some(IRValue::INT(*i as i64))),
+            Number::FLOAT(f, _) => (None, Some(IRValue::FLOAT(*f))),
         }
     }
 
@@ -238,17 +478,41 @@ impl IRParser<'_> {
         // instructions: &mut Box<Vec<Instruction>>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        // todo check if we are dealing with a struct!
+        decl.typ = decl.typ.as_ref().map(|typ| self.resolve_type(typ));
+        match decl.value.as_mut().map(|v| &mut v.kind) {
+            // `const Point = type { x: i32 y: i32 }` declares a struct type named after the decl
+            Some(ParsedASTKind::TYPE(typ)) => return self.gen_struct_type(&decl.identifier, typ),
+            // and `const Shape = enum { Circle(f64) Empty }` an enum type
+            Some(ParsedASTKind::ENUM(variants)) => {
+                return self.gen_enum_type(&decl.identifier, variants)
+            }
+            Some(ParsedASTKind::FN(func)) => self.resolve_fun_types(func),
+            _ => {}
+        }
+        let value_type = decl.value.as_ref().and_then(|value| value.typ.clone());
+        let typ = decl.typ.clone().or_else(|| value_type.clone());
 
         match &decl.typ {
-            Some(Type::STRUCT(i)) => (
-                Some(Instruction::STACK_VAR(
-                    decl.identifier.clone(),
-                    decl.typ.clone().unwrap(),
+            Some(Type::STRUCT(name)) => {
+                // reported by the type checker
+                if !self.structs.contains_key(name) {
+                    return (None, None);
+                }
+                // without a value the fields are zeroed
+                let mut value = None;
+                if let Some(ast) = decl.value.as_mut() {
+                    value = self.gen_value(ast, current_block);
+                    value = self.expect_value(value, &ast.position);
+                }
+                (
+                    Some(Instruction::STACK_VAR(
+                        decl.identifier.clone(),
+                        Type::STRUCT(name.to_string()),
+                        value,
+                    )),
                     None,
-                )),
-                None,
-            ),
+                )
+            }
             Some(Type::TYPE) => {
                 // todo figure out the const type qualifier thingy here!
                 match decl.qualifier {
@@ -259,7 +523,7 @@ impl IRParser<'_> {
                 }
 
                 if let Some(value) = decl.value.as_mut() {
-                    let (_, data) = self.gen_ast(value, current_block);
+                    self.gen_value(value, current_block);
                 }
                 // we then need to stack the var & give it a value
 
@@ -280,15 +544,16 @@ impl IRParser<'_> {
                 // first generate the decl value
                 let mut instruction_data = None;
                 if let Some(value) = decl.value.as_mut() {
-                    let (_, data) = self.gen_ast(value, current_block);
-                    instruction_data = data;
+                    let data = self.gen_value(value, current_block);
+                    instruction_data =
+                        data.map(|data| self.coerce(data, value_type, &decl.typ, current_block));
                 }
                 self.counter += 1;
                 (
                     Some(Instruction::STACK_VAR(
                         decl.identifier.clone(),
-                        // todo get the type!
-                        Type::I32,
+                        // the type checker couldn't work it out
+                        typ.unwrap_or(Type::I32),
                         instruction_data,
                     )),
                     None,
@@ -305,23 +570,19 @@ impl IRParser<'_> {
         let block_id = self.block_counter;
         self.block_counter += 1;
         let mut new_block_instructions: Box<Vec<Instruction>> = Box::new(vec![]);
+        // the block evaluates to its last statement
+        let mut value = None;
         for mut instruction in &mut block.body {
-            let (instruction, _) = self.gen_ast(&mut instruction, &mut new_block_instructions);
+            let (instruction, statement_value) =
+                self.gen_ast(&mut instruction, &mut new_block_instructions);
             if let Some(instruction_unwrapped) = instruction {
                 new_block_instructions.push(instruction_unwrapped);
             }
+            value = statement_value;
         }
         let mut new_block = Instruction::BLOCK(format!("{:?}", block_id), new_block_instructions);
         // self.write_instruction_to_block(new_block, current_block);
-        (Some(new_block), None)
-    }
-
-    fn gen_typ(
-        &mut self,
-        typ: &mut Typ,
-        current_block: &mut Box<Vec<Instruction>>,
-    ) -> (Option<Instruction>, Option<IRValue>) {
-        (None, None)
+        (Some(new_block), value)
     }
 
     fn gen_left_unary(
@@ -329,18 +590,16 @@ impl IRParser<'_> {
         left_unary: &mut LeftUnary,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
+        let negate = matches!(left_unary, LeftUnary::NEG(_));
         match left_unary {
             LeftUnary::COMP(expr) => {
                 // todo
-                let mut ir_executor = IRInterpreter {
-                    compiler_options: self.compiler_options,
-                    counter: 0,
-                    variables_map: HashMap::new(),
-                };
+                let mut ir_executor = IRInterpreter::new(self.compiler_options, self.diagnostics);
+                ir_executor.position = Some(expr.position.clone());
 
                 // todo we need to capture this all in a new block
                 let mut comptime_block: Box<Vec<Instruction>> = Box::new(vec![]);
-                self.gen_ast(expr, &mut comptime_block);
+                self.gen_value(expr, &mut comptime_block);
 
                 let comptime_instruction = Instruction::PROGRAM(comptime_block);
                 let result = ir_executor.execute(&comptime_instruction);
@@ -363,12 +622,119 @@ impl IRParser<'_> {
                 //     return (None, Some(data_unpacked));
                 // }
             }
+            LeftUnary::ADDRESS(operand) => return self.gen_address(operand, current_block),
+            LeftUnary::DEREF(operand) => {
+                let Some(pointee) = pointee_type(operand) else {
+                    return (None, None);
+                };
+                let pointer = self.gen_value(operand, current_block);
+                let Some(pointer) = self.expect_value(pointer, &operand.position) else {
+                    return (None, None);
+                };
+                let label = self.new_local();
+                self.write_instruction_to_block(
+                    Instruction::DEREF(label.clone(), pointer, pointee),
+                    current_block,
+                );
+                return (None, Some(IRValue::REF(Ref { value: label })));
+            }
+            LeftUnary::NEG(operand) | LeftUnary::NOT(operand) => {
+                let typ = operand.typ.clone().unwrap_or(Type::I32);
+                let value = self.gen_value(operand, current_block);
+                let Some(value) = self.expect_value(value, &operand.position) else {
+                    return (None, None);
+                };
+                match value {
+                    // negative literals are folded so they can be used like any other literal
+                    IRValue::INT(i) if negate => {
+                        return (None, Some(IRValue::INT(i.wrapping_neg())))
+                    }
+                    IRValue::FLOAT(f) if negate => return (None, Some(IRValue::FLOAT(-f))),
+                    _ => {}
+                }
+                let label = self.new_local();
+                let instruction = if negate {
+                    Instruction::NEG(label.clone(), value, typ)
+                } else {
+                    Instruction::NOT(label.clone(), value)
+                };
+                self.write_instruction_to_block(instruction, current_block);
+                (None, Some(IRValue::REF(Ref { value: label })))
+            }
         }
+    }
 
-        (None, None)
+    // `&x` or `&a[i]`, only variables (and their elements) live somewhere with an address
+    fn gen_address(
+        &mut self,
+        operand: &mut ParsedAST,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let label = self.new_local();
+        let typ = operand.typ.clone();
+        let instruction = match &mut operand.kind {
+            ParsedASTKind::IDENTIFIER(variable) => {
+                let typ = typ.unwrap_or(Type::I32);
+                let variable = Ref {
+                    value: variable.to_string(),
+                };
+                Instruction::ADDRESS(label.clone(), variable, None, typ)
+            }
+            ParsedASTKind::INDEX(index)
+                if matches!(index.left.kind, ParsedASTKind::IDENTIFIER(_))
+                    && !matches!(
+                        index.index.kind,
+                        ParsedASTKind::BINARY(Binary {
+                            op: Token::DOT_DOT,
+                            ..
+                        })
+                    ) =>
+            {
+                let Some(element) = typ else {
+                    return (None, None);
+                };
+                let ParsedASTKind::IDENTIFIER(variable) = &index.left.kind else {
+                    return (None, None);
+                };
+                let variable = Ref {
+                    value: variable.to_string(),
+                };
+                let i = self.gen_value(&mut index.index, current_block);
+                let Some(i) = self.expect_value(i, &index.index.position) else {
+                    return (None, None);
+                };
+                Instruction::ADDRESS(label.clone(), variable, Some(i), element)
+            }
+            // reported by the type checker
+            _ => return (None, None),
+        };
+        self.write_instruction_to_block(instruction, current_block);
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // `p.?`, the pointer in an optional. it is checked against null when the program runs
+    fn gen_unwrap(
+        &mut self,
+        operand: &mut ParsedAST,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // reported by the type checker
+        if operand
+            .typ
+            .as_ref()
+            .is_some_and(|typ| !matches!(typ, Type::OPTIONAL(_)))
+        {
+            return (None, None);
+        }
+        let value = self.gen_value(operand, current_block);
+        let Some(value) = self.expect_value(value, &operand.position) else {
+            return (None, None);
+        };
+        let label = self.new_local();
+        self.write_instruction_to_block(Instruction::UNWRAP(label.clone(), value), current_block);
+        (None, Some(IRValue::REF(Ref { value: label })))
     }
 
-    // todo we need to do name resolving here!!!
     fn gen_type(
         &mut self,
         typ: &mut Typ,
@@ -389,96 +755,860 @@ impl IRParser<'_> {
         )
     }
 
-    fn gen_func(
+    fn gen_struct_type(
         &mut self,
-        func: &mut Fun,
-        current_block: &mut Box<Vec<Instruction>>,
+        name: &String,
+        typ: &mut Typ,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        let mut name: String;
-        if func.identifier.is_some() {
-            name = func.identifier.as_mut().unwrap().to_string();
-        } else {
-            name = format!("{}_lambda", self.lambda_counter);
-            self.lambda_counter += 1;
+        for (_, field) in typ.fields.iter_mut() {
+            *field = self.resolve_type(field);
         }
+        self.structs.insert(name.to_string(), typ.fields.clone());
+        let types = typ.fields.iter().map(|(_, t)| t.clone()).collect();
+        (Some(Instruction::TYPE(name.to_string(), types)), None)
+    }
 
-        // todo for some expressions such as calls we dont return the instruction, i think we should return
-        // the instructions by default and let the blocks generate them?
-        let (i, _) = self.gen_ast(&mut func.body, current_block);
+    fn gen_enum_type(
+        &mut self,
+        name: &String,
+        variants: &mut Vec<(String, Vec<Type>)>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        for (_, payload) in variants.iter_mut() {
+            for typ in payload.iter_mut() {
+                *typ = self.resolve_type(typ);
+            }
+        }
+        self.enums.insert(name.to_string(), variants.clone());
+        (
+            Some(Instruction::ENUM(name.to_string(), variants.clone())),
+            None,
+        )
+    }
 
-        let mut params: Vec<Type> = vec![];
-        for p in func.params.iter() {
-            params.push(p.typ.clone().unwrap());
+    // named types are parsed as structs, the ones that name an enum are enums
+    fn resolve_type(&self, typ: &Type) -> Type {
+        typ.resolve(&self.enums)
+    }
+
+    fn resolve_fun_types(&self, func: &mut Fun) {
+        for param in func.params.iter_mut() {
+            param.typ = param.typ.as_ref().map(|typ| self.resolve_type(typ));
         }
+        func.return_type = func.return_type.as_ref().map(|typ| self.resolve_type(typ));
+    }
+
+    // the index of a variant and the types of its payload
+    fn find_variant(&self, enum_name: &str, variant: &str) -> Option<(usize, Vec<Type>)> {
+        let variants = self.enums.get(enum_name)?;
+        let tag = variants.iter().position(|(name, _)| name == variant)?;
+        Some((tag, variants[tag].1.clone()))
+    }
 
-        let func_instruction = Instruction::FUNC(name, params, Box::new(i.unwrap()));
+    // `Shape::Circle(2.0)`
+    fn gen_variant(
+        &mut self,
+        variant: &mut Variant,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // unknown variants are reported by the type checker
+        let Some((tag, payload_types)) = self.find_variant(&variant.enum_name, &variant.variant)
+        else {
+            return (None, None);
+        };
+        if payload_types.len() != variant.payload.len() {
+            return (None, None);
+        }
+        let mut values: Vec<IRValue> = vec![];
+        for (i, value) in variant.payload.iter_mut().enumerate() {
+            let payload_type = Some(payload_types[i].clone());
+            let value_type = value.typ.clone();
+            let data = self.gen_value(value, current_block);
+            let Some(data) = self.expect_value(data, &value.position) else {
+                return (None, None);
+            };
+            values.push(self.coerce(data, value_type, &payload_type, current_block));
+        }
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::VARIANT(label.clone(), variant.enum_name.clone(), tag, values),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
 
-        (Some(func_instruction), None)
+    // a match becomes a chain of ifs, one for each arm. the last arm doesn't need checking,
+    // as the patterns must cover every value
+    fn gen_match(
+        &mut self,
+        m: &mut Match,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // if we don't know the type of the value, the patterns can tell us
+        let typ = m.scrutinee.typ.clone().or_else(|| {
+            m.arms.iter().find_map(|arm| match &arm.pattern.kind {
+                PatternKind::VARIANT(enum_name, _, _) => Some(Type::ENUM(enum_name.clone())),
+                _ => None,
+            })
+        });
+        let value = self.gen_value(&mut m.scrutinee, current_block);
+        let Some(value) = self.expect_value(value, &m.scrutinee.position) else {
+            return (None, None);
+        };
+        self.gen_arms(&mut m.arms, &value, &typ, current_block)
     }
 
-    fn gen_assign(
+    // the if for the first arm, which goes on to the rest if it doesn't match
+    fn gen_arms(
         &mut self,
-        assign: &mut Assign,
+        arms: &mut [Arm],
+        value: &IRValue,
+        typ: &Option<Type>,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        // new instruction?
+        let Some((arm, rest)) = arms.split_first_mut() else {
+            return (None, None);
+        };
+        let test = match rest.is_empty() {
+            true => None,
+            false => self.gen_pattern_test(&mut arm.pattern, value, current_block),
+        };
+
+        // the bindings are only in scope in the arm
+        let mut body_instructions: Box<Vec<Instruction>> = Box::new(vec![]);
+        self.gen_bindings(&arm.pattern, value, typ, &mut body_instructions);
+        let body_value = self.gen_value(&mut arm.body, &mut body_instructions);
+        let body = self.new_block(body_instructions);
+        // an arm that matches anything is taken if we get to it
+        let Some(test) = test else {
+            return (Some(body), body_value);
+        };
+
+        let mut else_instructions: Box<Vec<Instruction>> = Box::new(vec![]);
+        let (rest_instruction, rest_value) =
+            self.gen_arms(rest, value, typ, &mut else_instructions);
+        if let Some(rest_instruction) = rest_instruction {
+            else_instructions.push(rest_instruction);
+        }
+        let else_body = self.new_block(else_instructions);
+        // every arm gives a value, so the match does too
+        if let (Some(body_value), Some(else_value)) = (body_value, rest_value) {
+            let label = self.new_local();
+            return (
+                Some(Instruction::IF(
+                    label.clone(),
+                    test,
+                    Box::new(body),
+                    body_value,
+                    Box::new(else_body),
+                    else_value,
+                )),
+                Some(IRValue::REF(Ref { value: label })),
+            );
+        }
+        (
+            Some(Instruction::COND_BR(
+                test,
+                Box::new(body),
+                Some(Box::new(else_body)),
+            )),
+            None,
+        )
+    }
 
-        match assign.lhs.as_mut() {
-            ParsedAST::IDENTIFIER(i) => {
-                let (_, value) = self.gen_ast(&mut assign.rhs, current_block);
+    // a bool that is true if the value matches the pattern, none if it matches anything. the
+    // payload of a variant is only read once we know the value holds that variant
+    fn gen_pattern_test(
+        &mut self,
+        pattern: &mut Pattern,
+        value: &IRValue,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> Option<IRValue> {
+        match &mut pattern.kind {
+            PatternKind::WILDCARD | PatternKind::BINDING(_) => None,
+            PatternKind::LITERAL(literal) => {
+                let typ = literal.typ.clone().unwrap_or(Type::I32);
+                let literal = self.gen_value(literal, current_block)?;
+                let label = self.new_local();
                 self.write_instruction_to_block(
-                    Instruction::STORE(
+                    Instruction::CMP(label.clone(), Comparison::EQ, value.clone(), literal, typ),
+                    current_block,
+                );
+                Some(IRValue::REF(Ref { value: label }))
+            }
+            PatternKind::VARIANT(enum_name, variant, payload) => {
+                let (tag, _) = self.find_variant(enum_name, variant)?;
+                let tag_label = self.new_local();
+                self.write_instruction_to_block(
+                    Instruction::TAG(tag_label.clone(), value.clone()),
+                    current_block,
+                );
+                let label = self.new_local();
+                self.write_instruction_to_block(
+                    Instruction::CMP(
+                        label.clone(),
+                        Comparison::EQ,
+                        IRValue::REF(Ref { value: tag_label }),
+                        IRValue::INT(tag as i64),
+                        Type::U32,
+                    ),
+                    current_block,
+                );
+                if payload.iter().all(irrefutable) {
+                    return Some(IRValue::REF(Ref { value: label }));
+                }
+                // each part of the payload is only checked if everything before it matched
+                let slot = self.new_local();
+                self.write_instruction_to_block(
+                    Instruction::STACK_VAR(
+                        slot.clone(),
+                        Type::BOOL,
+                        Some(IRValue::REF(Ref { value: label })),
+                    ),
+                    current_block,
+                );
+                for (i, part) in payload.iter_mut().enumerate() {
+                    if irrefutable(part) {
+                        continue;
+                    }
+                    let mut part_block: Box<Vec<Instruction>> = Box::new(vec![]);
+                    let part_label = self.new_local();
+                    part_block.push(Instruction::PAYLOAD(
+                        part_label.clone(),
+                        value.clone(),
+                        enum_name.clone(),
+                        tag,
+                        i,
+                    ));
+                    let part_value = IRValue::REF(Ref { value: part_label });
+                    let matched = self.gen_pattern_test(part, &part_value, &mut part_block)?;
+                    part_block.push(Instruction::STORE(
                         Ref {
-                            value: i.to_string(),
+                            value: slot.clone(),
                         },
-                        value.unwrap(),
+                        matched,
+                    ));
+                    let part_block = Box::new(self.new_block(part_block));
+                    let condition = IRValue::REF(Ref {
+                        value: slot.clone(),
+                    });
+                    self.write_instruction_to_block(
+                        Instruction::COND_BR(condition, part_block, None),
+                        current_block,
+                    );
+                }
+                Some(IRValue::REF(Ref { value: slot }))
+            }
+        }
+    }
+
+    // declare the variables a pattern binds, the value is known to match it
+    fn gen_bindings(
+        &mut self,
+        pattern: &Pattern,
+        value: &IRValue,
+        typ: &Option<Type>,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) {
+        match &pattern.kind {
+            PatternKind::BINDING(name) => {
+                self.write_instruction_to_block(
+                    Instruction::STACK_VAR(
+                        name.clone(),
+                        typ.clone().unwrap_or(Type::I32),
+                        Some(value.clone()),
                     ),
                     current_block,
                 );
             }
-            _ => todo!(),
+            PatternKind::VARIANT(enum_name, variant, payload) => {
+                let Some((tag, types)) = self.find_variant(enum_name, variant) else {
+                    return;
+                };
+                for (i, (part, typ)) in payload.iter().zip(types).enumerate() {
+                    let mut names = vec![];
+                    pattern_bindings(part, &mut names);
+                    if names.is_empty() {
+                        continue;
+                    }
+                    let label = self.new_local();
+                    self.write_instruction_to_block(
+                        Instruction::PAYLOAD(
+                            label.clone(),
+                            value.clone(),
+                            enum_name.clone(),
+                            tag,
+                            i,
+                        ),
+                        current_block,
+                    );
+                    let part_value = IRValue::REF(Ref { value: label });
+                    self.gen_bindings(part, &part_value, &Some(typ), current_block);
+                }
+            }
+            PatternKind::WILDCARD | PatternKind::LITERAL(_) => {}
         }
-        (None, None)
     }
 
-    fn gen_call(
+    fn gen_struct_literal(
         &mut self,
-        call: &mut Call,
+        literal: &mut StructLiteral,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        // todo a call should just be a string reference to a function
-        let mut f: String;
+        // unknown types and fields are reported by the type checker
+        let Some(fields) = self.structs.get(&literal.name) else {
+            return (None, None);
+        };
+        let names: Vec<String> = fields.iter().map(|(name, _)| name.clone()).collect();
+        let field_types: Vec<Type> = fields.iter().map(|(_, typ)| typ.clone()).collect();
+        let Some(order) = order_named(&names, &literal.fields) else {
+            return (None, None);
+        };
 
-        match call.callee.as_ref() {
-            ParsedAST::IDENTIFIER(i) => {
-                f = i.to_string();
-            }
-            _ => todo!(),
+        // like args, the fields are worked out in the order they are written
+        let mut values: Vec<IRValue> = vec![];
+        for field in literal.fields.iter_mut() {
+            let value = self.gen_value(&mut field.value, current_block);
+            let Some(value) = self.expect_value(value, &field.value.position) else {
+                return (None, None);
+            };
+            values.push(value);
         }
-
-        // // let (callee_instruction, callee_data) = self.gen_ast(&mut call.callee, current_block);
-        // let mut first_arg = call.args[0].borrow_mut();
-        // let (first_arg_instruction, first_arg_data) = self.gen_ast(first_arg, current_block);
-
-        let locals_id = self.locals_counter;
-        self.locals_counter += 1;
-
-        let mut args: Vec<IRValue> = vec![];
-
-        for arg in call.args.iter_mut() {
-            let (instr, val) = self.gen_ast(arg, current_block);
-            args.push(val.unwrap());
+        let mut ordered = vec![];
+        for (field, i) in order.iter().enumerate() {
+            let field_type = Some(field_types[field].clone());
+            let value = self.coerce(
+                values[*i].clone(),
+                literal.fields[*i].value.typ.clone(),
+                &field_type,
+                current_block,
+            );
+            ordered.push(value);
         }
-
+        let values = ordered;
+        let label = self.new_local();
         self.write_instruction_to_block(
-            Instruction::CALL(
-                locals_id.to_string(),
-                // callee_data.expect("expected callee data"),
-                f.to_string(),
-                args,
-            ),
+            Instruction::STRUCT(label.clone(), literal.name.clone(), values),
             current_block,
         );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // reading `a.b.c`
+    fn gen_field(
+        &mut self,
+        ast: &mut ParsedAST,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        if let ParsedASTKind::LHS_ACCESS(access) = &mut ast.kind {
+            if self.is_len(access) {
+                return self.gen_len(access, current_block);
+            }
+        }
+        let Some((path, _)) = self.field_path(ast) else {
+            return (None, None);
+        };
+        let base = access_base(ast);
+        let pointee = base.typ.as_ref().and_then(|typ| typ.pointee().cloned());
+        let value = self.gen_value(base, current_block);
+        let Some(mut value) = self.expect_value(value, &base.position) else {
+            return (None, None);
+        };
+        // the fields of a struct can be read through a pointer to it
+        if let Some(pointee) = pointee {
+            let label = self.new_local();
+            self.write_instruction_to_block(
+                Instruction::DEREF(label.clone(), value, pointee),
+                current_block,
+            );
+            value = IRValue::REF(Ref { value: label });
+        }
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::FIELD(label.clone(), value, path),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // `.len` of an array or slice
+    fn is_len(&self, access: &LhsAccess) -> bool {
+        access.right == "len" && matches!(access.left.typ, Some(Type::ARRAY(..) | Type::SLICE(_)))
+    }
+
+    fn gen_len(
+        &mut self,
+        access: &mut LhsAccess,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // the length of an array is part of its type
+        if let Some(Type::ARRAY(_, len)) = access.left.typ {
+            return (None, Some(IRValue::INT(len as i64)));
+        }
+        let value = self.gen_value(&mut access.left, current_block);
+        let Some(value) = self.expect_value(value, &access.left.position) else {
+            return (None, None);
+        };
+        let label = self.new_local();
+        self.write_instruction_to_block(Instruction::LEN(label.clone(), value), current_block);
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    fn gen_array_literal(
+        &mut self,
+        elements: &mut Vec<ParsedAST>,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // reported by the type checker
+        if elements.is_empty() {
+            return (None, None);
+        }
+        let mut values: Vec<IRValue> = vec![];
+        for element in elements.iter_mut() {
+            let value = self.gen_value(element, current_block);
+            let Some(value) = self.expect_value(value, &element.position) else {
+                return (None, None);
+            };
+            values.push(value);
+        }
+        let label = self.new_local();
+        self.write_instruction_to_block(Instruction::ARRAY(label.clone(), values), current_block);
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // `a[i]`, or `a[start..end]` for a slice
+    fn gen_index(
+        &mut self,
+        index: &mut Index,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let Some(element) = element_type(&index.left) else {
+            return (None, None);
+        };
+        let base = self.gen_value(&mut index.left, current_block);
+        let Some(base) = self.expect_value(base, &index.left.position) else {
+            return (None, None);
+        };
+
+        let label = self.new_local();
+        if let ParsedASTKind::BINARY(Binary {
+            left,
+            op: Token::DOT_DOT,
+            right,
+        }) = &mut index.index.kind
+        {
+            let start = self.gen_value(left, current_block);
+            let Some(start) = self.expect_value(start, &left.position) else {
+                return (None, None);
+            };
+            let end = self.gen_value(right, current_block);
+            let Some(end) = self.expect_value(end, &right.position) else {
+                return (None, None);
+            };
+            self.write_instruction_to_block(
+                Instruction::SLICE(label.clone(), base, start, end, element),
+                current_block,
+            );
+            return (None, Some(IRValue::REF(Ref { value: label })));
+        }
+
+        let value = self.gen_value(&mut index.index, current_block);
+        let Some(value) = self.expect_value(value, &index.index.position) else {
+            return (None, None);
+        };
+        self.write_instruction_to_block(
+            Instruction::INDEX(label.clone(), base, value, element),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // an array can be given where a slice of its elements is expected, it becomes a slice of
+    // the whole array
+    fn coerce(
+        &mut self,
+        value: IRValue,
+        from: Option<Type>,
+        to: &Option<Type>,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> IRValue {
+        let (Some(Type::ARRAY(element, len)), Some(Type::SLICE(to_element))) = (from, to) else {
+            return value;
+        };
+        if element != *to_element {
+            return value;
+        }
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::SLICE(
+                label.clone(),
+                value,
+                IRValue::INT(0),
+                IRValue::INT(len as i64),
+                *element,
+            ),
+            current_block,
+        );
+        IRValue::REF(Ref { value: label })
+    }
+
+    // the index of each field along a chain of accesses like `a.b.c`, and the type of the
+    // last one. fields that don't exist are reported by the type checker
+    fn field_path(&self, ast: &ParsedAST) -> Option<(Vec<usize>, Type)> {
+        let ParsedASTKind::LHS_ACCESS(access) = &ast.kind else {
+            panic!("expected a field access");
+        };
+        let (mut path, typ) = match &access.left.kind {
+            ParsedASTKind::LHS_ACCESS(_) if pointee_type(&access.left).is_none() => {
+                self.field_path(&access.left)?
+            }
+            // a pointer to a struct has the fields of the struct, the path starts again from it
+            _ => {
+                let typ = access.left.typ.clone()?;
+                (vec![], typ.pointee().cloned().unwrap_or(typ))
+            }
+        };
+        let Type::STRUCT(name) = typ else {
+            return None;
+        };
+        let fields = self.structs.get(&name)?;
+        let index = fields
+            .iter()
+            .position(|(field, _)| *field == access.right)?;
+        path.push(index);
+        Some((path, fields[index].1.clone()))
+    }
+
+    fn add_signature(&mut self, func: &Fun) {
+        if let (Some(name), Type::FN(function)) = (&func.identifier, func.fn_type()) {
+            self.signatures.insert(name.to_string(), function);
+        }
+    }
+
+    fn gen_func(
+        &mut self,
+        func: &mut Fun,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        self.resolve_fun_types(func);
+        // so the body can call the function
+        self.add_signature(func);
+
+        let mut name: String;
+        if func.identifier.is_some() {
+            name = func.identifier.as_mut().unwrap().to_string();
+        } else {
+            name = format!("{}_lambda", self.lambda_counter);
+            self.lambda_counter += 1;
+        }
+
+        // todo for some expressions such as calls we dont return the instruction, i think we should return
+        // the instructions by default and let the blocks generate them?
+        // loops outside the function can't be broken out of from inside it
+        let loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        self.return_types.push(func.return_type.clone());
+        // params that are functions are called through their value, even if they have the
+        // same name as a function
+        let signatures = self.signatures.clone();
+        for p in func.params.iter() {
+            self.signatures.remove(&p.identifier);
+        }
+        let (i, value) = self.gen_ast(&mut func.body, current_block);
+        self.signatures = signatures;
+        self.return_types.pop();
+        self.loop_depth = loop_depth;
+        let Some(mut body) = i else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0205",
+                    "function body must be a block".to_string(),
+                    func.body.position.clone(),
+                )
+                .with_label("expected `{ ... }`".to_string()),
+            );
+            return (None, None);
+        };
+
+        // the last expression is returned if the function has a return type
+        if let (Some(_), Some(value), Instruction::BLOCK(_, instructions)) =
+            (&func.return_type, value, &mut body)
+        {
+            instructions.push(Instruction::RET(Some(value)));
+        }
+
+        let mut params: Vec<(String, Type)> = vec![];
+        for p in func.params.iter() {
+            params.push((p.identifier.clone(), p.typ.clone().unwrap()));
+        }
+
+        if func.identifier.is_some() {
+            let func_instruction =
+                Instruction::FUNC(name, None, params, func.return_type.clone(), Box::new(body));
+            return (Some(func_instruction), None);
+        }
+
+        // a lambda becomes a function that is passed the variables it uses from where it is
+        // defined, and a closure holding the function and the values of those variables
+        let captures = self.captures(func);
+        let values = captures
+            .iter()
+            .map(|capture| {
+                IRValue::REF(Ref {
+                    value: capture.to_string(),
+                })
+            })
+            .collect();
+        self.write_instruction_to_block(
+            Instruction::FUNC(
+                name.clone(),
+                Some(captures),
+                params,
+                func.return_type.clone(),
+                Box::new(body),
+            ),
+            current_block,
+        );
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::CLOSURE(label.clone(), name, values),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+
+    // the variables a lambda uses that aren't its own. functions and intrinsics aren't
+    // variables so they aren't captured
+    fn captures(&self, func: &Fun) -> Vec<String> {
+        let mut bound: Vec<String> = func.params.iter().map(|p| p.identifier.clone()).collect();
+        let mut free: Vec<String> = vec![];
+        free_variables(&func.body, &mut bound, &mut free);
+        free.retain(|name| {
+            !self.signatures.contains_key(name) && !INTRINSICS.contains(&name.as_str())
+        });
+        free
+    }
+
+    fn gen_assign(
+        &mut self,
+        assign: &mut Assign,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // new instruction?
+
+        // `*p = 1`, or `(*p) = 1`
+        if let Some(pointer) = deref_operand(&mut assign.lhs) {
+            let Some(pointee) = pointee_type(pointer) else {
+                return (None, None);
+            };
+            let pointer = self.gen_value(pointer, current_block);
+            let Some(pointer) = self.expect_value(pointer, &assign.lhs.position) else {
+                return (None, None);
+            };
+            let value = self.gen_value(&mut assign.rhs, current_block);
+            let Some(value) = self.expect_value(value, &assign.rhs.position) else {
+                return (None, None);
+            };
+            self.write_instruction_to_block(
+                Instruction::STORE_DEREF(pointer, vec![], value, pointee),
+                current_block,
+            );
+            return (None, None);
+        }
+        match &mut assign.lhs.kind {
+            ParsedASTKind::IDENTIFIER(i) => {
+                let value = self.gen_value(&mut assign.rhs, current_block);
+                let Some(value) = self.expect_value(value, &assign.rhs.position) else {
+                    return (None, None);
+                };
+                self.write_instruction_to_block(
+                    Instruction::STORE(
+                        Ref {
+                            value: i.to_string(),
+                        },
+                        value,
+                    ),
+                    current_block,
+                );
+            }
+            ParsedASTKind::INDEX(index) => {
+                let Some(element) = element_type(&index.left) else {
+                    return (None, None);
+                };
+                let label = match (&index.left.kind, &index.index.kind) {
+                    (
+                        _,
+                        ParsedASTKind::BINARY(Binary {
+                            op: Token::DOT_DOT, ..
+                        }),
+                    ) => "a slice cannot be assigned to",
+                    (ParsedASTKind::IDENTIFIER(_), _) => "",
+                    _ => "only elements of variables can be assigned to",
+                };
+                if !label.is_empty() {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0203",
+                            "invalid left hand side of assignment".to_string(),
+                            assign.lhs.position.clone(),
+                        )
+                        .with_label(label.to_string()),
+                    );
+                    return (None, None);
+                }
+                let ParsedASTKind::IDENTIFIER(variable) = &index.left.kind else {
+                    return (None, None);
+                };
+                let variable = Ref {
+                    value: variable.to_string(),
+                };
+                let i = self.gen_value(&mut index.index, current_block);
+                let Some(i) = self.expect_value(i, &index.index.position) else {
+                    return (None, None);
+                };
+                let value = self.gen_value(&mut assign.rhs, current_block);
+                let Some(value) = self.expect_value(value, &assign.rhs.position) else {
+                    return (None, None);
+                };
+                self.write_instruction_to_block(
+                    Instruction::STORE_INDEX(variable, i, value, element),
+                    current_block,
+                );
+            }
+            ParsedASTKind::LHS_ACCESS(_) => {
+                let Some((path, _)) = self.field_path(&assign.lhs) else {
+                    return (None, None);
+                };
+                let base = access_base(&mut assign.lhs);
+                // `p.x = 1` and `(*p).x = 1` store through the pointer
+                let base_type = base.typ.clone();
+                let (pointer, pointee) = match base_type.as_ref().and_then(|typ| typ.pointee()) {
+                    Some(pointee) => (Some(base), Some(pointee.clone())),
+                    None => (deref_operand(base), base_type),
+                };
+                if let (Some(pointer), Some(pointee)) = (pointer, pointee) {
+                    let pointer = self.gen_value(pointer, current_block);
+                    let Some(pointer) = self.expect_value(pointer, &assign.lhs.position) else {
+                        return (None, None);
+                    };
+                    let value = self.gen_value(&mut assign.rhs, current_block);
+                    let Some(value) = self.expect_value(value, &assign.rhs.position) else {
+                        return (None, None);
+                    };
+                    self.write_instruction_to_block(
+                        Instruction::STORE_DEREF(pointer, path, value, pointee),
+                        current_block,
+                    );
+                    return (None, None);
+                }
+                let base = access_base(&mut assign.lhs);
+                let ParsedASTKind::IDENTIFIER(variable) = &base.kind else {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0203",
+                            "invalid left hand side of assignment".to_string(),
+                            base.position.clone(),
+                        )
+                        .with_label("only fields of variables can be assigned to".to_string()),
+                    );
+                    return (None, None);
+                };
+                let variable = Ref {
+                    value: variable.to_string(),
+                };
+                let value = self.gen_value(&mut assign.rhs, current_block);
+                let Some(value) = self.expect_value(value, &assign.rhs.position) else {
+                    return (None, None);
+                };
+                self.write_instruction_to_block(
+                    Instruction::STORE_FIELD(variable, path, value),
+                    current_block,
+                );
+            }
+            _ => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0203",
+                        "invalid left hand side of assignment".to_string(),
+                        assign.lhs.position.clone(),
+                    )
+                    .with_label("cannot assign to this expression".to_string()),
+                );
+            }
+        }
+        (None, None)
+    }
+
+    fn gen_call(
+        &mut self,
+        call: &mut Call,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let locals_id = self.locals_counter;
+        self.locals_counter += 1;
+
+        // named functions and intrinsics are called by name, anything else is a function value,
+        // i.e. `f(x)` for a `var f = fn ...` or `fns[0](x)`
+        let named = match &call.callee.kind {
+            ParsedASTKind::IDENTIFIER(name)
+                if self.signatures.contains_key(name)
+                    || !matches!(call.callee.typ, Some(Type::FN(_))) =>
+            {
+                Some(name.clone())
+            }
+            _ => None,
+        };
+        let callee_type = match &named {
+            Some(name) => self.signatures.get(name).cloned().map(Type::FN),
+            None => call.callee.typ.clone(),
+        };
+        // worked out before the args, as it is written before them
+        let mut callee_value = None;
+        if named.is_none() {
+            let value = self.gen_value(&mut call.callee, current_block);
+            let Some(value) = self.expect_value(value, &call.callee.position) else {
+                return (None, None);
+            };
+            callee_value = Some(value);
+        }
+        let Some(order) = order_args(call, &callee_type) else {
+            return (None, None);
+        };
+
+        let param_types = match &callee_type {
+            Some(Type::FN(function)) => function.args.clone(),
+            _ => vec![],
+        };
+
+        // the args are worked out in the order they are written, then passed in parameter order
+        let mut values: Vec<IRValue> = vec![];
+        for arg in call.args.iter_mut() {
+            let val = self.gen_value(&mut arg.value, current_block);
+            let Some(val) = self.expect_value(val, &arg.value.position) else {
+                return (None, None);
+            };
+            values.push(val);
+        }
+        let mut args: Vec<IRValue> = vec![];
+        for (param, i) in order.iter().enumerate() {
+            let param_type = param_types.get(param).cloned();
+            let arg = self.coerce(
+                values[*i].clone(),
+                call.args[*i].value.typ.clone(),
+                &param_type,
+                current_block,
+            );
+            args.push(arg);
+        }
+
+        let instruction = match (named, callee_value, callee_type) {
+            (Some(name), _, _) => Instruction::CALL(locals_id.to_string(), name, args),
+            (None, Some(value), Some(typ)) => {
+                Instruction::CALL_VALUE(locals_id.to_string(), value, typ, args)
+            }
+            // reported by the type checker
+            _ => return (None, None),
+        };
+        self.write_instruction_to_block(instruction, current_block);
         // todo this is really annoying
         (
             None,
@@ -493,16 +1623,37 @@ impl IRParser<'_> {
         iff: &mut If,
         current_block: &mut Box<Vec<Instruction>>,
     ) -> (Option<Instruction>, Option<IRValue>) {
-        let (_, condition_data) = self.gen_ast(&mut iff.condition, current_block);
+        let condition_data = self.gen_value(&mut iff.condition, current_block);
         if let Some(condition_data_unwrapped) = condition_data {
             // todo uhhh
             // todo bug: we have an issue here because the statement bit doesn't return teh i
-            let (body_instruction, _) = self.gen_ast(&mut iff.body, current_block);
-            let body_instruction_unwrapped = body_instruction.expect("expected body instruction");
-            // todo do the else
+            let (body_instruction, body_value) = self.gen_ast(&mut iff.body, current_block);
+            let Some(body_instruction_unwrapped) =
+                self.expect_block(body_instruction, &iff.body.position)
+            else {
+                return (None, None);
+            };
             if let Some(mut else_body) = iff.else_body.as_mut() {
-                let (else_body_instruction, _) = self.gen_ast(&mut else_body, current_block);
-                let e = else_body_instruction.expect("expected body");
+                let (else_body_instruction, else_value) =
+                    self.gen_ast(&mut else_body, current_block);
+                let Some(e) = self.expect_block(else_body_instruction, &else_body.position) else {
+                    return (None, None);
+                };
+                // both branches give a value, so the if does too
+                if let (Some(body_value), Some(else_value)) = (body_value, else_value) {
+                    let label = self.new_local();
+                    return (
+                        Some(Instruction::IF(
+                            label.clone(),
+                            condition_data_unwrapped,
+                            Box::new(body_instruction_unwrapped),
+                            body_value,
+                            Box::new(e),
+                            else_value,
+                        )),
+                        Some(IRValue::REF(Ref { value: label })),
+                    );
+                }
                 return (
                     Some(Instruction::COND_BR(
                         condition_data_unwrapped,
@@ -522,10 +1673,212 @@ impl IRParser<'_> {
                 );
             }
         } else {
-            panic!("conditional branch requires condition");
+            self.expect_value(None, &iff.condition.position);
+            (None, None)
         }
-        // todo
-        (None, None)
+    }
+
+    fn gen_while(
+        &mut self,
+        w: &mut While,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        // the condition is worked out again before every iteration so it gets its own block
+        let mut condition_block: Box<Vec<Instruction>> = Box::new(vec![]);
+        let condition = self.gen_value(&mut w.condition, &mut condition_block);
+        let Some(condition) = self.expect_value(condition, &w.condition.position) else {
+            return (None, None);
+        };
+        let Some(body) = self.gen_loop_body(&mut w.body, current_block) else {
+            return (None, None);
+        };
+        let condition_block = self.new_block(condition_block);
+        (
+            Some(Instruction::LOOP(
+                Box::new(condition_block),
+                condition,
+                Box::new(body),
+                None,
+            )),
+            None,
+        )
+    }
+
+    // `for i in a..b` counts i up from a while it is less than b, b is only evaluated once
+    fn gen_for(
+        &mut self,
+        f: &mut For,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let ParsedASTKind::BINARY(Binary {
+            left,
+            op: Token::DOT_DOT,
+            right,
+        }) = &mut f.iterable.kind
+        else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0208",
+                    "only ranges can be iterated over".to_string(),
+                    f.iterable.position.clone(),
+                )
+                .with_label("expected `start..end`".to_string()),
+            );
+            return (None, None);
+        };
+        let typ = arithmetic_type(left, right).unwrap_or(Type::I32);
+        let start = self.gen_value(left, current_block);
+        let Some(start) = self.expect_value(start, &left.position) else {
+            return (None, None);
+        };
+        let end = self.gen_value(right, current_block);
+        let Some(end) = self.expect_value(end, &right.position) else {
+            return (None, None);
+        };
+
+        let end_slot = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::STACK_VAR(end_slot.clone(), typ.clone(), Some(end)),
+            current_block,
+        );
+        self.write_instruction_to_block(
+            Instruction::STACK_VAR(f.variable.clone(), typ.clone(), Some(start)),
+            current_block,
+        );
+        let variable = Ref {
+            value: f.variable.clone(),
+        };
+
+        let condition = self.new_local();
+        let condition_block = self.new_block(Box::new(vec![Instruction::CMP(
+            condition.clone(),
+            Comparison::LT,
+            IRValue::REF(variable.clone()),
+            IRValue::REF(Ref { value: end_slot }),
+            typ.clone(),
+        )]));
+
+        let next = self.new_local();
+        let step = self.new_block(Box::new(vec![
+            Instruction::ADD(
+                next.clone(),
+                IRValue::REF(variable.clone()),
+                IRValue::INT(1),
+                typ,
+            ),
+            Instruction::STORE(variable, IRValue::REF(Ref { value: next })),
+        ]));
+
+        let Some(body) = self.gen_loop_body(&mut f.body, current_block) else {
+            return (None, None);
+        };
+        (
+            Some(Instruction::LOOP(
+                Box::new(condition_block),
+                IRValue::REF(Ref { value: condition }),
+                Box::new(body),
+                Some(Box::new(step)),
+            )),
+            None,
+        )
+    }
+
+    fn gen_loop_body(
+        &mut self,
+        body: &mut ParsedAST,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> Option<Instruction> {
+        self.loop_depth += 1;
+        let (instruction, _) = self.gen_ast(body, current_block);
+        self.loop_depth -= 1;
+        self.expect_block(instruction, &body.position)
+    }
+
+    fn gen_ret(
+        &mut self,
+        value: &mut Option<Box<ParsedAST>>,
+        position: &Position,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let Some(return_type) = self.return_types.last().cloned() else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0209",
+                    "`ret` outside of a function".to_string(),
+                    position.clone(),
+                )
+                .with_label("cannot `ret` here".to_string()),
+            );
+            return (None, None);
+        };
+        match (value, return_type) {
+            (Some(value), Some(_)) => {
+                let data = self.gen_value(value, current_block);
+                let Some(data) = self.expect_value(data, &value.position) else {
+                    return (None, None);
+                };
+                (Some(Instruction::RET(Some(data))), None)
+            }
+            (None, None) => (Some(Instruction::RET(None)), None),
+            (Some(value), None) => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0210",
+                        "this function doesn't return a value".to_string(),
+                        value.position.clone(),
+                    )
+                    .with_note("add a return type with `-> type`".to_string()),
+                );
+                (None, None)
+            }
+            (None, Some(typ)) => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0210",
+                        format!("expected a value of type `{}` to return", typ),
+                        position.clone(),
+                    )
+                    .with_label("`ret` needs a value".to_string()),
+                );
+                (None, None)
+            }
+        }
+    }
+
+    // break and continue
+    fn gen_jump(
+        &mut self,
+        instruction: Instruction,
+        keyword: &str,
+        position: &Position,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        if self.loop_depth == 0 {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0207",
+                    format!("`{}` outside of a loop", keyword),
+                    position.clone(),
+                )
+                .with_label(format!("cannot `{}` here", keyword)),
+            );
+            return (None, None);
+        }
+        (Some(instruction), None)
+    }
+
+    // branches need a block to jump to
+    fn expect_block(
+        &self,
+        instruction: Option<Instruction>,
+        position: &Position,
+    ) -> Option<Instruction> {
+        if instruction.is_none() && !self.diagnostics.has_errors() {
+            self.diagnostics.emit(
+                Diagnostic::error("E0206", "expected a block".to_string(), position.clone())
+                    .with_label("expected `{ ... }`".to_string()),
+            );
+        }
+        instruction
     }
 
     fn gen_identifier(
@@ -566,6 +1919,9 @@ impl IRParser<'_> {
         //     current_block,
         // );
         self.counter += 1;
+        if self.signatures.contains_key(identifier.as_str()) {
+            return self.gen_function_value(identifier, current_block);
+        }
         (
             None,
             Some(IRValue::REF(Ref {
@@ -573,4 +1929,279 @@ impl IRParser<'_> {
             })),
         )
     }
+
+    // a named function used as a value becomes a closure of a lambda that calls it, as
+    // closures are passed what they capture before their args and named functions aren't
+    fn gen_function_value(
+        &mut self,
+        name: &str,
+        current_block: &mut Box<Vec<Instruction>>,
+    ) -> (Option<Instruction>, Option<IRValue>) {
+        let Some(function) = self.signatures.get(name).cloned() else {
+            return (None, None);
+        };
+        let params: Vec<(String, Type)> = function.names.into_iter().zip(function.args).collect();
+        let args = params
+            .iter()
+            .map(|(param, _)| {
+                IRValue::REF(Ref {
+                    value: param.to_string(),
+                })
+            })
+            .collect();
+        let result = self.new_local();
+        let mut body = vec![Instruction::CALL(result.clone(), name.to_string(), args)];
+        let return_type = function.return_type.map(|typ| *typ);
+        if return_type.is_some() {
+            body.push(Instruction::RET(Some(IRValue::REF(Ref { value: result }))));
+        }
+        let body = self.new_block(Box::new(body));
+
+        let lambda = format!("{}_lambda", self.lambda_counter);
+        self.lambda_counter += 1;
+        self.write_instruction_to_block(
+            Instruction::FUNC(
+                lambda.clone(),
+                Some(vec![]),
+                params,
+                return_type,
+                Box::new(body),
+            ),
+            current_block,
+        );
+        let label = self.new_local();
+        self.write_instruction_to_block(
+            Instruction::CLOSURE(label.clone(), lambda, vec![]),
+            current_block,
+        );
+        (None, Some(IRValue::REF(Ref { value: label })))
+    }
+}
+
+// the type a pointer points to, anything else is reported by the type checker
+fn pointee_type(pointer: &ParsedAST) -> Option<Type> {
+    pointer.typ.as_ref()?.pointee().cloned()
+}
+
+// the type of the elements of something being indexed
+fn element_type(ast: &ParsedAST) -> Option<Type> {
+    match ast.typ.as_ref()? {
+        Type::ARRAY(element, _) | Type::SLICE(element) => Some(*element.clone()),
+        _ => None,
+    }
+}
+
+// the index of the arg for each parameter of a call. functions declared with a name, and
+// function values whose type names the params, take named args. the type checker has made
+// sure each is given once
+fn order_args(call: &Call, callee_type: &Option<Type>) -> Option<Vec<usize>> {
+    match callee_type {
+        Some(Type::FN(function)) if !function.names.is_empty() => {
+            order_named(&function.names, &call.args)
+        }
+        _ => Some((0..call.args.len()).collect()),
+    }
+}
+
+// the index of the `name=value` arg for each name
+fn order_named(names: &[String], args: &[Arg]) -> Option<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| args.iter().position(|arg| arg.name.as_ref() == Some(name)))
+        .collect()
+}
+
+// the value a chain of field accesses starts from, i.e. `a` in `a.b.c`. a pointer along the
+// way ends the chain, in `a.p.x` it is `a.p` and `x` is reached through it
+fn access_base<'b, 'c>(ast: &'b mut ParsedAST<'c>) -> &'b mut ParsedAST<'c> {
+    if matches!(ast.kind, ParsedASTKind::LHS_ACCESS(_)) {
+        let ParsedASTKind::LHS_ACCESS(access) = &mut ast.kind else {
+            unreachable!()
+        };
+        if pointee_type(&access.left).is_some() {
+            return &mut access.left;
+        }
+        access_base(&mut access.left)
+    } else {
+        ast
+    }
+}
+
+// the pointer in `*p`, looking through any parentheses around it
+fn deref_operand<'b, 'c>(ast: &'b mut ParsedAST<'c>) -> Option<&'b mut ParsedAST<'c>> {
+    match &mut ast.kind {
+        ParsedASTKind::GROUP(group) => deref_operand(&mut group.expression),
+        ParsedASTKind::LEFT_UNARY(LeftUnary::DEREF(pointer)) => Some(pointer),
+        _ => None,
+    }
+}
+
+// names the interpreter and llvm backend provide
+pub const INTRINSICS: [&str; 3] = ["printf", "format", "SYNTH_FILENAME"];
+
+// the identifiers an expression uses that it doesn't declare, in the order they are first used
+fn free_variables(ast: &ParsedAST, bound: &mut Vec<String>, free: &mut Vec<String>) {
+    match &ast.kind {
+        ParsedASTKind::IDENTIFIER(name) => {
+            if !bound.contains(name) && !free.contains(name) {
+                free.push(name.to_string());
+            }
+        }
+        ParsedASTKind::STMT(inner) => free_variables(inner, bound, free),
+        ParsedASTKind::BLOCK(block) => {
+            let scope = bound.len();
+            for item in block.body.iter() {
+                free_variables(item, bound, free);
+            }
+            bound.truncate(scope);
+        }
+        ParsedASTKind::DECL(decl) => {
+            if let Some(value) = &decl.value {
+                free_variables(value, bound, free);
+            }
+            bound.push(decl.identifier.clone());
+        }
+        ParsedASTKind::ASSIGN(assign) => {
+            free_variables(&assign.lhs, bound, free);
+            free_variables(&assign.rhs, bound, free);
+        }
+        ParsedASTKind::BINARY(binary) => {
+            free_variables(&binary.left, bound, free);
+            free_variables(&binary.right, bound, free);
+        }
+        ParsedASTKind::LEFT_UNARY(
+            LeftUnary::COMP(operand)
+            | LeftUnary::NEG(operand)
+            | LeftUnary::NOT(operand)
+            | LeftUnary::ADDRESS(operand)
+            | LeftUnary::DEREF(operand),
+        ) => free_variables(operand, bound, free),
+        ParsedASTKind::UNWRAP(operand) | ParsedASTKind::UNSAFE(operand) => {
+            free_variables(operand, bound, free)
+        }
+        ParsedASTKind::GROUP(group) => free_variables(&group.expression, bound, free),
+        ParsedASTKind::CALL(call) => {
+            free_variables(&call.callee, bound, free);
+            for arg in call.args.iter() {
+                free_variables(&arg.value, bound, free);
+            }
+        }
+        ParsedASTKind::IF(iff) => {
+            free_variables(&iff.condition, bound, free);
+            free_variables(&iff.body, bound, free);
+            if let Some(else_body) = &iff.else_body {
+                free_variables(else_body, bound, free);
+            }
+        }
+        ParsedASTKind::WHILE(w) => {
+            free_variables(&w.condition, bound, free);
+            free_variables(&w.body, bound, free);
+        }
+        ParsedASTKind::FOR(f) => {
+            free_variables(&f.iterable, bound, free);
+            let scope = bound.len();
+            bound.push(f.variable.clone());
+            free_variables(&f.body, bound, free);
+            bound.truncate(scope);
+        }
+        ParsedASTKind::RET(Some(value)) => free_variables(value, bound, free),
+        ParsedASTKind::FN(func) => {
+            let scope = bound.len();
+            bound.extend(func.params.iter().map(|p| p.identifier.clone()));
+            free_variables(&func.body, bound, free);
+            bound.truncate(scope);
+            if let Some(name) = &func.identifier {
+                bound.push(name.to_string());
+            }
+        }
+        // the right is the name of a field
+        ParsedASTKind::LHS_ACCESS(access) => free_variables(&access.left, bound, free),
+        ParsedASTKind::STRUCT_LITERAL(literal) => {
+            for field in literal.fields.iter() {
+                free_variables(&field.value, bound, free);
+            }
+        }
+        ParsedASTKind::ARRAY_LITERAL(elements) => {
+            for element in elements.iter() {
+                free_variables(element, bound, free);
+            }
+        }
+        ParsedASTKind::INDEX(index) => {
+            free_variables(&index.left, bound, free);
+            free_variables(&index.index, bound, free);
+        }
+        ParsedASTKind::VARIANT(variant) => {
+            for value in variant.payload.iter() {
+                free_variables(value, bound, free);
+            }
+        }
+        ParsedASTKind::MATCH(m) => {
+            free_variables(&m.scrutinee, bound, free);
+            for arm in m.arms.iter() {
+                let scope = bound.len();
+                pattern_bindings(&arm.pattern, bound);
+                free_variables(&arm.body, bound, free);
+                bound.truncate(scope);
+            }
+        }
+        _ => {}
+    }
+}
+
+// the names a pattern binds
+fn pattern_bindings(pattern: &Pattern, names: &mut Vec<String>) {
+    match &pattern.kind {
+        PatternKind::BINDING(name) => names.push(name.to_string()),
+        PatternKind::VARIANT(_, _, payload) => {
+            for part in payload.iter() {
+                pattern_bindings(part, names);
+            }
+        }
+        PatternKind::WILDCARD | PatternKind::LITERAL(_) => {}
+    }
+}
+
+// the comparison a token stands for
+fn comparison(token: &Token) -> Option<Comparison> {
+    match token {
+        Token::EQUAL_EQUAL => Some(Comparison::EQ),
+        Token::NOT_EQUAL => Some(Comparison::NE),
+        Token::LESS => Some(Comparison::LT),
+        Token::LESS_EQUAL => Some(Comparison::LE),
+        Token::GREATER => Some(Comparison::GT),
+        Token::GREATER_EQUAL => Some(Comparison::GE),
+        _ => None,
+    }
+}
+
+// evaluate an operator on two integer literals at compile time, wrapping like the
+// instruction would for the type. division by zero is left for runtime to report
+fn fold_int(op: &Token, lhs: i64, rhs: i64, typ: &Type) -> Option<i64> {
+    let unsigned = typ.is_unsigned();
+    let value = match op {
+        Token::PLUS => lhs.wrapping_add(rhs),
+        Token::MINUS => lhs.wrapping_sub(rhs),
+        Token::STAR => lhs.wrapping_mul(rhs),
+        Token::DIV if rhs != 0 && unsigned => (lhs as u64 / rhs as u64) as i64,
+        Token::DIV if rhs != 0 => lhs.wrapping_div(rhs),
+        Token::PERCENT if rhs != 0 && unsigned => (lhs as u64 % rhs as u64) as i64,
+        Token::PERCENT if rhs != 0 => lhs.wrapping_rem(rhs),
+        op => {
+            let ordering = match unsigned {
+                true => (lhs as u64).cmp(&(rhs as u64)),
+                false => lhs.cmp(&rhs),
+            };
+            let holds = match comparison(op)? {
+                Comparison::EQ => ordering.is_eq(),
+                Comparison::NE => ordering.is_ne(),
+                Comparison::LT => ordering.is_lt(),
+                Comparison::LE => ordering.is_le(),
+                Comparison::GT => ordering.is_gt(),
+                Comparison::GE => ordering.is_ge(),
+            };
+            // a comparison is a bool, not the type of its operands
+            return Some(holds as i64);
+        }
+    };
+    Some(typ.wrap(value))
 }
@@ -1,22 +1,92 @@
+use std::rc::Rc;
 use std::time::Instant;
 
 use log::debug;
 
-use crate::token::Token;
+use crate::diagnostic::{Diagnostic, DiagnosticSink};
+use crate::token::{NumberLiteral, Position, PositionedToken, StringPart, Token};
+use crate::types::Type;
 
-pub struct Lexer {
+// a point in the program, (byte offset, line, col)
+type Mark = (usize, u32, u32);
+
+// words are lexed as identifiers first then checked against this table, so
+// `iffy` and `u32_buf` are identifiers rather than a keyword followed by something
+fn keyword(word: &str) -> Option<Token> {
+    match word {
+        "bool" => Some(Token::BOOL),
+        "break" => Some(Token::BREAK),
+        "char" => Some(Token::CHAR),
+        "comp" => Some(Token::COMP),
+        "const" => Some(Token::CONST),
+        "continue" => Some(Token::CONTINUE),
+        "else" => Some(Token::ELSE),
+        "enum" => Some(Token::ENUM),
+        "false" => Some(Token::FALSE),
+        "fn" => Some(Token::FN),
+        "f32" => Some(Token::F32),
+        "for" => Some(Token::FOR),
+        "i32" => Some(Token::I32),
+        "if" => Some(Token::IF),
+        "in" => Some(Token::IN),
+        "match" => Some(Token::MATCH),
+        "mut" => Some(Token::MUT),
+        "null" => Some(Token::NULL),
+        "pub" => Some(Token::PUB),
+        "priv" => Some(Token::PRIV),
+        "ret" => Some(Token::RET),
+        "true" => Some(Token::TRUE),
+        "type" => Some(Token::TYPE),
+        "unsafe" => Some(Token::UNSAFE),
+        "u8" => Some(Token::U8),
+        "u16" => Some(Token::U16),
+        "u32" => Some(Token::U32),
+        "u64" => Some(Token::U64),
+        "i8" => Some(Token::I8),
+        "i16" => Some(Token::I16),
+        "i64" => Some(Token::I64),
+        "f64" => Some(Token::F64),
+        "var" => Some(Token::VAR),
+        "while" => Some(Token::WHILE),
+        _ => None,
+    }
+}
+
+pub struct Lexer<'a> {
+    // byte offset into the program, always on a char boundary
     pub current: usize,
+    pub line: u32,
+    pub col: u32,
     pub program: Box<String>,
+    pub file: Rc<str>,
     // todo this is bad practice
-    pub tokens: Box<Vec<Token>>,
+    pub tokens: Box<Vec<PositionedToken>>,
+    pub diagnostics: &'a DiagnosticSink,
+    // keep comments and whitespace as tokens so the source can be rebuilt from them
+    // (for formatting and doc generation), the parser can't handle these
+    pub trivia: bool,
 }
 
-impl Lexer {
-    pub fn new() -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(file: &str, diagnostics: &'a DiagnosticSink) -> Lexer<'a> {
         Lexer {
             current: 0,
+            line: 1,
+            col: 1,
             program: Box::new(String::from("")),
+            file: Rc::from(file),
             tokens: Box::new(vec![]),
+            diagnostics,
+            trivia: false,
+        }
+    }
+
+    // only the tests need to see comments and whitespace for now
+    #[cfg(test)]
+    pub fn with_trivia(file: &str, diagnostics: &'a DiagnosticSink) -> Lexer<'a> {
+        Lexer {
+            trivia: true,
+            ..Lexer::new(file, diagnostics)
         }
     }
 
@@ -24,178 +94,14 @@ impl Lexer {
         //-> Box<Vec<Token>>{
 
         self.program = program;
+        self.current = 0;
+        self.line = 1;
+        self.col = 1;
 
         let now = Instant::now();
 
-        while !self.end() {
-            match self.program.chars().nth(self.current).unwrap() {
-                '\n' => {}
-                '\t' => {}
-                '\r' => {}
-                '$' => self.tokens.push(Token::DOLLAR),
-                '@' => self.tokens.push(Token::AT),
-                '#' => self.tokens.push(Token::HASH),
-                '+' => self.tokens.push(Token::PLUS),
-                '-' => self.tokens.push(Token::MINUS),
-                '*' => self.tokens.push(Token::STAR),
-                '/' => {
-                    if self.program.chars().nth(self.current + 1).unwrap() == '/' {
-                        self.single_line_comment();
-                    } else {
-                        self.tokens.push(Token::DIV);
-                    }
-                }
-                '{' => self.tokens.push(Token::LCURLY),
-                '}' => self.tokens.push(Token::RCURLY),
-                '(' => self.tokens.push(Token::LPAREN),
-                ')' => self.tokens.push(Token::RPAREN),
-                '[' => self.tokens.push(Token::LBRACKET),
-                ']' => self.tokens.push(Token::RBRACKET),
-                '.' => self.tokens.push(Token::DOT),
-                ',' => self.tokens.push(Token::COMMA),
-                ':' => self.tokens.push(Token::COLON),
-                ';' => self.tokens.push(Token::SEMICOLON),
-                '=' => self.tokens.push(Token::EQUAL),
-                'b' => {
-                    if self.is_keyword("bool".to_string()) {
-                        self.tokens.push(Token::BOOL);
-                        self.current += 3; // its only 3 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'c' => {
-                    if self.is_keyword("comp".to_string()) {
-                        self.tokens.push(Token::COMP);
-                        self.current += 4; // its only 3 because we + 1 later
-                    } else if self.is_keyword("const".to_string()) {
-                        self.tokens.push(Token::CONST);
-                        self.current += 4; // its only 3 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'e' => {
-                    if self.is_keyword("else".to_string()) {
-                        self.tokens.push(Token::ELSE);
-                        self.current += 3; // its only 3 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'f' => {
-                    if self.is_keyword("false".to_string()) {
-                        self.tokens.push(Token::FALSE);
-                        self.current += 4; // its only 2 because we + 1 later
-                    } else if self.is_keyword("fn".to_string()) {
-                        self.tokens.push(Token::FN);
-                        self.current += 1; // its only 2 because we + 1 later
-                    } else if self.is_keyword("f32".to_string()) {
-                        self.tokens.push(Token::F32);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else if self.is_keyword("for".to_string()) {
-                        self.tokens.push(Token::FOR);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'i' => {
-                    if self.is_keyword("i32".to_string()) {
-                        self.tokens.push(Token::I32);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else if self.is_keyword("if".to_string()) {
-                        self.tokens.push(Token::IF);
-                        self.current += 1; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'm' => {
-                    if self.is_keyword("mut".to_string()) {
-                        self.tokens.push(Token::MUT);
-                        self.current += 2; // its only 3 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'p' => {
-                    if self.is_keyword("pub".to_string()) {
-                        self.tokens.push(Token::PUB);
-                        self.current += 2; // its only 3 because we + 1 later
-                    } else if self.is_keyword("priv".to_string()) {
-                        self.tokens.push(Token::PRIV);
-                        self.current += 3; // its only 3 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'r' => {
-                    if self.is_keyword("ret".to_string()) {
-                        self.tokens.push(Token::RET);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                't' => {
-                    if self.is_keyword("true".to_string()) {
-                        self.tokens.push(Token::TRUE);
-                        self.current += 3; // its only 2 because we + 1 later
-                    } else if self.is_keyword("type".to_string()) {
-                        self.tokens.push(Token::TYPE);
-                        self.current += 3; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'u' => {
-                    if self.is_keyword("u32".to_string()) {
-                        self.tokens.push(Token::U32);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                'v' => {
-                    if self.is_keyword("var".to_string()) {
-                        self.tokens.push(Token::VAR);
-                        self.current += 2; // its only 2 because we + 1 later
-                    } else {
-                        // todo do identifier
-                        self.other();
-                        continue;
-                    }
-                }
-                ' ' => {}
-                _ => {
-                    // todo do identifier
-                    self.other();
-                    continue;
-                }
-            }
-
-            self.current += 1;
+        while let Some(c) = self.peek() {
+            self.token(c);
         }
 
         let elapsed = now.elapsed();
@@ -206,114 +112,972 @@ impl Lexer {
         );
     }
 
-    fn single_line_comment(&mut self) {
-        self.current += 2;
-        while self.program.chars().nth(self.current).unwrap() != '\n'
-            && self.program.chars().nth(self.current).unwrap() != '\r'
-        {
-            self.current += 1;
+    // lex the token starting with c
+    fn token(&mut self, c: char) {
+        match c {
+            '\n' | '\t' | '\r' | ' ' => self.whitespace(),
+            '$' => self.single(Token::DOLLAR),
+            '@' => self.single(Token::AT),
+            '#' => self.single(Token::HASH),
+            '+' => self.operator(&[('=', Token::PLUS_EQUAL)], Token::PLUS),
+            '-' => self.operator(
+                &[('>', Token::ARROW), ('=', Token::MINUS_EQUAL)],
+                Token::MINUS,
+            ),
+            '*' => self.operator(&[('=', Token::STAR_EQUAL)], Token::STAR),
+            '/' => {
+                if self.peek_next() == Some('/') {
+                    self.single_line_comment();
+                } else if self.peek_next() == Some('*') {
+                    self.block_comment();
+                } else {
+                    self.operator(&[('=', Token::DIV_EQUAL)], Token::DIV);
+                }
+            }
+            '%' => self.single(Token::PERCENT),
+            '^' => self.single(Token::CARET),
+            '~' => self.single(Token::TILDE),
+            '?' => self.single(Token::QUESTION),
+            '!' => self.operator(&[('=', Token::NOT_EQUAL)], Token::NOT),
+            '<' => self.operator(&[('=', Token::LESS_EQUAL)], Token::LESS),
+            '>' => self.operator(&[('=', Token::GREATER_EQUAL)], Token::GREATER),
+            '&' => self.operator(&[('&', Token::AND)], Token::AMPERSAND),
+            '|' => self.operator(&[('|', Token::OR), ('>', Token::PIPE)], Token::BAR),
+            '{' => self.single(Token::LCURLY),
+            '}' => self.single(Token::RCURLY),
+            '(' => self.single(Token::LPAREN),
+            ')' => self.single(Token::RPAREN),
+            '[' => self.single(Token::LBRACKET),
+            ']' => self.single(Token::RBRACKET),
+            '.' => self.operator(&[('.', Token::DOT_DOT)], Token::DOT),
+            ',' => self.single(Token::COMMA),
+            ':' => self.operator(&[(':', Token::DOUBLE_COLON)], Token::COLON),
+            ';' => self.single(Token::SEMICOLON),
+            '=' => self.operator(
+                &[('=', Token::EQUAL_EQUAL), ('>', Token::FAT_ARROW)],
+                Token::EQUAL,
+            ),
+            _ => self.other(c),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.program[self.current..].chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.peek_ahead(1)
+    }
+
+    fn peek_ahead(&self, n: usize) -> Option<char> {
+        self.program[self.current..].chars().nth(n)
+    }
+
+    // move past the current char, keeping track of the line and column
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
         }
-        //self.current+=1;
+        Some(c)
     }
 
-    fn is_keyword(&self, keyword: String) -> bool {
-        let mut matched = true;
-        for i in 0..keyword.chars().count() {
-            if self.program.chars().nth(self.current + i).is_some()
-                && self.program.chars().nth(self.current + i).unwrap()
-                    != keyword.chars().nth(i).unwrap()
-            {
-                matched = false;
+    fn mark(&self) -> Mark {
+        (self.current, self.line, self.col)
+    }
+
+    // the position from start up to where we are now
+    fn position(&self, start: Mark) -> Position {
+        Position {
+            file: self.file.clone(),
+            start: start.0,
+            end: self.current,
+            col_start: start.2,
+            col_end: self.col,
+            line_start: start.1,
+            line_end: self.line,
+        }
+    }
+
+    fn add_token(&mut self, token: Token, start: Mark) {
+        let position = self.position(start);
+        self.tokens.push(PositionedToken { token, position });
+    }
+
+    // a token made of the current char
+    fn single(&mut self, token: Token) {
+        let start = self.mark();
+        self.advance();
+        self.add_token(token, start);
+    }
+
+    // a two char token if the next char matches one of the options, otherwise a single char one
+    fn operator(&mut self, options: &[(char, Token)], single: Token) {
+        let next = self.peek_next();
+        for (c, token) in options.iter() {
+            if next == Some(*c) {
+                let start = self.mark();
+                self.advance();
+                self.advance();
+                self.add_token(token.clone(), start);
+                return;
             }
         }
-        matched
+        self.single(single);
+    }
+
+    fn whitespace(&mut self) {
+        let start = self.mark();
+        let s = self.take_while(|c| c == '\n' || c == '\t' || c == '\r' || c == ' ');
+        if self.trivia {
+            self.add_token(Token::WHITESPACE(s), start);
+        }
     }
 
-    fn end(&self) -> bool {
-        return self.current >= self.program.chars().count();
+    fn single_line_comment(&mut self) {
+        let start = self.mark();
+        let s = self.take_while(|c| c != '\n' && c != '\r');
+        // `///` is a doc comment but `////` is just a comment
+        if s.starts_with("///") && !s.starts_with("////") {
+            let doc = &s[3..];
+            let doc = doc.strip_prefix(' ').unwrap_or(doc);
+            self.add_token(Token::DOC_COMMENT(doc.to_string()), start);
+        } else if self.trivia {
+            self.add_token(Token::COMMENT(s), start);
+        }
+    }
+
+    // /* */ comments, which can be nested
+    fn block_comment(&mut self) {
+        let start = self.mark();
+        self.advance();
+        self.advance();
+        let opening = self.position(start);
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.peek(), self.peek_next()) {
+                (Some('/'), Some('*')) => {
+                    self.advance();
+                    self.advance();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.advance();
+                    self.advance();
+                    depth -= 1;
+                }
+                (Some(_), _) => {
+                    self.advance();
+                }
+                (None, _) => {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0006",
+                            "unterminated block comment".to_string(),
+                            opening,
+                        )
+                        .with_label("comment starts here".to_string()),
+                    );
+                    break;
+                }
+            }
+        }
+        if self.trivia {
+            let s = self.program[start.0..self.current].to_string();
+            self.add_token(Token::COMMENT(s), start);
+        }
     }
 
-    fn other(&mut self) {
-        let c = self.program.chars().nth(self.current).unwrap();
+    fn other(&mut self, c: char) {
         if c.is_digit(10) {
             self.number();
-        } else if c.is_alphabetic() || c == '_' {
+        } else if c == 'r' && self.is_raw_string() {
+            self.raw_string();
+        } else if c.is_ascii_alphabetic() || c == '_' {
             self.identifier();
-        } else if c.eq_ignore_ascii_case(&'"') || c.eq_ignore_ascii_case(&'\'') {
+        } else if c == '"' {
             self.string();
+        } else if c == '\'' {
+            self.char_literal();
+        } else {
+            let start = self.mark();
+            self.advance();
+            let position = self.position(start);
+            self.diagnostics.emit(Diagnostic::error(
+                "E0001",
+                format!("unexpected character `{}`", c),
+                position,
+            ));
         }
     }
 
+    // advance while the predicate holds, returning the text we moved past
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.current;
+        while let Some(c) = self.peek() {
+            if !predicate(c) {
+                break;
+            }
+            self.advance();
+        }
+        self.program[start..self.current].to_string()
+    }
+
+    // i.e. 123, 1_000_000, 0xFF, 0b1010, 0o17, 1.5, 1e-3, 10u8, 2.5f64
+    // the value is worked out by the parser which reports anything that doesn't fit
     fn number(&mut self) {
-        let mut s = String::from("");
-        while !self.end()
-            && (self.program.chars().nth(self.current).unwrap().is_digit(10)
-                || (self.program.chars().nth(self.current).unwrap() == '.'))
-        {
-            s.push(self.program.chars().nth(self.current).unwrap());
-            self.current += 1;
+        let start = self.mark();
+        let radix = match (self.peek(), self.peek_next()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance();
+            self.advance();
+        }
+        let mut digits = self.take_while(|c| c.is_digit(radix) || c == '_');
+        let mut float = false;
+        if radix == 10 {
+            // only take the dot if a digit follows so that `0..10` is a range
+            if self.peek() == Some('.') && self.peek_next().map_or(false, |c| c.is_digit(10)) {
+                self.advance();
+                digits.push('.');
+                digits += &self.take_while(|c| c.is_digit(10) || c == '_');
+                float = true;
+            }
+            let exponent = match (self.peek_next(), self.peek_ahead(2)) {
+                (Some(c), _) if c.is_digit(10) => true,
+                (Some('+' | '-'), Some(c)) => c.is_digit(10),
+                _ => false,
+            };
+            if matches!(self.peek(), Some('e' | 'E')) && exponent {
+                self.advance();
+                digits.push('e');
+                if let Some(sign @ ('+' | '-')) = self.peek() {
+                    self.advance();
+                    digits.push(sign);
+                }
+                digits += &self.take_while(|c| c.is_digit(10) || c == '_');
+                float = true;
+            }
+        }
+        digits.retain(|c| c != '_');
+
+        let suffix_start = self.mark();
+        let suffix = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        let suffix = self.number_suffix(&suffix, suffix_start, radix, float);
+
+        if digits.is_empty() {
+            let position = self.position(start);
+            self.diagnostics.emit(Diagnostic::error(
+                "E0004",
+                format!("no digits in {} literal", radix_name(radix)),
+                position,
+            ));
+            digits.push('0');
         }
-        self.tokens.push(Token::NUMBER(s));
+
+        let text = self.program[start.0..self.current].to_string();
+        self.add_token(
+            Token::NUMBER(NumberLiteral {
+                text,
+                digits,
+                radix,
+                float,
+                suffix,
+            }),
+            start,
+        );
     }
 
+    // check the suffix of a number literal is a type it can be, i.e. `u8` in `10u8`
+    fn number_suffix(&self, suffix: &str, start: Mark, radix: u32, float: bool) -> Option<Type> {
+        if suffix.is_empty() {
+            return None;
+        }
+        let position = self.position(start);
+        let typ = match suffix {
+            "u8" => Type::U8,
+            "u16" => Type::U16,
+            "u32" => Type::U32,
+            "u64" => Type::U64,
+            "i8" => Type::I8,
+            "i16" => Type::I16,
+            "i32" => Type::I32,
+            "i64" => Type::I64,
+            "f32" => Type::F32,
+            "f64" => Type::F64,
+            // digits that are too big for the radix end up here, i.e. the 2 in `0b102`
+            s if s.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.diagnostics.emit(Diagnostic::error(
+                    "E0004",
+                    format!(
+                        "invalid digit `{}` in {} literal",
+                        &s[..1],
+                        radix_name(radix)
+                    ),
+                    position,
+                ));
+                return None;
+            }
+            s => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0005",
+                        format!("invalid suffix `{}` for number literal", s),
+                        position,
+                    )
+                    .with_note(
+                        "valid suffixes are u8, u16, u32, u64, i8, i16, i32, i64, f32 and f64"
+                            .to_string(),
+                    ),
+                );
+                return None;
+            }
+        };
+        let float_suffix = typ == Type::F32 || typ == Type::F64;
+        if float && !float_suffix {
+            self.diagnostics.emit(Diagnostic::error(
+                "E0005",
+                format!("integer suffix `{}` on a float literal", suffix),
+                position,
+            ));
+            return None;
+        }
+        if float_suffix && radix != 10 {
+            self.diagnostics.emit(Diagnostic::error(
+                "E0005",
+                format!("{} float literals are not supported", radix_name(radix)),
+                position,
+            ));
+            return None;
+        }
+        Some(typ)
+    }
+
+    // an identifier or keyword, [A-Za-z_][A-Za-z0-9_]*
     fn identifier(&mut self) {
-        let mut s = String::from("");
-        while !self.end()
-            && (self
-                .program
-                .chars()
-                .nth(self.current)
-                .unwrap()
-                .is_alphabetic()
-                || self.program.chars().nth(self.current).unwrap() == '_')
-        {
-            s.push(self.program.chars().nth(self.current).unwrap());
-            self.current += 1;
+        let start = self.mark();
+        let s = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        match keyword(&s) {
+            Some(token) => self.add_token(token, start),
+            None => self.add_token(Token::IDENTIFIER(s), start),
         }
-        self.tokens.push(Token::IDENTIFIER(s));
     }
 
+    // strings can interpolate values, i.e. "hi $name" or "sum ${a + b}".
+    // these are lexed into the literal parts and the tokens of each value
     fn string(&mut self) {
-        let first_char = self.program.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let start = self.mark();
+        self.advance(); // consume the "
+        let mut parts: Vec<StringPart> = vec![];
         let mut s = String::from("");
-        while !self.end()
-            && !self
-                .program
-                .chars()
-                .nth(self.current)
-                .unwrap()
-                .eq_ignore_ascii_case(&first_char)
-        {
-            match self.program.chars().nth(self.current).unwrap() {
-                '\\' => match self.program.chars().nth(self.current + 1).unwrap() {
-                    '"' => {
-                        s.push_str("\\\"");
-                        self.current += 2;
+        let mut terminated = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.advance();
+                    terminated = true;
+                    break;
+                }
+                '$' => match self.interpolation() {
+                    Some(tokens) => {
+                        if !s.is_empty() {
+                            parts.push(StringPart::LITERAL(std::mem::take(&mut s)));
+                        }
+                        parts.push(StringPart::CODE(tokens));
                     }
-                    '\'' => {
-                        s.push_str("\\'");
-                        self.current += 2;
+                    None => {
+                        s.push(c);
+                        self.advance();
                     }
-                    'n' => {
-                        s.push_str("\n");
-                        self.current += 2;
+                },
+                '\\' => {
+                    if let Some(escaped) = self.escape() {
+                        s.push(escaped);
                     }
-                    't' => {
-                        s.push_str("\t");
-                        self.current += 2;
+                }
+                _ => {
+                    s.push(c);
+                    self.advance();
+                }
+            }
+        }
+        if !terminated {
+            let position = self.position(start);
+            self.diagnostics.emit(
+                Diagnostic::error("E0002", "unterminated string".to_string(), position)
+                    .with_label("expected a closing \"".to_string()),
+            );
+        }
+        if parts.is_empty() {
+            self.add_token(Token::STRING(s), start);
+        } else {
+            if !s.is_empty() {
+                parts.push(StringPart::LITERAL(s));
+            }
+            self.add_token(Token::INTERPOLATED_STRING(parts), start);
+        }
+    }
+
+    // r"..." or r#"..."# which can span lines and have no escapes or interpolation,
+    // the #s let the string contain a "
+    fn raw_string(&mut self) {
+        let start = self.mark();
+        self.advance(); // consume the r
+        let hashes = self.take_while(|c| c == '#').len();
+        self.advance(); // consume the "
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let end = self.program[self.current..]
+            .find(&closing)
+            .map(|offset| self.current + offset);
+        let content_start = self.current;
+        while self.current < end.unwrap_or(self.program.len()) {
+            self.advance();
+        }
+        let s = self.program[content_start..self.current].to_string();
+        if end.is_some() {
+            for _ in closing.chars() {
+                self.advance();
+            }
+        } else {
+            let position = self.position(start);
+            self.diagnostics.emit(
+                Diagnostic::error("E0002", "unterminated raw string".to_string(), position)
+                    .with_label(format!("expected a closing {}", closing)),
+            );
+        }
+        self.add_token(Token::STRING(s), start);
+    }
+
+    // we are on an r, is it the start of a raw string (r followed by any #s then ")
+    fn is_raw_string(&self) -> bool {
+        self.program[self.current + 1..]
+            .trim_start_matches('#')
+            .starts_with('"')
+    }
+
+    // 'a', '\n' or '\u{1F600}'
+    fn char_literal(&mut self) {
+        let start = self.mark();
+        self.advance(); // consume the '
+        let value = match self.peek() {
+            Some('\\') => self.escape(),
+            Some('\'') => {
+                self.advance();
+                let position = self.position(start);
+                self.diagnostics.emit(Diagnostic::error(
+                    "E0008",
+                    "empty character literal".to_string(),
+                    position,
+                ));
+                self.add_token(Token::CHARACTER('\0'), start);
+                return;
+            }
+            Some('\n') | None => None,
+            Some(c) => {
+                self.advance();
+                Some(c)
+            }
+        };
+        if self.peek() == Some('\'') {
+            self.advance();
+            self.add_token(Token::CHARACTER(value.unwrap_or('\0')), start);
+            return;
+        }
+
+        // if there is a closing ' on the same line someone probably wanted a string
+        let line = self.program[self.current..].lines().next().unwrap_or("");
+        if let Some(offset) = line.find('\'') {
+            let end = self.current + offset + 1;
+            while self.current < end {
+                self.advance();
+            }
+            let position = self.position(start);
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0008",
+                    "character literal may only contain one character".to_string(),
+                    position,
+                )
+                .with_note("strings use double quotes, i.e. \"hello\"".to_string()),
+            );
+        } else {
+            let position = self.position(start);
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0002",
+                    "unterminated character literal".to_string(),
+                    position,
+                )
+                .with_label("expected a closing '".to_string()),
+            );
+        }
+        self.add_token(Token::CHARACTER(value.unwrap_or('\0')), start);
+    }
+
+    // an escape sequence in a string or character literal, we are on the \.
+    // returns None if it is invalid (which we report) or we ran out of input
+    fn escape(&mut self) -> Option<char> {
+        let start = self.mark();
+        self.advance(); // consume the \
+        let escaped = match self.advance()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            '$' => '$',
+            'x' => {
+                let mut digits = String::from("");
+                while digits.len() < 2 && self.peek().map_or(false, |c| c.is_digit(16)) {
+                    digits.push(self.advance().unwrap());
+                }
+                if digits.len() < 2 {
+                    return self.escape_error(start, "expected two hex digits in `\\x` escape");
+                }
+                let value = u32::from_str_radix(&digits, 16).unwrap();
+                if value > 0x7f {
+                    return self.escape_error(start, "`\\x` escapes must be at most `\\x7f`");
+                }
+                char::from_u32(value).unwrap()
+            }
+            'u' => {
+                if self.peek() != Some('{') {
+                    return self.escape_error(start, "expected `{` after `\\u`");
+                }
+                self.advance();
+                let digits = self.take_while(|c| c.is_digit(16));
+                if self.peek() != Some('}') {
+                    return self.escape_error(start, "expected `}` to end the unicode escape");
+                }
+                self.advance();
+                if digits.is_empty() || digits.len() > 6 {
+                    return self.escape_error(
+                        start,
+                        "unicode escapes must have between 1 and 6 hex digits",
+                    );
+                }
+                match char::from_u32(u32::from_str_radix(&digits, 16).unwrap()) {
+                    Some(c) => c,
+                    None => {
+                        return self.escape_error(
+                            start,
+                            "unicode escape is not a valid unicode scalar value",
+                        )
                     }
-                    _ => {
-                        panic!()
+                }
+            }
+            c => {
+                let position = self.position(start);
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0003",
+                        format!("unknown escape sequence `\\{}`", c),
+                        position,
+                    )
+                    .with_note(
+                        "supported escapes are \\n \\t \\r \\0 \\\\ \\\" \\' \\$ \\xNN and \\u{NNNN}"
+                            .to_string(),
+                    ),
+                );
+                return None;
+            }
+        };
+        Some(escaped)
+    }
+
+    fn escape_error(&self, start: Mark, message: &str) -> Option<char> {
+        let position = self.position(start);
+        self.diagnostics
+            .emit(Diagnostic::error("E0003", message.to_string(), position));
+        None
+    }
+
+    // the tokens of `$name` or `${expression}` in a string, a `$` followed by anything
+    // else is just a `$` so we return None without consuming it
+    fn interpolation(&mut self) -> Option<Vec<PositionedToken>> {
+        let first = self.tokens.len();
+        match self.peek_next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                self.advance(); // consume the $
+                self.identifier();
+            }
+            Some('{') => {
+                let start = self.mark();
+                self.advance();
+                self.advance();
+                let opening = self.position(start);
+                let mut depth = 0;
+                loop {
+                    match self.peek() {
+                        None => {
+                            self.diagnostics.emit(
+                                Diagnostic::error(
+                                    "E0007",
+                                    "unterminated interpolation".to_string(),
+                                    opening,
+                                )
+                                .with_label("expected a closing }".to_string()),
+                            );
+                            break;
+                        }
+                        Some('}') if depth == 0 => {
+                            self.advance();
+                            break;
+                        }
+                        Some(c) => {
+                            if c == '{' {
+                                depth += 1;
+                            } else if c == '}' {
+                                depth -= 1;
+                            }
+                            self.token(c);
+                        }
                     }
-                },
-                _ => {
-                    s.push(self.program.chars().nth(self.current).unwrap());
-                    self.current += 1;
                 }
             }
+            _ => return None,
+        }
+        Some(self.tokens.drain(first..).collect())
+    }
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        16 => "hex",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::diagnostic::{Diagnostic, DiagnosticSink};
+    use crate::lex::Lexer;
+    use crate::token::{NumberLiteral, StringPart, Token};
+    use crate::types::Type;
+
+    fn number(text: &str, digits: &str, radix: u32, float: bool, suffix: Option<Type>) -> Token {
+        Token::NUMBER(NumberLiteral {
+            text: text.to_string(),
+            digits: digits.to_string(),
+            radix,
+            float,
+            suffix,
+        })
+    }
+
+    fn lex(program: &str) -> (Vec<Token>, DiagnosticSink) {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let tokens = lexer.tokens.iter().map(|t| t.token.clone()).collect();
+        (tokens, diagnostics)
+    }
+
+    // a synthetic program made of `copies` functions
+    fn synthetic_program(copies: usize) -> String {
+        let mut program = String::new();
+        for i in 0..copies {
+            program += &format!(
+                "// function number {}\nconst f = fn(x: i32) {{\n    var y: i32 = x + {}.5 * 2;\n    print(\"result \\n\", y);\n}}\n",
+                i, i
+            );
+        }
+        program
+    }
+
+    // the fastest of a few runs so we aren't at the mercy of the scheduler
+    fn time_lex(program: &str) -> (Duration, usize) {
+        let mut best = Duration::MAX;
+        let mut tokens = 0;
+        for _ in 0..3 {
+            let diagnostics = DiagnosticSink::new();
+            let mut lexer = Lexer::new("bench.sy", &diagnostics);
+            let now = Instant::now();
+            lexer.lex(Box::new(program.to_string()));
+            best = best.min(now.elapsed());
+            tokens = lexer.tokens.len();
+            assert!(!diagnostics.has_errors());
         }
-        self.current += 1;
-        self.tokens.push(Token::STRING(s));
+        (best, tokens)
+    }
+
+    #[test]
+    fn keywords_need_a_word_boundary() {
+        let (tokens, _) = lex("variance format iffy u32_buf x1 if u32");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::IDENTIFIER("variance".to_string()),
+                Token::IDENTIFIER("format".to_string()),
+                Token::IDENTIFIER("iffy".to_string()),
+                Token::IDENTIFIER("u32_buf".to_string()),
+                Token::IDENTIFIER("x1".to_string()),
+                Token::IF,
+                Token::U32,
+            ]
+        );
+    }
+
+    #[test]
+    fn lexes_multi_char_operators() {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(
+            "== != < <= > >= && || ! -> => 0..10 |> :: += -= *= /= - = : . | &".to_string(),
+        ));
+        let tokens: Vec<Token> = lexer.tokens.iter().map(|t| t.token.clone()).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::EQUAL_EQUAL,
+                Token::NOT_EQUAL,
+                Token::LESS,
+                Token::LESS_EQUAL,
+                Token::GREATER,
+                Token::GREATER_EQUAL,
+                Token::AND,
+                Token::OR,
+                Token::NOT,
+                Token::ARROW,
+                Token::FAT_ARROW,
+                number("0", "0", 10, false, None),
+                Token::DOT_DOT,
+                number("10", "10", 10, false, None),
+                Token::PIPE,
+                Token::DOUBLE_COLON,
+                Token::PLUS_EQUAL,
+                Token::MINUS_EQUAL,
+                Token::STAR_EQUAL,
+                Token::DIV_EQUAL,
+                Token::MINUS,
+                Token::EQUAL,
+                Token::COLON,
+                Token::DOT,
+                Token::BAR,
+                Token::AMPERSAND,
+            ]
+        );
+        assert_eq!(lexer.tokens[12].position.col_start, 32);
+    }
+
+    #[test]
+    fn lexes_number_literals() {
+        let (tokens, diagnostics) =
+            lex("0xFF_u8 0b1010 0o17 1_000_000 1e-3 2.5E+2f64 10u32 4294967295 0..1");
+        assert!(!diagnostics.has_errors());
+        assert_eq!(
+            tokens,
+            vec![
+                number("0xFF_u8", "FF", 16, false, Some(Type::U8)),
+                number("0b1010", "1010", 2, false, None),
+                number("0o17", "17", 8, false, None),
+                number("1_000_000", "1000000", 10, false, None),
+                number("1e-3", "1e-3", 10, true, None),
+                number("2.5E+2f64", "2.5e+2", 10, true, Some(Type::F64)),
+                number("10u32", "10", 10, false, Some(Type::U32)),
+                number("4294967295", "4294967295", 10, false, None),
+                number("0", "0", 10, false, None),
+                Token::DOT_DOT,
+                number("1", "1", 10, false, None),
+            ]
+        );
+
+        let (_, diagnostics) = lex("0b102 10u7 1.5u8 0x");
+        let codes: Vec<&str> = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0004", "E0005", "E0005", "E0004"]);
+    }
+
+    #[test]
+    fn lexes_comments() {
+        let (tokens, diagnostics) =
+            lex("/* a /* nested */ comment */ x // line\n/// some docs\n///  indented\n//// not docs\ny");
+        assert!(!diagnostics.has_errors());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::IDENTIFIER("x".to_string()),
+                Token::DOC_COMMENT("some docs".to_string()),
+                Token::DOC_COMMENT(" indented".to_string()),
+                Token::IDENTIFIER("y".to_string()),
+            ]
+        );
+
+        let (_, diagnostics) = lex("x /* /* */");
+        assert_eq!(diagnostics.diagnostics()[0].code, "E0006");
+    }
+
+    #[test]
+    fn lexes_interpolated_strings() {
+        let (tokens, diagnostics) = lex("\"hi $name, ${a + 1} costs \\$5\"");
+        assert!(!diagnostics.has_errors());
+        let Token::INTERPOLATED_STRING(parts) = &tokens[0] else {
+            panic!("expected an interpolated string");
+        };
+        let parts: Vec<Result<String, Vec<Token>>> = parts
+            .iter()
+            .map(|part| match part {
+                StringPart::LITERAL(s) => Ok(s.to_string()),
+                StringPart::CODE(tokens) => Err(tokens.iter().map(|t| t.token.clone()).collect()),
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                Ok("hi ".to_string()),
+                Err(vec![Token::IDENTIFIER("name".to_string())]),
+                Ok(", ".to_string()),
+                Err(vec![
+                    Token::IDENTIFIER("a".to_string()),
+                    Token::PLUS,
+                    number("1", "1", 10, false, None),
+                ]),
+                Ok(" costs $5".to_string()),
+            ]
+        );
+        assert_eq!(tokens.len(), 1);
+    }
+
+    #[test]
+    fn lexes_chars_raw_strings_and_escapes() {
+        let (tokens, diagnostics) = lex(
+            "'a' '\\n' '\\u{1F600}' \"\\\\ \\0\\r \\x41 \\\"q\\\"\" r\"c:\\new\" r#\"say \"hi\"\nbye\"# r",
+        );
+        assert!(!diagnostics.has_errors());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::CHARACTER('a'),
+                Token::CHARACTER('\n'),
+                Token::CHARACTER('😀'),
+                Token::STRING("\\ \0\r A \"q\"".to_string()),
+                Token::STRING("c:\\new".to_string()),
+                Token::STRING("say \"hi\"\nbye".to_string()),
+                Token::IDENTIFIER("r".to_string()),
+            ]
+        );
+
+        let (tokens, diagnostics) = lex("\"\\q \\x80 \\u{110000} \\u{}\" '' 'ab' r#\"open");
+        let codes: Vec<&str> = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec!["E0003", "E0003", "E0003", "E0003", "E0008", "E0008", "E0002"]
+        );
+        // the escape errors point at just the escape
+        let position = diagnostics.diagnostics()[1]
+            .primary
+            .clone()
+            .unwrap()
+            .position;
+        assert_eq!((position.col_start, position.col_end), (5, 9));
+        assert_eq!(tokens.len(), 4);
+    }
+
+    #[test]
+    fn tracks_token_positions() {
+        let source = "var x = 1\n  x = \"hi\"\n";
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(source.to_string()));
+        let spans: Vec<(&str, String)> = lexer
+            .tokens
+            .iter()
+            .map(|t| {
+                (
+                    &source[t.position.start..t.position.end],
+                    t.position.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans[..7],
+            [
+                ("var", "test.sy:1:1".to_string()),
+                ("x", "test.sy:1:5".to_string()),
+                ("=", "test.sy:1:7".to_string()),
+                ("1", "test.sy:1:9".to_string()),
+                ("x", "test.sy:2:3".to_string()),
+                ("=", "test.sy:2:5".to_string()),
+                ("\"hi\"", "test.sy:2:7".to_string()),
+            ]
+        );
+
+        // a diagnostic over the joined span of the assignment points at it in the source
+        let assign = lexer.tokens[4].position.join(&lexer.tokens[6].position);
+        let rendered = Diagnostic::error("E0500", "mismatched types".to_string(), assign)
+            .with_label("expected `i32`, found `string`".to_string())
+            .with_secondary(
+                lexer.tokens[1].position.clone(),
+                "this is `i32`".to_string(),
+            )
+            .render(source);
+        assert_eq!(
+            rendered,
+            "error[E0500]: mismatched types
+ --> test.sy:2:3
+  |
+2 |   x = \"hi\"
+  |   ^^^^^^^^ expected `i32`, found `string`
+ ::: test.sy:1:5
+  |
+1 | var x = 1
+  |     - this is `i32`
+"
+        );
+    }
+
+    #[test]
+    fn trivia_tokens_cover_the_source() {
+        let source = "// comment\nconst x = 1 /* block */\n\t/// docs\nfn y {}\n";
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::with_trivia("test.sy", &diagnostics);
+        lexer.lex(Box::new(source.to_string()));
+        let rebuilt: String = lexer
+            .tokens
+            .iter()
+            .map(|t| &source[t.position.start..t.position.end])
+            .collect();
+        assert_eq!(rebuilt, source);
+        assert_eq!(
+            lexer.tokens[0].token,
+            Token::COMMENT("// comment".to_string())
+        );
+    }
+
+    // a benchmark rather than a check, the times depend on how busy the machine is
+    #[test]
+    #[ignore = "timing based, run with `cargo test -- --ignored`"]
+    fn lexing_is_linear_in_file_size() {
+        // roughly 200KB and 800KB of source
+        let small = synthetic_program(2_500);
+        let large = synthetic_program(10_000);
+
+        let (small_time, small_tokens) = time_lex(&small);
+        let (large_time, large_tokens) = time_lex(&large);
+        assert_eq!(small_tokens * 4, large_tokens);
+
+        // 4x the input should take about 4x as long, quadratic lexing would take 16x
+        let ratio = large_time.as_secs_f64() / small_time.as_secs_f64().max(1e-6);
+        assert!(
+            ratio < 10.0,
+            "lexing {} bytes took {:?} but {} bytes took {:?}",
+            small.len(),
+            small_time,
+            large.len(),
+            large_time
+        );
     }
 }
@@ -13,6 +13,7 @@ use crate::{
     codegen::llvm::LLVMCodeGenerator,
     codegen::x86::X86CodeGenerator,
     compiler::CompilerOptions,
+    diagnostic::DiagnosticSink,
     optimize::{GeneralPassIROptimizer, IROptimizer},
     symtable::SymTable,
 };
@@ -21,14 +22,17 @@ mod ast;
 mod codegen;
 mod compiler;
 mod comptime;
+mod diagnostic;
 mod ir;
 mod ir_interpret;
 mod ir_parse;
 mod lex;
 mod optimize;
 mod parse;
+mod resolve;
 mod symtable;
 mod token;
+mod typeck;
 mod types;
 
 const VERSION: &str = "0.0.1";
@@ -52,9 +56,19 @@ struct Args {
     optimize: Option<usize>,
 }
 
+// print any errors and warnings, returns true if we should stop compiling
+fn report_errors(diagnostics: &DiagnosticSink, source: &str) -> bool {
+    let has_errors = diagnostics.has_errors();
+    if !diagnostics.diagnostics().is_empty() {
+        eprint!("{}", diagnostics.render(source));
+        // so they aren't shown again by the next stage
+        diagnostics.clear();
+    }
+    has_errors
+}
+
 fn main() {
     let now = Instant::now();
-    std::env::set_var("RUST_BACKTRACE", "1");
     std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
 
@@ -67,7 +81,10 @@ fn main() {
             print!(">");
             io::stdout().flush();
             let mut line = String::new();
-            std::io::stdin().read_line(&mut line).unwrap();
+            if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                // end of input
+                break;
+            }
             let mut optimization: usize = 0;
             if let Some(o) = args.optimize {
                 optimization = o;
@@ -77,28 +94,39 @@ fn main() {
                 current_file: "<interpret>".to_string(),
             };
 
-            let mut lexer = lex::Lexer::new();
-            lexer.lex(Box::new(line));
+            let diagnostics = DiagnosticSink::new();
+
+            let mut lexer = lex::Lexer::new(&compiler_options.current_file, &diagnostics);
+            lexer.lex(Box::new(line.clone()));
 
             let mut parser = parse::Parser {
                 tokens: &lexer.tokens,
+                diagnostics: &diagnostics,
             };
-            let ast = parser.parse();
-
-            let mut ir_parser = ir_parse::IRParser {
-                compiler_options: &compiler_options,
-                counter: 0,
-                lambda_counter: 0,
-                block_counter: 0,
-                locals_counter: 0,
-            };
+            let mut ast = parser.parse();
+            if report_errors(&diagnostics, &line) {
+                continue;
+            }
+            resolve::Resolver::new(&diagnostics).resolve(&mut ast);
+            if report_errors(&diagnostics, &line) {
+                continue;
+            }
+            typeck::TypeChecker::new(&diagnostics).check(&mut ast);
+            if report_errors(&diagnostics, &line) {
+                continue;
+            }
+
+            let mut ir_parser = ir_parse::IRParser::new(&compiler_options, &diagnostics);
             let mut main_block = ir_parser.parse(ast);
-            let mut ir_interpreter = ir_interpret::IRInterpreter {
-                compiler_options: &compiler_options,
-                counter: 0,
-                variables_map: HashMap::new(),
-            };
+            if report_errors(&diagnostics, &line) {
+                continue;
+            }
+            let mut ir_interpreter =
+                ir_interpret::IRInterpreter::new(&compiler_options, &diagnostics);
             let result = ir_interpreter.execute(&main_block);
+            if report_errors(&diagnostics, &line) {
+                continue;
+            }
             println!("{:?}", result);
         }
         return;
@@ -113,25 +141,41 @@ fn main() {
         current_file: args.file.to_string(),
     };
 
-    let source = std::fs::read_to_string(args.file.to_string())
-        .expect("unable to read source file test.trove");
+    let source = match std::fs::read_to_string(args.file.to_string()) {
+        Ok(source) => source,
+        Err(err) => {
+            error!("unable to read source file {}: {}", args.file, err);
+            std::process::exit(1);
+        }
+    };
+
+    let diagnostics = DiagnosticSink::new();
 
-    let mut lexer = lex::Lexer::new();
-    lexer.lex(Box::new(source));
+    let mut lexer = lex::Lexer::new(&compiler_options.current_file, &diagnostics);
+    lexer.lex(Box::new(source.clone()));
 
     let mut parser = parse::Parser {
         tokens: &lexer.tokens,
+        diagnostics: &diagnostics,
     };
 
-    let ast = parser.parse();
-    let mut ir_parser = ir_parse::IRParser {
-        compiler_options: &compiler_options,
-        counter: 0,
-        lambda_counter: 0,
-        block_counter: 0,
-        locals_counter: 0,
-    };
+    let mut ast = parser.parse();
+    if report_errors(&diagnostics, &source) {
+        std::process::exit(1);
+    }
+    resolve::Resolver::new(&diagnostics).resolve(&mut ast);
+    if report_errors(&diagnostics, &source) {
+        std::process::exit(1);
+    }
+    typeck::TypeChecker::new(&diagnostics).check(&mut ast);
+    if report_errors(&diagnostics, &source) {
+        std::process::exit(1);
+    }
+    let mut ir_parser = ir_parse::IRParser::new(&compiler_options, &diagnostics);
     let mut main_block = ir_parser.parse(ast);
+    if report_errors(&diagnostics, &source) {
+        std::process::exit(1);
+    }
     if let Some(write_ir) = args.write_ir {
         if write_ir {
             let f: File = File::create("./build/build.sir").expect("unable to create file");
@@ -167,20 +211,27 @@ fn main() {
     match args.arch.as_str() {
         "x86" => {
             let mut code_generator = LLVMCodeGenerator {
+                diagnostics: &diagnostics,
+                position: None,
                 anon_local_counter: 0,
                 anon_string_counter: 0,
                 anon_local_block_counter: 0,
-                str_buffer: "".to_string(),
                 sym_table: SymTable::new(),
+                loops: vec![],
+                structs: HashMap::new(),
+                enums: HashMap::new(),
             };
             code_generator.generate(&main_block);
+            if report_errors(&diagnostics, &source) {
+                std::process::exit(1);
+            }
         }
         _ => {
             error!(
                 "unsupported format {:?}, supported formats are [x86]",
                 args.arch
             );
-            return;
+            std::process::exit(1);
         }
     }
     let elapsed = now.elapsed();
@@ -1,22 +1,30 @@
-use std::collections::HashMap;
-use std::process::id;
+use std::rc::Rc;
 use std::time::Instant;
 
 use log::debug;
 
 use crate::ast::{
-    Assign, Binary, Block, Call, Decl, ExpressionInstruction, ExpressionInstructionEnum, Fun, If,
-    LeftUnary, LhsAccess, Number, ParsedAST, Program, Qualifier, Typ,
+    Arg, Arm, Assign, Binary, Block, Call, Decl, For, Fun, Group, If, Index, LeftUnary, LhsAccess,
+    Match, Number, ParsedAST, ParsedASTKind, Pattern, PatternKind, Program, Qualifier,
+    StructLiteral, Typ, Variant, While,
 };
-use crate::token::Token;
-use crate::types::Type;
+use crate::diagnostic::{Diagnostic, DiagnosticSink, Severity};
+use crate::token::{NumberLiteral, Position, PositionedToken, StringPart, Token};
+use crate::types::{FnPrimative, Type};
 
 pub struct Parser<'a> {
-    pub tokens: &'a Box<Vec<Token>>,
+    pub tokens: &'a [PositionedToken],
+    pub diagnostics: &'a DiagnosticSink,
 }
 
-impl Parser<'_> {
-    pub fn parse(&mut self) -> Box<ParsedAST> {
+// syntax errors are passed back up to the statement we were parsing, which reports
+// them and skips ahead to the next statement (see Parser::synchronise)
+type ParseResult<'a> = Result<ParsedAST<'a>, ParseError>;
+// boxed as a diagnostic is a lot bigger than most of what we parse
+type ParseError = Box<Diagnostic>;
+
+impl<'a> Parser<'a> {
+    pub fn parse(&mut self) -> Box<ParsedAST<'a>> {
         let now = Instant::now();
         let ast = Box::new(self.parse_program());
         let elapsed = now.elapsed();
@@ -28,7 +36,7 @@ impl Parser<'_> {
         ast
     }
 
-    fn parse_program(&mut self) -> ParsedAST {
+    fn parse_program(&mut self) -> ParsedAST<'a> {
         let mut current: usize = 0;
         let mut body: Vec<ParsedAST> = vec![];
 
@@ -36,61 +44,330 @@ impl Parser<'_> {
             body.push(self.statement(&mut current));
         }
 
-        return ParsedAST::PROGRAM(Program { body: body });
+        let position = self.span(0, &current);
+        return ParsedAST::new(ParsedASTKind::PROGRAM(Program { body: body }), position);
+    }
+
+    // parse a statement, if it has a syntax error we report it and return an error node
+    // so we can carry on and find the rest of the errors
+    fn statement(&self, current: &mut usize) -> ParsedAST<'a> {
+        let start = *current;
+        let doc = self.doc_comment(current);
+        if doc.is_some() && (self.end(current) || self.expecting(Token::RCURLY, current)) {
+            let position = self.span(start, current);
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0104",
+                    "expected a declaration after doc comment".to_string(),
+                    position.clone(),
+                )
+                .with_label("this doc comment doesn't document anything".to_string()),
+            );
+            return ParsedAST::new(ParsedASTKind::ERROR, position);
+        }
+        let doc_end = *current;
+        match self.try_statement(current) {
+            Ok(mut statement) => {
+                if let Some(doc) = doc {
+                    if !attach_doc(&mut statement, doc) {
+                        self.diagnostics.emit(
+                            Diagnostic::new(
+                                Severity::WARNING,
+                                "W0100",
+                                "unused doc comment".to_string(),
+                            )
+                            .with_primary(self.span(start, &doc_end), "".to_string())
+                            .with_note(
+                                "doc comments can only be put on declarations and functions"
+                                    .to_string(),
+                            ),
+                        );
+                    }
+                }
+                statement
+            }
+            Err(diagnostic) => {
+                self.diagnostics.emit(*diagnostic);
+                self.synchronise(start, current);
+                ParsedAST::new(ParsedASTKind::ERROR, self.span(start, current))
+            }
+        }
+    }
+
+    // consecutive `///` comments are joined into one doc string
+    fn doc_comment(&self, current: &mut usize) -> Option<String> {
+        let mut lines: Vec<&str> = vec![];
+        while let Some(PositionedToken {
+            token: Token::DOC_COMMENT(line),
+            ..
+        }) = self.tokens.get(*current)
+        {
+            lines.push(line);
+            *current += 1;
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    // skip tokens until we get to something that looks like the start of the next statement.
+    // we stop before a `}` that closes the block we are in, after a `;`, or before a
+    // keyword that starts a declaration (as long as we aren't inside braces opened in
+    // the statement we are skipping)
+    fn synchronise(&self, start: usize, current: &mut usize) {
+        // always make progress, otherwise we would keep failing on the same token
+        if *current == start && !self.end(current) {
+            *current += 1;
+        }
+        let mut depth = 0;
+        while let Some(t) = self.tokens.get(*current) {
+            match t.token {
+                Token::SEMICOLON if depth == 0 => {
+                    *current += 1;
+                    return;
+                }
+                Token::LCURLY => depth += 1,
+                Token::RCURLY => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                }
+                Token::FN
+                | Token::CONST
+                | Token::VAR
+                | Token::IF
+                | Token::FOR
+                | Token::WHILE
+                | Token::RET
+                | Token::DOC_COMMENT(_)
+                    if depth == 0 =>
+                {
+                    return
+                }
+                _ => {}
+            }
+            *current += 1;
+        }
     }
 
-    fn statement(&self, current: &mut usize) -> ParsedAST {
-        match self.peek(&current) {
+    fn try_statement(&self, current: &mut usize) -> ParseResult<'a> {
+        match self.peek(&current)? {
             // Token::LCURLY => self.block(current),
             Token::IF => self.if_stmt(current),
-            // Token::FOR => self.for_stmt(current),
-            // Token::RET => self.ret(current),
-            _ => ParsedAST::STMT(Box::new(self.expression(current))),
+            Token::WHILE => self.while_stmt(current),
+            Token::FOR => self.for_stmt(current),
+            Token::BREAK | Token::CONTINUE => {
+                let start = *current;
+                let kind = match self.consume(current)? {
+                    Token::BREAK => ParsedASTKind::BREAK,
+                    _ => ParsedASTKind::CONTINUE,
+                };
+                Ok(ParsedAST::new(kind, self.span(start, current)))
+            }
+            Token::RET => self.ret(current),
+            _ => {
+                let expression = self.expression(current)?;
+                let position = expression.position.clone();
+                Ok(ParsedAST::new(
+                    ParsedASTKind::STMT(Box::new(expression)),
+                    position,
+                ))
+            }
         }
     }
 
-    fn if_stmt(&self, current: &mut usize) -> ParsedAST {
-        self.consume(current); // consume the if
-        let condition = Box::new(self.expression(current));
+    fn if_stmt(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the if
+        let condition = Box::new(self.condition(current)?);
         let body = Box::new(self.statement(current));
         let mut else_body: Option<Box<ParsedAST>> = None;
 
         if !self.end(current) && self.expecting(Token::ELSE, current) {
-            self.consume(current); // consume the else
+            self.consume(current)?; // consume the else
             else_body = Some(Box::new(self.statement(current)));
         }
 
-        return ParsedAST::IF(If {
-            condition,
-            body,
-            else_body,
-        });
+        return Ok(ParsedAST::new(
+            ParsedASTKind::IF(If {
+                condition,
+                body,
+                else_body,
+            }),
+            self.span(start, current),
+        ));
+    }
+
+    // the condition of an if, while or for ends at the `{` of its body, so that
+    // `if p { ... }` isn't read as the struct literal `p{ ... }`
+    fn condition(&self, current: &mut usize) -> ParseResult<'a> {
+        let mut end = *current;
+        let mut depth = 0;
+        while let Some(next) = self.tokens.get(end) {
+            match next.token {
+                Token::LPAREN | Token::LBRACKET => depth += 1,
+                Token::RPAREN | Token::RBRACKET => depth -= 1,
+                Token::LCURLY if depth == 0 => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        // indices into the slice are the same as into the whole file
+        let parser = Parser {
+            tokens: &self.tokens[..end],
+            diagnostics: self.diagnostics,
+        };
+        parser.expression(current)
+    }
+
+    fn while_stmt(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the while
+        let condition = Box::new(self.condition(current)?);
+        let body = Box::new(self.statement(current));
+        Ok(ParsedAST::new(
+            ParsedASTKind::WHILE(While { condition, body }),
+            self.span(start, current),
+        ))
+    }
+
+    fn for_stmt(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the for
+        let variable = self.consume_identifier(current)?;
+        self.consume_expected(current, Token::IN)?;
+        let iterable = Box::new(self.condition(current)?);
+        let body = Box::new(self.statement(current));
+        Ok(ParsedAST::new(
+            ParsedASTKind::FOR(For {
+                variable,
+                iterable,
+                body,
+            }),
+            self.span(start, current),
+        ))
     }
 
-    fn expression(&self, current: &mut usize) -> ParsedAST {
-        match self.peek(&current) {
-            _ => self.comparison(current),
+    fn ret(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the ret
+                                // a bare ret is followed by the end of the block
+        let mut value = None;
+        if !self.end(current)
+            && !self.expecting(Token::RCURLY, current)
+            && !self.expecting(Token::SEMICOLON, current)
+        {
+            value = Some(Box::new(self.expression(current)?));
         }
+        Ok(ParsedAST::new(
+            ParsedASTKind::RET(value),
+            self.span(start, current),
+        ))
     }
 
-    fn comparison(&self, current: &mut usize) -> ParsedAST {
-        // todo!
-        self.decl_or_assign(current)
+    fn expression(&self, current: &mut usize) -> ParseResult<'a> {
+        match self.peek(&current)? {
+            _ => self.decl_or_assign(current),
+        }
     }
 
-    fn parse_type(&self, current: &mut usize) -> Type {
-        match self.consume(current) {
-            Token::I32 => Type::I32,
-            Token::TYPE => Type::TYPE,
-            Token::IDENTIFIER(i) => Type::STRUCT(i.to_string()),
-            _ => panic!(),
+    fn parse_type(&self, current: &mut usize) -> Result<Type, ParseError> {
+        match self.consume(current)? {
+            Token::TYPE => Ok(Type::TYPE),
+            // the type of a function value, i.e. `fn(i32, i32) -> i32`
+            Token::FN => {
+                self.consume_expected(current, Token::LPAREN)?;
+                let mut args: Vec<Type> = vec![];
+                // the params can be named, i.e. `fn(x: i32) -> i32`
+                let mut names: Vec<String> = vec![];
+                while !self.expecting(Token::RPAREN, current) {
+                    if let (Token::IDENTIFIER(name), Token::COLON) =
+                        (self.peek(current)?, self.peek_ahead(current, 1)?)
+                    {
+                        if names.len() != args.len() {
+                            return Err(self.unnamed_param(current));
+                        }
+                        names.push(name.to_string());
+                        self.consume(current)?;
+                        self.consume(current)?;
+                    } else if !names.is_empty() {
+                        return Err(self.unnamed_param(current));
+                    }
+                    args.push(self.parse_type(current)?);
+                    if !self.expecting(Token::RPAREN, current) {
+                        self.consume_expected(current, Token::COMMA)?;
+                    }
+                }
+                self.consume_expected(current, Token::RPAREN)?;
+                let mut return_type = None;
+                if self.expecting(Token::ARROW, current) {
+                    self.consume(current)?;
+                    return_type = Some(Box::new(self.parse_type(current)?));
+                }
+                Ok(Type::FN(FnPrimative {
+                    args,
+                    return_type,
+                    names,
+                }))
+            }
+            // `string` isn't a keyword so it can still name things, it's only a type here
+            Token::IDENTIFIER(i) if i == "string" => Ok(Type::STRING),
+            Token::IDENTIFIER(i) => Ok(Type::STRUCT(i.to_string())),
+            Token::STAR => Ok(Type::POINTER(Box::new(self.parse_type(current)?))),
+            Token::AMPERSAND => Ok(Type::REFERENCE(Box::new(self.parse_type(current)?))),
+            // `?*T`, a pointer that may be null
+            Token::QUESTION => {
+                let position = self.position(current);
+                let inner = self.parse_type(current)?;
+                if inner.pointee().is_none() {
+                    return Err(Box::new(
+                        Diagnostic::error(
+                            "E0108",
+                            format!("`{}` cannot be optional", inner),
+                            position.join(&self.position(&(*current - 1))),
+                        )
+                        .with_label("only pointers can be optional".to_string()),
+                    ));
+                }
+                Ok(Type::OPTIONAL(Box::new(inner)))
+            }
+            // `[4]i32` is an array and `[i32]` is a slice
+            Token::LBRACKET => {
+                if let Token::NUMBER(literal) = self.peek(current)? {
+                    let position = self.position(current);
+                    self.consume(current)?;
+                    let Number::INTEGER(len, _) = self.number(literal, &position, false)? else {
+                        return Err(Box::new(Diagnostic::error(
+                            "E0107",
+                            "the length of an array must be a whole number".to_string(),
+                            position,
+                        )));
+                    };
+                    self.consume_expected(current, Token::RBRACKET)?;
+                    return Ok(Type::ARRAY(
+                        Box::new(self.parse_type(current)?),
+                        len as usize,
+                    ));
+                }
+                let element = self.parse_type(current)?;
+                self.consume_expected(current, Token::RBRACKET)?;
+                Ok(Type::SLICE(Box::new(element)))
+            }
+            t => match primitive_type(t) {
+                Some(typ) => Ok(typ),
+                None => Err(self.unexpected(t, "a type", &(*current - 1))),
+            },
         }
     }
 
-    fn decl_or_assign(&self, current: &mut usize) -> ParsedAST {
+    fn decl_or_assign(&self, current: &mut usize) -> ParseResult<'a> {
         // self.assign(current)
         // todo
-        let first = self.peek(current);
+        let start = *current;
+        let first = self.peek(current)?;
 
         if self.end_ahead(current, 1) {
             return self.assign(current);
@@ -100,70 +377,85 @@ impl Parser<'_> {
 
         match first {
             Token::CONST => {
-                self.consume(current);
-                let identifier: String;
-                match self.consume(current) {
-                    Token::IDENTIFIER(i) => identifier = i.to_string(),
-                    _ => panic!(),
-                }
+                self.consume(current)?;
+                let identifier = self.consume_identifier(current)?;
+                let position = self.position(&(*current - 1));
 
                 let mut typ: Option<Type> = None;
                 if self.expecting(Token::COLON, current) {
                     // get the type
-                    self.consume(current);
-                    typ = Some(self.parse_type(current));
+                    self.consume(current)?;
+                    typ = Some(self.parse_type(current)?);
                 }
 
-                self.consume(current); // consume the =
-                let value = self.expression(current);
+                self.consume_expected(current, Token::EQUAL)?;
+                let value = self.expression(current)?;
 
                 debug!("parsing decl type! {:?}", typ);
-                return ParsedAST::DECL(Decl {
-                    identifier,
-                    qualifier: Qualifier::CONST,
-                    requires_infering: true,
-                    typ: typ,
-                    value: Some(Box::new(value)),
-                });
+                return Ok(ParsedAST::new(
+                    ParsedASTKind::DECL(Decl {
+                        identifier,
+                        position,
+                        qualifier: Qualifier::CONST,
+                        requires_infering: typ.is_none(),
+                        typ: typ,
+                        value: Some(Box::new(value)),
+                        doc: None,
+                    }),
+                    self.span(start, current),
+                ));
             }
             Token::VAR => {
-                self.consume(current);
-                let identifier: String;
-                match self.consume(current) {
-                    Token::IDENTIFIER(i) => identifier = i.to_string(),
-                    _ => panic!(),
-                }
+                self.consume(current)?;
+                let identifier = self.consume_identifier(current)?;
+                let position = self.position(&(*current - 1));
 
                 let mut typ: Option<Type> = None;
                 if self.expecting(Token::COLON, current) {
                     // get the type
-                    self.consume(current);
-                    typ = Some(self.parse_type(current));
+                    self.consume(current)?;
+                    typ = Some(self.parse_type(current)?);
                 }
 
-                self.consume(current); // consume the =
-                let value = self.expression(current);
+                // a variable with a type can be left to be zeroed, i.e. `var p: Point`
+                let mut value = None;
+                if typ.is_none() || self.expecting(Token::EQUAL, current) {
+                    self.consume_expected(current, Token::EQUAL)?;
+                    value = Some(Box::new(self.expression(current)?));
+                }
 
-                return ParsedAST::DECL(Decl {
-                    identifier,
-                    qualifier: Qualifier::VAR,
-                    typ: typ,
-                    requires_infering: true,
-                    value: Some(Box::new(value)),
-                });
+                return Ok(ParsedAST::new(
+                    ParsedASTKind::DECL(Decl {
+                        identifier,
+                        position,
+                        qualifier: Qualifier::VAR,
+                        requires_infering: typ.is_none(),
+                        typ: typ,
+                        value,
+                        doc: None,
+                    }),
+                    self.span(start, current),
+                ));
             }
             Token::IDENTIFIER(identifier) => {
-                match self.peek_ahead(current, 1) {
+                match self.peek_ahead(current, 1)? {
                     Token::EQUAL => {
                         // doing assign
-                        self.consume(current);
+                        self.consume(current)?;
                         // consume the =
-                        self.consume(current);
-                        let rhs = self.expression(current);
-                        return ParsedAST::ASSIGN(Assign {
-                            lhs: Box::new(ParsedAST::IDENTIFIER(identifier.to_string())),
-                            rhs: Box::new(rhs),
-                        });
+                        self.consume(current)?;
+                        let rhs = self.expression(current)?;
+                        let lhs = ParsedAST::new(
+                            ParsedASTKind::IDENTIFIER(identifier.to_string()),
+                            self.position(&start),
+                        );
+                        return Ok(ParsedAST::new(
+                            ParsedASTKind::ASSIGN(Assign {
+                                lhs: Box::new(lhs),
+                                rhs: Box::new(rhs),
+                            }),
+                            self.span(start, current),
+                        ));
                     }
                     _ => return self.assign(current),
                 }
@@ -269,23 +561,27 @@ impl Parser<'_> {
         // }
     }
 
-    fn assign(&self, current: &mut usize) -> ParsedAST {
-        let higher_precedence = self.expression_instructions(current);
+    fn assign(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        let higher_precedence = self.expression_instructions(current)?;
         if !self.end(current) {
             if self.expecting(Token::EQUAL, current) {
-                self.consume(current);
-                let rhs = self.expression_instructions(current);
-                return ParsedAST::ASSIGN(Assign {
-                    lhs: Box::new(higher_precedence),
-                    rhs: Box::new(rhs),
-                });
+                self.consume(current)?;
+                let rhs = self.expression_instructions(current)?;
+                return Ok(ParsedAST::new(
+                    ParsedASTKind::ASSIGN(Assign {
+                        lhs: Box::new(higher_precedence),
+                        rhs: Box::new(rhs),
+                    }),
+                    self.span(start, current),
+                ));
             }
         }
-        higher_precedence
+        Ok(higher_precedence)
     }
 
     // e.g. comp 1+2
-    fn expression_instructions(&self, current: &mut usize) -> ParsedAST {
+    fn expression_instructions(&self, current: &mut usize) -> ParseResult<'a> {
         // if !self.end(&current) {
         //     if self.expecting(Token::COMP, current) {
         //         self.consume(current);
@@ -296,194 +592,450 @@ impl Parser<'_> {
         //         });
         //     }
         // }
-        self.plus_or_minus(current)
+        self.binary(current, 0)
     }
 
-    fn plus_or_minus(&self, current: &mut usize) -> ParsedAST {
-        let higher_precedence = self.mul_or_div(current);
+    // precedence climbing, only operators that bind at least as tightly as
+    // min_precedence are folded into the left operand
+    fn binary(&self, current: &mut usize, min_precedence: u8) -> ParseResult<'a> {
+        let start = *current;
+        let mut left = self.unary(current)?;
+
+        while !self.end(current) {
+            let Some(precedence) = binary_precedence(self.peek(current)?) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            let token = self.consume(current)?;
+            self.not_statement(current)?;
+            // every binary operator is left associative, so the right operand may only
+            // contain operators that bind tighter than this one
+            let right = self.binary(current, precedence + 1)?;
+            left = ParsedAST::new(
+                ParsedASTKind::BINARY(Binary {
+                    left: Box::new(left),
+                    op: token,
+                    right: Box::new(right),
+                }),
+                self.span(start, current),
+            );
+        }
+        Ok(left)
+    }
 
-        if !self.end(current) {
-            match self.peek(current) {
-                Token::PLUS | Token::MINUS => {
-                    let token = self.consume(current);
-                    let right = self.expression(current);
-                    return ParsedAST::BINARY(Binary {
-                        left: Box::new(higher_precedence),
-                        op: token,
-                        right: Box::new(right),
-                    });
-                }
-                _ => return higher_precedence,
+    fn unary(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        if self.expecting(Token::COMP, current) {
+            self.consume(current)?;
+            let rhs = self.expression(current)?;
+            return Ok(ParsedAST::new(
+                ParsedASTKind::LEFT_UNARY(LeftUnary::COMP(Box::new(rhs))),
+                self.span(start, current),
+            ));
+        }
+
+        match self.peek(current)? {
+            // a negative number is checked against its type after it's negated, so `-128i8`
+            // is in range
+            Token::MINUS if matches!(self.peek_ahead(current, 1)?, Token::NUMBER(_)) => {
+                self.consume(current)?;
+                let number_start = *current;
+                let Token::NUMBER(literal) = self.consume(current)? else {
+                    unreachable!()
+                };
+                let position = self.span(number_start, current);
+                let number = self.number(literal, &position, true)?;
+                let operand = ParsedAST::new(ParsedASTKind::NUMBER(number), position);
+                Ok(ParsedAST::new(
+                    ParsedASTKind::LEFT_UNARY(LeftUnary::NEG(Box::new(operand))),
+                    self.span(start, current),
+                ))
             }
+            Token::MINUS | Token::NOT | Token::AMPERSAND | Token::STAR => {
+                let token = self.consume(current)?;
+                let operand = Box::new(self.unary(current)?);
+                let unary = match token {
+                    Token::MINUS => LeftUnary::NEG(operand),
+                    Token::AMPERSAND => LeftUnary::ADDRESS(operand),
+                    Token::STAR => LeftUnary::DEREF(operand),
+                    _ => LeftUnary::NOT(operand),
+                };
+                Ok(ParsedAST::new(
+                    ParsedASTKind::LEFT_UNARY(unary),
+                    self.span(start, current),
+                ))
+            }
+            _ => self.call(current),
         }
-        higher_precedence
     }
 
-    fn mul_or_div(&self, current: &mut usize) -> ParsedAST {
-        let higher_precedence = self.unary(current);
+    fn call(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        let higher_precedence = self.single(current)?;
+        self.postfix(higher_precedence, start, current)
+    }
 
-        if !self.end(current) {
-            match self.peek(current) {
-                Token::STAR | Token::DIV => {
-                    let token = self.consume(current);
-                    let right = self.expression(current);
-                    return ParsedAST::BINARY(Binary {
-                        left: Box::new(higher_precedence),
-                        op: token,
-                        right: Box::new(right),
-                    });
+    // the args of a call, i.e. `(a=1, b=2)`
+    fn args(&self, current: &mut usize) -> Result<Vec<Arg<'a>>, ParseError> {
+        self.delimited(current, Token::RPAREN, |current| {
+            let mut args: Vec<Arg> = vec![];
+            while !self.expecting(Token::RPAREN, current) {
+                args.push(self.arg(current)?);
+                if !self.expecting(Token::RPAREN, current) {
+                    self.consume_expected(current, Token::COMMA)?;
                 }
-                _ => return higher_precedence,
+            }
+            Ok(args)
+        })
+    }
+
+    // `name=value` or just `value`
+    fn arg(&self, current: &mut usize) -> Result<Arg<'a>, ParseError> {
+        let start = *current;
+        let mut name = None;
+        if let Token::IDENTIFIER(identifier) = self.peek(current)? {
+            if !self.end_ahead(current, 1) && *self.peek_ahead(current, 1)? == Token::EQUAL {
+                name = Some(identifier.to_string());
+                self.consume(current)?; // consume the name
+                self.consume(current)?; // consume the =
             }
         }
-        higher_precedence
+        let value = self.nested_expression(current)?;
+        Ok(Arg {
+            name,
+            value,
+            position: self.span(start, current),
+        })
     }
 
-    fn unary(&self, current: &mut usize) -> ParsedAST {
-        // todo &some_var
-        if self.expecting(Token::COMP, current) {
-            self.consume(current);
-            let rhs = self.expression(current);
-            return ParsedAST::LEFT_UNARY(LeftUnary::COMP(Box::new(rhs)));
+    // calls, field accesses, indexing and unwrapping, `a.b[1](x).c` is `(((a.b)[1])(x)).c`
+    fn postfix(
+        &self,
+        mut left: ParsedAST<'a>,
+        start: usize,
+        current: &mut usize,
+    ) -> ParseResult<'a> {
+        while !self.end(current) {
+            if self.expecting(Token::DOT, current) {
+                self.consume(current)?; // consume the dot
+                if self.expecting(Token::QUESTION, current) {
+                    self.consume(current)?;
+                    left = ParsedAST::new(
+                        ParsedASTKind::UNWRAP(Box::new(left)),
+                        self.span(start, current),
+                    );
+                    continue;
+                }
+                let field = self.consume_identifier(current)?;
+                left = ParsedAST::new(
+                    ParsedASTKind::LHS_ACCESS(LhsAccess {
+                        left: Box::new(left),
+                        right: field,
+                    }),
+                    self.span(start, current),
+                );
+            } else if self.expecting(Token::LBRACKET, current) {
+                let index = self.delimited(current, Token::RBRACKET, |current| {
+                    self.nested_expression(current)
+                })?;
+                left = ParsedAST::new(
+                    ParsedASTKind::INDEX(Index {
+                        left: Box::new(left),
+                        index: Box::new(index),
+                    }),
+                    self.span(start, current),
+                );
+            } else if self.expecting(Token::LPAREN, current) && self.callable(current) {
+                let args = self.args(current)?;
+                left = ParsedAST::new(
+                    ParsedASTKind::CALL(Call {
+                        callee: Box::new(left),
+                        args,
+                    }),
+                    self.span(start, current),
+                );
+            } else {
+                break;
+            }
         }
+        Ok(left)
+    }
 
-        self.call(current)
+    // a `(` calls what is right before it on the same line if that is a name, an index, a call
+    // or something in parentheses. anything else starts the next statement, like the `(` in
+    // `f(x)` followed by `(*p).y = 1` on the next line
+    fn callable(&self, current: &usize) -> bool {
+        let (Some(before), Some(paren)) = (
+            current.checked_sub(1).and_then(|i| self.tokens.get(i)),
+            self.tokens.get(*current),
+        ) else {
+            return false;
+        };
+        matches!(
+            before.token,
+            Token::IDENTIFIER(_) | Token::RBRACKET | Token::RPAREN
+        ) && before.position.line_end == paren.position.line_start
     }
 
-    fn call(&self, current: &mut usize) -> ParsedAST {
-        let higher_presedence = self.struct_access(current);
-        if !self.end(current) {
-            match self.peek_ahead(current, -1) {
-                Token::IDENTIFIER(_) => {
-                    if !self.end_ahead(current, 1) {
-                        // todo peak_ahead could fail :(
-                        match self.peek(current) {
-                            Token::LPAREN => {
-                                self.consume(current);
-                                let mut args: Vec<ParsedAST> = vec![];
-                                while !self.expecting(Token::RPAREN, current) {
-                                    args.push(self.expression(current));
-                                    if !self.expecting(Token::RPAREN, current) {
-                                        self.consume(current); // consume the ,
-                                    }
-                                }
-                                self.consume(current); // consume the )
-                                return ParsedAST::CALL(Call {
-                                    callee: Box::new(higher_presedence),
-                                    args,
-                                });
-                            }
-                            _ => return higher_presedence,
-                        }
+    // `Point{x=1, y=2}`, the name has already been consumed
+    fn struct_literal(&self, name: &str, start: usize, current: &mut usize) -> ParseResult<'a> {
+        self.consume_expected(current, Token::LCURLY)?;
+        let mut fields: Vec<Arg> = vec![];
+        while !self.expecting(Token::RCURLY, current) {
+            fields.push(self.arg(current)?);
+            if !self.expecting(Token::RCURLY, current) {
+                self.consume_expected(current, Token::COMMA)?;
+            }
+        }
+        self.consume_expected(current, Token::RCURLY)?;
+        Ok(ParsedAST::new(
+            ParsedASTKind::STRUCT_LITERAL(StructLiteral {
+                name: name.to_string(),
+                fields,
+            }),
+            self.span(start, current),
+        ))
+    }
+
+    // `Shape::Circle(2.0)`, the name of the enum has already been consumed
+    fn variant(&self, enum_name: &str, start: usize, current: &mut usize) -> ParseResult<'a> {
+        self.consume_expected(current, Token::DOUBLE_COLON)?;
+        let variant = self.consume_identifier(current)?;
+        let mut payload: Vec<ParsedAST> = vec![];
+        if !self.end(current) && self.expecting(Token::LPAREN, current) {
+            self.consume(current)?;
+            while !self.expecting(Token::RPAREN, current) {
+                payload.push(self.nested_expression(current)?);
+                if !self.expecting(Token::RPAREN, current) {
+                    self.consume_expected(current, Token::COMMA)?;
+                }
+            }
+            self.consume_expected(current, Token::RPAREN)?;
+        }
+        Ok(ParsedAST::new(
+            ParsedASTKind::VARIANT(Variant {
+                enum_name: enum_name.to_string(),
+                variant,
+                payload,
+            }),
+            self.span(start, current),
+        ))
+    }
+
+    // `enum { Circle(f64) Rect(f64, f64) Empty }`
+    fn enum_type(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the enum
+        self.consume_expected(current, Token::LCURLY)?;
+        let mut variants: Vec<(String, Vec<Type>)> = vec![];
+        while !self.expecting(Token::RCURLY, current) {
+            let variant_start = *current;
+            let name = self.consume_identifier(current)?;
+            // the rest of the enum is still fine, so we carry on
+            let duplicate = variants.iter().any(|(variant, _)| *variant == name);
+            if duplicate {
+                self.diagnostics.emit(Diagnostic::error(
+                    "E0109",
+                    format!("variant `{}` is declared more than once", name),
+                    self.position(&variant_start),
+                ));
+            }
+            let mut payload: Vec<Type> = vec![];
+            if self.expecting(Token::LPAREN, current) {
+                self.consume(current)?;
+                while !self.expecting(Token::RPAREN, current) {
+                    payload.push(self.parse_type(current)?);
+                    if !self.expecting(Token::RPAREN, current) {
+                        self.consume_expected(current, Token::COMMA)?;
                     }
                 }
-                _ => return higher_presedence,
+                self.consume_expected(current, Token::RPAREN)?;
+            }
+            if !duplicate {
+                variants.push((name, payload));
+            }
+            // variants can be separated by commas
+            if self.expecting(Token::COMMA, current) {
+                self.consume(current)?;
             }
         }
+        self.consume_expected(current, Token::RCURLY)?;
+        Ok(ParsedAST::new(
+            ParsedASTKind::ENUM(variants),
+            self.span(start, current),
+        ))
+    }
 
-        higher_presedence
+    // `match shape { Shape::Circle(r) => r * r, _ => 0.0 }`
+    fn match_expr(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        self.consume(current)?; // consume the match
+        let scrutinee = Box::new(self.condition(current)?);
+        self.consume_expected(current, Token::LCURLY)?;
+        let mut arms: Vec<Arm> = vec![];
+        while !self.expecting(Token::RCURLY, current) {
+            let pattern = self.pattern(current)?;
+            self.consume_expected(current, Token::FAT_ARROW)?;
+            let body = Box::new(self.expression(current)?);
+            arms.push(Arm { pattern, body });
+            // arms can be separated by commas
+            if self.expecting(Token::COMMA, current) {
+                self.consume(current)?;
+            }
+        }
+        self.consume_expected(current, Token::RCURLY)?;
+        Ok(ParsedAST::new(
+            ParsedASTKind::MATCH(Match { scrutinee, arms }),
+            self.span(start, current),
+        ))
     }
 
-    fn struct_access(&self, current: &mut usize) -> ParsedAST {
-        let higher_precedence = self.single(current);
-        if !self.end(current) {
-            match self.peek(current) {
-                Token::DOT => {
-                    self.consume(current); // consume the dot
-                    let rhs = self.expression(current);
-                    return ParsedAST::LHS_ACCESS(LhsAccess {
-                        left: Box::new(higher_precedence),
-                        right: Box::new(rhs),
-                    });
+    fn pattern(&self, current: &mut usize) -> Result<Pattern<'a>, ParseError> {
+        let start = *current;
+        let kind = match self.peek(current)? {
+            Token::IDENTIFIER(name) if name == "_" => {
+                self.consume(current)?;
+                PatternKind::WILDCARD
+            }
+            Token::IDENTIFIER(name) => {
+                self.consume(current)?;
+                if !self.expecting(Token::DOUBLE_COLON, current) {
+                    PatternKind::BINDING(name.to_string())
+                } else {
+                    self.consume(current)?;
+                    let variant = self.consume_identifier(current)?;
+                    let mut payload: Vec<Pattern> = vec![];
+                    if self.expecting(Token::LPAREN, current) {
+                        self.consume(current)?;
+                        while !self.expecting(Token::RPAREN, current) {
+                            payload.push(self.pattern(current)?);
+                            if !self.expecting(Token::RPAREN, current) {
+                                self.consume_expected(current, Token::COMMA)?;
+                            }
+                        }
+                        self.consume_expected(current, Token::RPAREN)?;
+                    }
+                    PatternKind::VARIANT(name.to_string(), variant, payload)
                 }
-                _ => return higher_precedence,
             }
-        }
-        higher_precedence
+            Token::NUMBER(_) | Token::CHARACTER(_) | Token::TRUE | Token::FALSE => {
+                PatternKind::LITERAL(Box::new(self.single(current)?))
+            }
+            // a negative number
+            Token::MINUS if matches!(self.peek_ahead(current, 1)?, Token::NUMBER(_)) => {
+                PatternKind::LITERAL(Box::new(self.unary(current)?))
+            }
+            t => return Err(self.unexpected(t, "a pattern", current)),
+        };
+        Ok(Pattern {
+            kind,
+            position: self.span(start, current),
+        })
     }
 
-    fn single(&self, current: &mut usize) -> ParsedAST {
-        match self.peek(current) {
+    fn single(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        match self.peek(current)? {
             Token::FN => {
-                self.consume(current);
-                let identifier = self.consume(current);
+                self.consume(current)?;
+                // without a name it is a lambda, i.e. `fn(x: i32) -> i32 { x * 2 }`
+                let identifier = match self.peek(current)? {
+                    Token::IDENTIFIER(_) => Some(self.consume_identifier(current)?),
+                    _ => None,
+                };
 
                 // do params
 
                 let mut params: Vec<Decl<'_>> = vec![];
                 if self.expecting(Token::LPAREN, current) {
-                    self.consume(current);
+                    self.consume(current)?;
 
                     loop {
                         if self.expecting(Token::RPAREN, current) {
-                            self.consume(current);
+                            self.consume(current)?;
                             break;
                         }
                         // do a decl
-                        let identifier = self.consume(current);
-                        self.consume(current);
-                        let typ = self.parse_type(current);
-
-                        let Token::IDENTIFIER(i) = identifier else {
-                            // todo do this more:)
-                            panic!("expected identifier");
-                        };
+                        let identifier = self.consume_identifier(current)?;
+                        let position = self.position(&(*current - 1));
+                        self.consume_expected(current, Token::COLON)?;
+                        let typ = self.parse_type(current)?;
 
                         params.push(Decl {
-                            identifier: i.to_string(),
+                            identifier,
+                            position,
                             qualifier: Qualifier::CONST,
                             typ: Some(typ),
                             requires_infering: false,
                             value: None,
+                            doc: None,
                         });
 
                         if !self.expecting(Token::RPAREN, current) {
-                            // todo we need to verify were consuming the right thing
-                            self.consume(current); // consume the ,
+                            self.consume_expected(current, Token::COMMA)?;
                         }
                     }
                 }
 
-                if let Token::IDENTIFIER(i) = identifier {
-                    return ParsedAST::FN(Fun {
-                        identifier: Some(i.to_string()),
-                        params: params,
-                        body: Box::new(self.statement(current)),
-                    });
+                let mut return_type = None;
+                if self.expecting(Token::ARROW, current) {
+                    self.consume(current)?;
+                    return_type = Some(self.parse_type(current)?);
                 }
-                panic!("expected identifier");
+
+                let body = Box::new(self.statement(current));
+                Ok(ParsedAST::new(
+                    ParsedASTKind::FN(Fun {
+                        identifier,
+                        params: params,
+                        return_type,
+                        body,
+                        doc: None,
+                    }),
+                    self.span(start, current),
+                ))
             }
             Token::TYPE => {
                 // we have a type definition!
                 // consume the type
-                self.consume(current);
-                // consume the {
-                self.consume(current);
-                let mut fields: HashMap<String, Type> = HashMap::new();
+                self.consume(current)?;
+                self.consume_expected(current, Token::LCURLY)?;
+                let mut fields: Vec<(String, Type)> = vec![];
 
                 while !self.expecting(Token::RCURLY, current) {
-                    let identifier = self.consume(current);
-
-                    match identifier {
-                        Token::IDENTIFIER(i) => {
-                            // consume the :
-                            // todo we need a method to consume a token and crash if we dont find it
-                            self.consume_expected(current, Token::COLON);
-                            let typ = self.type_from_token(self.consume(current));
-                            fields.insert(i.to_string(), typ);
-                        }
-                        _ => panic!("expected identifier"),
+                    let field_start = *current;
+                    let identifier = self.consume_identifier(current)?;
+                    if fields.iter().any(|(name, _)| *name == identifier) {
+                        return Err(Box::new(Diagnostic::error(
+                            "E0106",
+                            format!("field `{}` is declared more than once", identifier),
+                            self.position(&field_start),
+                        )));
+                    }
+                    // consume the :
+                    self.consume_expected(current, Token::COLON)?;
+                    let typ = self.parse_type(current)?;
+                    fields.push((identifier, typ));
+                    // fields can be separated by commas
+                    if self.expecting(Token::COMMA, current) {
+                        self.consume(current)?;
                     }
                 }
 
                 // consume the rbracket
-                self.consume(current);
-
-                ParsedAST::TYPE(Typ {
-                    fields,
-                    anon_name: None,
-                })
+                self.consume_expected(current, Token::RCURLY)?;
+
+                Ok(ParsedAST::new(
+                    ParsedASTKind::TYPE(Typ {
+                        fields,
+                        anon_name: None,
+                    }),
+                    self.span(start, current),
+                ))
             }
+            Token::ENUM => self.enum_type(current),
             // Token::HASH => {
             //     self.consume(current);
             //     let value = self.consume(current);
@@ -510,69 +1062,344 @@ impl Parser<'_> {
             // }
             // todo do we want true/false to be numbers?
             Token::TRUE => {
-                self.consume(current);
-                ParsedAST::NUMBER(Number::INTEGER(1))
+                self.consume(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::NUMBER(Number::INTEGER(1, Type::BOOL)),
+                    self.span(start, current),
+                ))
             }
             Token::FALSE => {
-                self.consume(current);
-                ParsedAST::NUMBER(Number::INTEGER(0))
+                self.consume(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::NUMBER(Number::INTEGER(0, Type::BOOL)),
+                    self.span(start, current),
+                ))
+            }
+            Token::NULL => {
+                self.consume(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::NULL,
+                    self.span(start, current),
+                ))
+            }
+            Token::UNSAFE => {
+                self.consume(current)?;
+                if !self.expecting(Token::LCURLY, current) {
+                    let t = self.peek(current)?;
+                    return Err(self.unexpected(t, "`{`", current));
+                }
+                let body = self.block(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::UNSAFE(Box::new(body)),
+                    self.span(start, current),
+                ))
+            }
+            Token::LBRACKET => {
+                let elements = self.delimited(current, Token::RBRACKET, |current| {
+                    let mut elements: Vec<ParsedAST> = vec![];
+                    while !self.expecting(Token::RBRACKET, current) {
+                        elements.push(self.nested_expression(current)?);
+                        if !self.expecting(Token::RBRACKET, current) {
+                            self.consume_expected(current, Token::COMMA)?;
+                        }
+                    }
+                    Ok(elements)
+                })?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::ARRAY_LITERAL(elements),
+                    self.span(start, current),
+                ))
             }
             Token::IDENTIFIER(identifier) => {
-                self.consume(current);
-                ParsedAST::IDENTIFIER(identifier.to_string())
+                self.consume(current)?;
+                if !self.end(current) && self.expecting(Token::LCURLY, current) {
+                    return self.struct_literal(identifier, start, current);
+                }
+                if !self.end(current) && self.expecting(Token::DOUBLE_COLON, current) {
+                    return self.variant(identifier, start, current);
+                }
+                Ok(ParsedAST::new(
+                    ParsedASTKind::IDENTIFIER(identifier.to_string()),
+                    self.span(start, current),
+                ))
             }
             Token::STRING(string) => {
-                self.consume(current);
-                ParsedAST::STRING(string.to_string())
+                self.consume(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::STRING(string.to_string()),
+                    self.span(start, current),
+                ))
             }
-            Token::NUMBER(number) => {
-                let num = self.consume(current);
-                if number.parse::<i32>().is_ok() {
-                    return ParsedAST::NUMBER(Number::INTEGER(number.parse::<i32>().unwrap()));
-                } else if number.parse::<f32>().is_ok() {
-                    return ParsedAST::NUMBER(Number::FLOAT(number.parse::<f32>().unwrap()));
-                }
-                panic!("failed to parse number {:?}", num);
+            Token::CHARACTER(c) => {
+                self.consume(current)?;
+                Ok(ParsedAST::new(
+                    ParsedASTKind::CHAR(*c),
+                    self.span(start, current),
+                ))
+            }
+            Token::INTERPOLATED_STRING(parts) => {
+                self.consume(current)?;
+                let position = self.span(start, current);
+                self.interpolated_string(parts, position)
+            }
+            Token::NUMBER(literal) => {
+                self.consume(current)?;
+                let position = self.span(start, current);
+                let number = self.number(literal, &position, false)?;
+                Ok(ParsedAST::new(ParsedASTKind::NUMBER(number), position))
+            }
+            Token::LPAREN => {
+                let expression = self.delimited(current, Token::RPAREN, |current| {
+                    if self.expecting(Token::RPAREN, current) {
+                        return Ok(None);
+                    }
+                    Ok(Some(self.nested_expression(current)?))
+                })?;
+                let kind = match expression {
+                    Some(expression) => ParsedASTKind::GROUP(Group {
+                        expression: Box::new(expression),
+                    }),
+                    None => ParsedASTKind::UNIT,
+                };
+                Ok(ParsedAST::new(kind, self.span(start, current)))
             }
-            // todo
-            // Token::LPAREN => {
-            //     self.consume(current);
-            //     let expression = self.expression(current);
-            //     self.consume(current);
-            //     ParsedAST::GROUP(Group {
-            //         expression: Box::new(expression),
-            //     })
-            // }
-            // todo
             Token::LCURLY => self.block(current),
-            _ => panic!(),
+            // blocks and ifs have a value, so they can be used like any other expression
+            Token::IF => self.if_stmt(current),
+            Token::MATCH => self.match_expr(current),
+            t => Err(self.unexpected(t, "an expression", current)),
         }
     }
 
-    fn block(&self, current: &mut usize) -> ParsedAST {
-        self.consume(current);
-        let mut body: Vec<ParsedAST> = vec![];
-        while !self.end(current) && !self.expecting(Token::RCURLY, current) {
-            body.push(self.statement(current));
+    fn block(&self, current: &mut usize) -> ParseResult<'a> {
+        let start = *current;
+        let body = self.delimited(current, Token::RCURLY, |current| {
+            let mut body: Vec<ParsedAST> = vec![];
+            while !self.end(current) && !self.expecting(Token::RCURLY, current) {
+                body.push(self.statement(current));
+            }
+            Ok(body)
+        })?;
+        return Ok(ParsedAST::new(
+            ParsedASTKind::BLOCK(Block {
+                new_scope: true,
+                body,
+            }),
+            self.span(start, current),
+        ));
+    }
+
+    // the position of the token at current, or the end of the last token if we have run out
+    fn position(&self, current: &usize) -> Position {
+        match self.tokens.get(*current) {
+            Some(t) => t.position.clone(),
+            None => match self.tokens.last() {
+                Some(t) => {
+                    let mut end = t.position.clone();
+                    end.start = end.end;
+                    end.line_start = end.line_end;
+                    end.col_start = end.col_end;
+                    end
+                }
+                // an empty program, there is nothing to point at
+                None => Position {
+                    file: Rc::from(""),
+                    start: 0,
+                    end: 0,
+                    col_start: 1,
+                    col_end: 1,
+                    line_start: 1,
+                    line_end: 1,
+                },
+            },
+        }
+    }
+
+    // the span from the token at start up to (and including) the last consumed token
+    fn span(&self, start: usize, current: &usize) -> Position {
+        let start_position = self.position(&start);
+        if *current > start {
+            start_position.join(&self.position(&(*current - 1)))
+        } else {
+            start_position
+        }
+    }
+
+    // "hi $name" is turned into a call to the format intrinsic, format("hi ", name)
+    fn interpolated_string(&self, parts: &'a [StringPart], position: Position) -> ParseResult<'a> {
+        let mut args: Vec<Arg> = vec![];
+        for part in parts.iter() {
+            match part {
+                StringPart::LITERAL(s) => args.push(Arg {
+                    name: None,
+                    value: ParsedAST::new(ParsedASTKind::STRING(s.to_string()), position.clone()),
+                    position: position.clone(),
+                }),
+                StringPart::CODE(tokens) => {
+                    if tokens.is_empty() {
+                        return Err(Box::new(Diagnostic::error(
+                            "E0105",
+                            "expected a value in string interpolation".to_string(),
+                            position,
+                        )));
+                    }
+                    // the tokens of the value are parsed on their own
+                    let parser = Parser {
+                        tokens,
+                        diagnostics: self.diagnostics,
+                    };
+                    let mut inner = 0;
+                    let value = parser.expression(&mut inner)?;
+                    if !parser.end(&inner) {
+                        return Err(parser.unexpected(&tokens[inner].token, "`}`", &inner));
+                    }
+                    let position = value.position.clone();
+                    args.push(Arg {
+                        name: None,
+                        value,
+                        position,
+                    });
+                }
+            }
         }
-        self.consume(current);
-        return ParsedAST::BLOCK(Block {
-            new_scope: true,
-            body,
+        Ok(ParsedAST::new(
+            ParsedASTKind::CALL(Call {
+                callee: Box::new(ParsedAST::new(
+                    ParsedASTKind::IDENTIFIER("format".to_string()),
+                    position.clone(),
+                )),
+                args,
+            }),
+            position,
+        ))
+    }
+
+    // parse what is between the `(`, `[` or `{` we are at and the delimiter that closes it
+    fn delimited<T>(
+        &self,
+        current: &mut usize,
+        close: Token,
+        inside: impl FnOnce(&mut usize) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let open = *current;
+        self.consume(current)?;
+        let result = inside(current);
+        let result = result.and_then(|inside| {
+            self.consume_expected(current, close.clone())?;
+            Ok(inside)
         });
+        result.map_err(|diagnostic| self.unclosed(open, &close, current, diagnostic))
+    }
+
+    // if what is between a pair of delimiters stops at the end of the file or the start of the
+    // next statement the closing one was left out, which is more useful to report than the
+    // token we found instead
+    fn unclosed(
+        &self,
+        open: usize,
+        close: &Token,
+        current: &usize,
+        error: ParseError,
+    ) -> ParseError {
+        // an unclosed delimiter inside this one is the one to report
+        if error.code == "E0110" || (!self.end(current) && !self.starts_statement(current)) {
+            return error;
+        }
+        let delimiter = describe(&self.tokens[open].token);
+        Box::new(
+            Diagnostic::error(
+                "E0110",
+                format!("unclosed {}", delimiter),
+                self.position(&open),
+            )
+            .with_label(format!("this {} is never closed", delimiter))
+            .with_secondary(
+                self.position(current),
+                format!("expected {} before this", describe(close)),
+            ),
+        )
     }
 
-    fn peek(&self, current: &usize) -> &Token {
+    // the params of a function type are either all named or none of them are
+    fn unnamed_param(&self, current: &usize) -> ParseError {
+        Box::new(
+            Diagnostic::error(
+                "E0111",
+                "only some parameters of the function type are named".to_string(),
+                self.position(current),
+            )
+            .with_label("this parameter".to_string())
+            .with_note("name every parameter or none of them".to_string()),
+        )
+    }
+
+    // an expression inside another one, which can't be a declaration
+    fn nested_expression(&self, current: &mut usize) -> ParseResult<'a> {
+        self.not_statement(current)?;
+        self.expression(current)
+    }
+
+    fn not_statement(&self, current: &usize) -> Result<(), ParseError> {
+        if self.starts_statement(current) {
+            return Err(self.unexpected(self.peek(current)?, "an expression", current));
+        }
+        Ok(())
+    }
+
+    // a token that can only start a statement, so if it comes up in the middle of an
+    // expression the expression was left unfinished
+    fn starts_statement(&self, current: &usize) -> bool {
+        match self.tokens.get(*current).map(|t| &t.token) {
+            Some(
+                Token::VAR
+                | Token::CONST
+                | Token::WHILE
+                | Token::FOR
+                | Token::RET
+                | Token::BREAK
+                | Token::CONTINUE
+                | Token::DOC_COMMENT(_),
+            ) => true,
+            // a lambda is an expression, but a named function is a declaration
+            Some(Token::FN) => matches!(
+                self.tokens.get(*current + 1).map(|t| &t.token),
+                Some(Token::IDENTIFIER(_))
+            ),
+            _ => false,
+        }
+    }
+
+    fn unexpected(&self, found: &Token, expected: &str, at: &usize) -> ParseError {
+        Box::new(
+            Diagnostic::error(
+                "E0100",
+                format!("expected {} found {}", expected, describe(found)),
+                self.position(at),
+            )
+            .with_label(format!("expected {}", expected)),
+        )
+    }
+
+    fn unexpected_end(&self, current: &usize) -> ParseError {
+        Box::new(Diagnostic::error(
+            "E0101",
+            "unexpected end of file".to_string(),
+            self.position(current),
+        ))
+    }
+
+    fn peek(&self, current: &usize) -> Result<&'a Token, ParseError> {
         match self.tokens.get(*current) {
-            std::option::Option::Some(t) => return t,
-            _ => panic!("there are no tokens at the current index {}", *current),
+            std::option::Option::Some(t) => return Ok(&t.token),
+            _ => Err(self.unexpected_end(current)),
         }
     }
 
-    fn peek_ahead(&self, current: &usize, amount: i32) -> &Token {
-        match self.tokens.get((*current as i32 + amount) as usize) {
-            std::option::Option::Some(t) => return t,
-            _ => panic!("there are no tokens at the current index {}", *current),
+    fn peek_ahead(&self, current: &usize, amount: i32) -> Result<&'a Token, ParseError> {
+        let index = (*current as i32 + amount) as usize;
+        match self.tokens.get(index) {
+            std::option::Option::Some(t) => return Ok(&t.token),
+            _ => Err(self.unexpected_end(&index)),
         }
     }
 
@@ -585,38 +1412,572 @@ impl Parser<'_> {
     }
 
     fn expecting(&self, token: Token, current: &usize) -> bool {
-        let next = self.peek(current);
-        return token.eq(&next);
+        match self.tokens.get(*current) {
+            Some(next) => token.eq(&next.token),
+            None => false,
+        }
     }
 
-    fn consume(&self, current: &mut usize) -> &Token {
+    fn consume(&self, current: &mut usize) -> Result<&'a Token, ParseError> {
         match self.tokens.get(*current) {
             std::option::Option::Some(t) => {
                 *current += 1;
-                return t;
+                return Ok(&t.token);
             }
-            _ => panic!("there are no tokens at the current index {}", *current),
+            _ => Err(self.unexpected_end(current)),
         }
     }
 
-    fn consume_expected(&self, current: &mut usize, token_expected: Token) -> &Token {
+    fn consume_expected(
+        &self,
+        current: &mut usize,
+        token_expected: Token,
+    ) -> Result<&'a Token, ParseError> {
         match self.tokens.get(*current) {
             std::option::Option::Some(t) => {
-                if *t != token_expected {
-                    panic!("expected {:?} found {:?}", token_expected, t);
+                if t.token != token_expected {
+                    return Err(self.unexpected(&t.token, &describe(&token_expected), current));
                 }
                 *current += 1;
-                return t;
+                return Ok(&t.token);
             }
-            _ => panic!("there are no tokens at the current index {}", *current),
+            _ => Err(self.unexpected_end(current)),
         }
     }
 
-    fn type_from_token(&self, token: &Token) -> Type {
-        match token {
-            Token::U32 => Type::U32,
-            Token::I32 => Type::I32,
-            _ => panic!("expected type"),
+    fn consume_identifier(&self, current: &mut usize) -> Result<String, ParseError> {
+        match self.consume(current)? {
+            Token::IDENTIFIER(i) => Ok(i.to_string()),
+            t => Err(self.unexpected(t, "an identifier", &(*current - 1))),
+        }
+    }
+
+    // work out the value of a number literal and check it fits in its type once it has been
+    // negated if there is a `-` in front of it
+    fn number(
+        &self,
+        literal: &NumberLiteral,
+        position: &Position,
+        negative: bool,
+    ) -> Result<Number, ParseError> {
+        let float_suffix = matches!(literal.suffix, Some(Type::F32) | Some(Type::F64));
+        if literal.float || float_suffix {
+            let typ = literal.suffix.clone().unwrap_or(Type::F32);
+            let Ok(value) = literal.digits.parse::<f64>() else {
+                return Err(Box::new(Diagnostic::error(
+                    "E0102",
+                    format!("invalid number literal `{}`", literal.text),
+                    position.clone(),
+                )));
+            };
+            let out_of_range = match typ {
+                Type::F32 => (value as f32).is_infinite(),
+                _ => value.is_infinite(),
+            };
+            if out_of_range {
+                return Err(Box::new(Diagnostic::error(
+                    "E0103",
+                    format!("literal out of range for `{}`", typ),
+                    position.clone(),
+                )));
+            }
+            return Ok(Number::FLOAT(value, typ));
+        }
+
+        let Ok(value) = u64::from_str_radix(&literal.digits, literal.radix) else {
+            return Err(Box::new(
+                Diagnostic::error(
+                    "E0103",
+                    format!("integer literal `{}` is too large", literal.text),
+                    position.clone(),
+                )
+                .with_note(format!("the largest integer is {}", u64::MAX)),
+            ));
+        };
+        let signed = if negative {
+            -(value as i128)
+        } else {
+            value as i128
+        };
+        let typ = match &literal.suffix {
+            Some(typ) => typ.clone(),
+            // unsuffixed integers are i32 unless they are too big
+            None if signed >= i32::MIN as i128 && signed <= i32::MAX as i128 => Type::I32,
+            None if signed >= i64::MIN as i128 && signed <= i64::MAX as i128 => Type::I64,
+            None if !negative => Type::U64,
+            None => Type::I64,
+        };
+        if let Some((min, max)) = typ.integer_bounds() {
+            if signed < min || signed > max {
+                return Err(Box::new(
+                    Diagnostic::error(
+                        "E0103",
+                        format!("literal out of range for `{}`", typ),
+                        position.clone(),
+                    )
+                    .with_note(format!("the range of `{}` is {}..={}", typ, min, max)),
+                ));
+            }
+        }
+        Ok(Number::INTEGER(value, typ))
+    }
+}
+
+// put the doc comment on the declaration or function in the statement, returns false
+// if there was nothing to document
+fn attach_doc(ast: &mut ParsedAST, doc: String) -> bool {
+    match &mut ast.kind {
+        ParsedASTKind::STMT(inner) => attach_doc(inner, doc),
+        ParsedASTKind::DECL(decl) => {
+            decl.doc = Some(doc);
+            true
+        }
+        ParsedASTKind::FN(fun) => {
+            fun.doc = Some(doc);
+            true
+        }
+        _ => false,
+    }
+}
+
+// the binary operator table, higher binds tighter
+fn binary_precedence(token: &Token) -> Option<u8> {
+    match token {
+        Token::PIPE => Some(1),
+        Token::OR => Some(2),
+        Token::AND => Some(3),
+        Token::EQUAL_EQUAL | Token::NOT_EQUAL => Some(4),
+        Token::LESS | Token::LESS_EQUAL | Token::GREATER | Token::GREATER_EQUAL => Some(5),
+        Token::DOT_DOT => Some(6),
+        Token::BAR => Some(7),
+        Token::CARET => Some(8),
+        Token::AMPERSAND => Some(9),
+        Token::PLUS | Token::MINUS => Some(10),
+        Token::STAR | Token::DIV | Token::PERCENT => Some(11),
+        _ => None,
+    }
+}
+
+// the types that have their own keyword
+fn primitive_type(token: &Token) -> Option<Type> {
+    match token {
+        Token::U8 => Some(Type::U8),
+        Token::U16 => Some(Type::U16),
+        Token::U32 => Some(Type::U32),
+        Token::U64 => Some(Type::U64),
+        Token::I8 => Some(Type::I8),
+        Token::I16 => Some(Type::I16),
+        Token::I32 => Some(Type::I32),
+        Token::I64 => Some(Type::I64),
+        Token::F32 => Some(Type::F32),
+        Token::F64 => Some(Type::F64),
+        Token::BOOL => Some(Type::BOOL),
+        Token::CHAR => Some(Type::CHAR),
+        _ => None,
+    }
+}
+
+// how a token is shown to the user in diagnostics
+pub(crate) fn describe(token: &Token) -> String {
+    match token {
+        Token::IDENTIFIER(i) => format!("identifier `{}`", i),
+        Token::NUMBER(n) => format!("number `{}`", n.text),
+        Token::INTERPOLATED_STRING(_) => "string".to_string(),
+        Token::CHARACTER(c) => format!("character {:?}", c),
+        Token::DOC_COMMENT(_) => "doc comment".to_string(),
+        Token::COMMENT(_) => "comment".to_string(),
+        Token::WHITESPACE(_) => "whitespace".to_string(),
+        Token::STRING(s) => format!("string \"{}\"", s),
+        Token::END => "end of file".to_string(),
+        Token::DOLLAR => "`$`".to_string(),
+        Token::AT => "`@`".to_string(),
+        Token::HASH => "`#`".to_string(),
+        Token::PLUS => "`+`".to_string(),
+        Token::MINUS => "`-`".to_string(),
+        Token::STAR => "`*`".to_string(),
+        Token::DIV => "`/`".to_string(),
+        Token::LCURLY => "`{`".to_string(),
+        Token::RCURLY => "`}`".to_string(),
+        Token::LPAREN => "`(`".to_string(),
+        Token::RPAREN => "`)`".to_string(),
+        Token::LBRACKET => "`[`".to_string(),
+        Token::RBRACKET => "`]`".to_string(),
+        Token::DOT => "`.`".to_string(),
+        Token::COMMA => "`,`".to_string(),
+        Token::COLON => "`:`".to_string(),
+        Token::SEMICOLON => "`;`".to_string(),
+        Token::EQUAL => "`=`".to_string(),
+        Token::EQUAL_EQUAL => "`==`".to_string(),
+        Token::NOT_EQUAL => "`!=`".to_string(),
+        Token::LESS => "`<`".to_string(),
+        Token::LESS_EQUAL => "`<=`".to_string(),
+        Token::GREATER => "`>`".to_string(),
+        Token::GREATER_EQUAL => "`>=`".to_string(),
+        Token::AND => "`&&`".to_string(),
+        Token::OR => "`||`".to_string(),
+        Token::NOT => "`!`".to_string(),
+        Token::ARROW => "`->`".to_string(),
+        Token::FAT_ARROW => "`=>`".to_string(),
+        Token::DOT_DOT => "`..`".to_string(),
+        Token::PIPE => "`|>`".to_string(),
+        Token::DOUBLE_COLON => "`::`".to_string(),
+        Token::PLUS_EQUAL => "`+=`".to_string(),
+        Token::MINUS_EQUAL => "`-=`".to_string(),
+        Token::STAR_EQUAL => "`*=`".to_string(),
+        Token::DIV_EQUAL => "`/=`".to_string(),
+        Token::PERCENT => "`%`".to_string(),
+        Token::AMPERSAND => "`&`".to_string(),
+        Token::BAR => "`|`".to_string(),
+        Token::CARET => "`^`".to_string(),
+        Token::TILDE => "`~`".to_string(),
+        Token::QUESTION => "`?`".to_string(),
+        // keywords are shown as they are written
+        keyword => format!("`{}`", format!("{:?}", keyword).to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{LeftUnary, Number, ParsedAST, ParsedASTKind, Pattern, PatternKind};
+    use crate::diagnostic::DiagnosticSink;
+    use crate::lex::Lexer;
+    use crate::parse::{describe, Parser};
+
+    // parse a single expression statement and show it as an s-expression
+    fn shape(program: &str) -> String {
+        fn show_pattern(pattern: &Pattern) -> String {
+            match &pattern.kind {
+                PatternKind::WILDCARD => "_".to_string(),
+                PatternKind::BINDING(name) => name.to_string(),
+                PatternKind::LITERAL(literal) => show(literal),
+                PatternKind::VARIANT(enum_name, variant, payload) => {
+                    let payload: Vec<String> = payload.iter().map(show_pattern).collect();
+                    format!("({}::{} {})", enum_name, variant, payload.join(" "))
+                }
+            }
+        }
+
+        fn show(ast: &ParsedAST) -> String {
+            match &ast.kind {
+                ParsedASTKind::STMT(inner) => show(inner),
+                ParsedASTKind::BINARY(binary) => format!(
+                    "({} {} {})",
+                    describe(binary.op).trim_matches('`'),
+                    show(&binary.left),
+                    show(&binary.right)
+                ),
+                ParsedASTKind::NUMBER(Number::INTEGER(i, _)) => i.to_string(),
+                ParsedASTKind::IDENTIFIER(i) => i.to_string(),
+                ParsedASTKind::GROUP(group) => format!("[{}]", show(&group.expression)),
+                ParsedASTKind::UNIT => "()".to_string(),
+                ParsedASTKind::CALL(call) => {
+                    let mut s = format!("(call {}", show(&call.callee));
+                    for arg in call.args.iter() {
+                        match &arg.name {
+                            Some(name) => s += &format!(" {}={}", name, show(&arg.value)),
+                            None => s += &format!(" {}", show(&arg.value)),
+                        }
+                    }
+                    s + ")"
+                }
+                ParsedASTKind::LHS_ACCESS(access) => {
+                    format!("(. {} {})", show(&access.left), access.right)
+                }
+                ParsedASTKind::INDEX(index) => {
+                    format!("(index {} {})", show(&index.left), show(&index.index))
+                }
+                ParsedASTKind::ARRAY_LITERAL(elements) => {
+                    let elements: Vec<String> = elements.iter().map(show).collect();
+                    format!("[{}]", elements.join(" "))
+                }
+                ParsedASTKind::STRUCT_LITERAL(literal) => {
+                    let mut s = format!("({}", literal.name);
+                    for field in literal.fields.iter() {
+                        s += &format!(
+                            " {}={}",
+                            field.name.as_deref().unwrap_or("_"),
+                            show(&field.value)
+                        );
+                    }
+                    s + ")"
+                }
+                ParsedASTKind::LEFT_UNARY(LeftUnary::NEG(operand)) => {
+                    format!("(- {})", show(operand))
+                }
+                ParsedASTKind::LEFT_UNARY(LeftUnary::NOT(operand)) => {
+                    format!("(! {})", show(operand))
+                }
+                ParsedASTKind::LEFT_UNARY(LeftUnary::ADDRESS(operand)) => {
+                    format!("(& {})", show(operand))
+                }
+                ParsedASTKind::LEFT_UNARY(LeftUnary::DEREF(operand)) => {
+                    format!("(* {})", show(operand))
+                }
+                ParsedASTKind::UNWRAP(operand) => format!("(.? {})", show(operand)),
+                ParsedASTKind::NULL => "null".to_string(),
+                ParsedASTKind::VARIANT(variant) => {
+                    let payload: Vec<String> = variant.payload.iter().map(show).collect();
+                    format!(
+                        "({}::{} {})",
+                        variant.enum_name,
+                        variant.variant,
+                        payload.join(" ")
+                    )
+                }
+                ParsedASTKind::MATCH(m) => {
+                    let mut s = format!("(match {}", show(&m.scrutinee));
+                    for arm in m.arms.iter() {
+                        s += &format!(" [{} {}]", show_pattern(&arm.pattern), show(&arm.body));
+                    }
+                    s + ")"
+                }
+                other => panic!("unexpected node {:?}", other),
+            }
         }
+
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let ast = parser.parse();
+        assert!(!diagnostics.has_errors(), "{:?}", diagnostics.diagnostics());
+        let ParsedASTKind::PROGRAM(program) = &ast.kind else {
+            panic!("expected a program");
+        };
+        assert_eq!(program.body.len(), 1);
+        show(&program.body[0])
+    }
+
+    #[test]
+    fn binary_operators_use_precedence_and_left_associativity() {
+        assert_eq!(shape("10 - 3 - 2"), "(- (- 10 3) 2)");
+        assert_eq!(shape("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(shape("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(shape("8 / 4 / 2 % 3"), "(% (/ (/ 8 4) 2) 3)");
+        assert_eq!(shape("a + 1 < b * 2"), "(< (+ a 1) (* b 2))");
+        assert_eq!(shape("a < b == b > c"), "(== (< a b) (> b c))");
+        assert_eq!(shape("a || b && c || d"), "(|| (|| a (&& b c)) d)");
+        assert_eq!(shape("a | b ^ c & d"), "(| a (^ b (& c d)))");
+        assert_eq!(shape("a == b & c"), "(== a (& b c))");
+        assert_eq!(shape("0..n + 1"), "(.. 0 (+ n 1))");
+        assert_eq!(shape("a |> f |> g"), "(|> (|> a f) g)");
+    }
+
+    #[test]
+    fn parentheses_group_expressions() {
+        assert_eq!(shape("(a + b) * c"), "(* [(+ a b)] c)");
+        assert_eq!(shape("10 - (3 - 2)"), "(- 10 [(- 3 2)])");
+        assert_eq!(shape("((1))"), "[[1]]");
+        assert_eq!(shape("()"), "()");
+    }
+
+    #[test]
+    fn unary_operators_bind_tighter_than_binary() {
+        assert_eq!(shape("-a * b"), "(* (- a) b)");
+        assert_eq!(shape("!a && b"), "(&& (! a) b)");
+        assert_eq!(shape("1 - -2"), "(- 1 (- 2))");
+        assert_eq!(shape("!!a"), "(! (! a))");
+    }
+
+    #[test]
+    fn integer_literals_are_range_checked_after_negation() {
+        let codes = |program: &str| {
+            let diagnostics = DiagnosticSink::new();
+            let mut lexer = Lexer::new("test.sy", &diagnostics);
+            lexer.lex(Box::new(program.to_string()));
+            let mut parser = Parser {
+                tokens: &lexer.tokens,
+                diagnostics: &diagnostics,
+            };
+            parser.parse();
+            let codes: Vec<&str> = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+            codes
+        };
+        assert!(codes("-128i8\n127i8\n255u8\n0u8\n-0u8").is_empty());
+        assert!(codes("-2147483648\n-9223372036854775808\n18446744073709551615").is_empty());
+        assert_eq!(codes("-129i8"), vec!["E0103"]);
+        assert_eq!(codes("128i8"), vec!["E0103"]);
+        assert_eq!(codes("256u8"), vec!["E0103"]);
+        assert_eq!(codes("-1u8"), vec!["E0103"]);
+        assert_eq!(codes("-9223372036854775809"), vec!["E0103"]);
+        assert_eq!(shape("-128i8"), "(- 128)");
+    }
+
+    #[test]
+    fn call_arguments_can_be_named() {
+        assert_eq!(shape("f(x=1, y=a + 2)"), "(call f x=1 y=(+ a 2))");
+        assert_eq!(shape("f(1, y=2)"), "(call f 1 y=2)");
+        assert_eq!(shape("f(x == 1)"), "(call f (== x 1))");
+        assert_eq!(shape("f()"), "(call f)");
+    }
+
+    #[test]
+    fn struct_literals_and_field_access() {
+        assert_eq!(shape("a.b.c + 1"), "(+ (. (. a b) c) 1)");
+        assert_eq!(shape("Point{x=1, y=p.y}"), "(Point x=1 y=(. p y))");
+        assert_eq!(shape("Point{}"), "(Point)");
+        assert_eq!(shape("f(p=Point{x=1}).x"), "(. (call f p=(Point x=1)) x)");
+    }
+
+    #[test]
+    fn array_literals_and_indexing() {
+        assert_eq!(shape("[1, 2, a + 3]"), "[1 2 (+ a 3)]");
+        assert_eq!(shape("[]"), "[]");
+        assert_eq!(shape("a.b[i + 1].c"), "(. (index (. a b) (+ i 1)) c)");
+        assert_eq!(shape("grid[1][2] * 2"), "(* (index (index grid 1) 2) 2)");
+        assert_eq!(shape("a[1..n - 1]"), "(index a (.. 1 (- n 1)))");
+        assert_eq!(shape("f(x=1)[0]"), "(index (call f x=1) 0)");
+    }
+
+    #[test]
+    fn address_of_dereference_and_unwrap() {
+        assert_eq!(shape("&a[1]"), "(& (index a 1))");
+        assert_eq!(shape("*p + 1"), "(+ (* p) 1)");
+        assert_eq!(shape("a * *p"), "(* a (* p))");
+        assert_eq!(shape("-*p.x"), "(- (* (. p x)))");
+        assert_eq!(shape("p.?.x == null"), "(== (. (.? p) x) null)");
+    }
+
+    #[test]
+    fn enum_variants_and_match_arms() {
+        assert_eq!(shape("Shape::Rect(1, 2 + 3)"), "(Shape::Rect 1 (+ 2 3))");
+        assert_eq!(shape("Shape::Empty == s"), "(== (Shape::Empty ) s)");
+        assert_eq!(
+            shape("match s { Shape::Rect(w, _) => w, Shape::Circle(-1) => 0 n => n }"),
+            "(match s [(Shape::Rect w _) w] [(Shape::Circle (- 1)) 0] [n n])"
+        );
+    }
+
+    #[test]
+    fn recovers_from_syntax_errors() {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(
+            "var a = (1 +
+fn g() { ret 1 }
+var b = 2 +
+var c = [1, 2
+const d = 4
+const e = f(x=1,
+var h = { 1"
+                .to_string(),
+        ));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let ast = parser.parse();
+
+        // the statements after each error are still parsed
+        let ParsedASTKind::PROGRAM(program) = &ast.kind else {
+            panic!("expected a program");
+        };
+        let statements: Vec<String> = program
+            .body
+            .iter()
+            .map(|statement| match &statement.kind {
+                ParsedASTKind::STMT(inner) => match &inner.kind {
+                    ParsedASTKind::DECL(decl) => decl.identifier.clone(),
+                    ParsedASTKind::FN(func) => func.identifier.clone().unwrap_or_default(),
+                    _ => "?".to_string(),
+                },
+                ParsedASTKind::ERROR => "error".to_string(),
+                _ => "?".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            statements,
+            vec!["error", "g", "error", "error", "d", "error", "error"]
+        );
+
+        // an unclosed delimiter is reported at the delimiter, not at the end of the file
+        let errors: Vec<(&str, u32)> = diagnostics
+            .diagnostics()
+            .iter()
+            .map(|d| (d.code, d.primary.as_ref().unwrap().position.line_start))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0110", 1),
+                ("E0100", 4),
+                ("E0110", 4),
+                ("E0110", 6),
+                ("E0110", 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_comments_attach_to_declarations() {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(
+            "/// the answer\n/// to everything\nconst x = 42\n/// nothing\n1 + 2\n/// dangling"
+                .to_string(),
+        ));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let ast = parser.parse();
+
+        let ParsedASTKind::PROGRAM(program) = &ast.kind else {
+            panic!("expected a program");
+        };
+        let ParsedASTKind::STMT(statement) = &program.body[0].kind else {
+            panic!("expected a statement");
+        };
+        let ParsedASTKind::DECL(decl) = &statement.kind else {
+            panic!("expected a decl");
+        };
+        assert_eq!(decl.doc.as_deref(), Some("the answer\nto everything"));
+
+        let codes: Vec<&str> = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["W0100", "E0104"]);
+    }
+
+    #[test]
+    fn string_is_a_primitive_type() {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(
+            "var a: string = \"hi\"\nvar b: [string]\nvar c: fn(string) -> string\nvar string = 1"
+                .to_string(),
+        ));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let ast = parser.parse();
+        assert!(!diagnostics.has_errors(), "{:?}", diagnostics.diagnostics());
+
+        let ParsedASTKind::PROGRAM(program) = &ast.kind else {
+            panic!("expected a program");
+        };
+        let types: Vec<String> = program
+            .body
+            .iter()
+            .map(|item| match &item.kind {
+                ParsedASTKind::STMT(statement) => match &statement.kind {
+                    ParsedASTKind::DECL(decl) => decl.typ.clone(),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(|typ| typ.map_or("?".to_string(), |typ| format!("{:?}", typ)))
+            .collect();
+        assert_eq!(types[0], "STRING");
+        assert_eq!(types[1], "SLICE(STRING)");
+        assert!(types[2].contains("args: [STRING]"), "{}", types[2]);
+        assert!(
+            types[2].contains("return_type: Some(STRING)"),
+            "{}",
+            types[2]
+        );
+        assert_eq!(types[3], "?");
     }
 }
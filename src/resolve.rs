@@ -0,0 +1,537 @@
+use std::time::Instant;
+
+use log::debug;
+
+use crate::{
+    ast::{
+        statement, Decl, Fun, LeftUnary, Match, ParsedAST, ParsedASTKind, Pattern, PatternKind,
+        Program,
+    },
+    diagnostic::{Diagnostic, DiagnosticSink},
+    ir_parse::INTRINSICS,
+    symtable::SymTable,
+    token::Position,
+    types::Type,
+};
+
+// works out which declaration each name refers to before the types are checked. a name is
+// looked for in the scope it is used in and then the ones around it, so a declaration in
+// an inner scope shadows the ones outside it until the scope ends, but a name can only be
+// declared once in the same scope. the functions and types at the top level can be used
+// before they are declared, anything else has to be declared first.
+//
+// each binding is given an id and renamed to `name.id` so the ir doesn't need to know about
+// scopes, types included. the ones at the top level keep their name as they are already unique
+pub struct Resolver<'a> {
+    pub diagnostics: &'a DiagnosticSink,
+    pub scopes: SymTable<String, Binding>,
+    // how many named functions we are inside
+    pub depth: usize,
+    // the id of the next binding
+    pub bindings: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingKind {
+    VARIABLE,
+    FUNCTION,
+    TYPE,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub kind: BindingKind,
+    // the name the ir knows it by
+    pub name: String,
+    // where it is declared
+    pub position: Position,
+    // how many named functions it is declared inside. named functions aren't closures, so
+    // they can't use the variables of the code around them
+    pub depth: usize,
+    // declared at the top level, which every function can use
+    pub global: bool,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(diagnostics: &'a DiagnosticSink) -> Resolver<'a> {
+        Resolver {
+            diagnostics,
+            scopes: SymTable::new(),
+            depth: 0,
+            bindings: 0,
+        }
+    }
+
+    pub fn resolve(&mut self, ast: &mut ParsedAST) {
+        let now = Instant::now();
+        self.visit(ast);
+        let elapsed = now.elapsed();
+        debug!(
+            "name resolution time elapsed {:.2?}ms ({:.2?}s).",
+            elapsed.as_millis(),
+            elapsed.as_secs()
+        );
+    }
+
+    fn visit(&mut self, ast: &mut ParsedAST) {
+        match &mut ast.kind {
+            ParsedASTKind::PROGRAM(program) => self.visit_program(program),
+            ParsedASTKind::STMT(inner) => self.visit(inner),
+            ParsedASTKind::EXPRESSION_INSTRUCTION(instruction) => self.visit(&mut instruction.rhs),
+            ParsedASTKind::BLOCK(block) => {
+                self.scopes.new_scope();
+                for item in block.body.iter_mut() {
+                    self.visit(item);
+                }
+                self.scopes.leave_scope();
+            }
+            ParsedASTKind::IF(iff) => {
+                self.visit(&mut iff.condition);
+                self.visit(&mut iff.body);
+                if let Some(else_body) = iff.else_body.as_mut() {
+                    self.visit(else_body);
+                }
+            }
+            ParsedASTKind::WHILE(w) => {
+                self.visit(&mut w.condition);
+                self.visit(&mut w.body);
+            }
+            ParsedASTKind::FOR(f) => {
+                self.visit(&mut f.iterable);
+                self.scopes.new_scope();
+                f.variable = self.declare(&f.variable, &ast.position, BindingKind::VARIABLE);
+                self.visit(&mut f.body);
+                self.scopes.leave_scope();
+            }
+            ParsedASTKind::RET(value) => {
+                if let Some(value) = value.as_mut() {
+                    self.visit(value);
+                }
+            }
+            ParsedASTKind::DECL(decl) => self.visit_decl(decl),
+            ParsedASTKind::ASSIGN(assign) => {
+                self.visit(&mut assign.lhs);
+                self.visit(&mut assign.rhs);
+            }
+            ParsedASTKind::IDENTIFIER(name) => self.lookup(name, &ast.position),
+            ParsedASTKind::FN(func) => self.visit_fn(func, &ast.position),
+            ParsedASTKind::LEFT_UNARY(
+                LeftUnary::COMP(operand)
+                | LeftUnary::NEG(operand)
+                | LeftUnary::NOT(operand)
+                | LeftUnary::ADDRESS(operand)
+                | LeftUnary::DEREF(operand),
+            ) => self.visit(operand),
+            ParsedASTKind::BINARY(binary) => {
+                self.visit(&mut binary.left);
+                self.visit(&mut binary.right);
+            }
+            ParsedASTKind::GROUP(group) => self.visit(&mut group.expression),
+            ParsedASTKind::CALL(call) => {
+                self.visit(&mut call.callee);
+                // the names of the args are the names of parameters, not variables
+                for arg in call.args.iter_mut() {
+                    self.visit(&mut arg.value);
+                }
+            }
+            ParsedASTKind::VARIANT(variant) => {
+                self.lookup_type(&mut variant.enum_name, &ast.position);
+                for value in variant.payload.iter_mut() {
+                    self.visit(value);
+                }
+            }
+            ParsedASTKind::MATCH(m) => self.visit_match(m),
+            ParsedASTKind::STRUCT_LITERAL(literal) => {
+                self.lookup_type(&mut literal.name, &ast.position);
+                for field in literal.fields.iter_mut() {
+                    self.visit(&mut field.value);
+                }
+            }
+            ParsedASTKind::ARRAY_LITERAL(elements) => {
+                for element in elements.iter_mut() {
+                    self.visit(element);
+                }
+            }
+            ParsedASTKind::INDEX(index) => {
+                self.visit(&mut index.left);
+                self.visit(&mut index.index);
+            }
+            ParsedASTKind::UNWRAP(operand) | ParsedASTKind::UNSAFE(operand) => self.visit(operand),
+            // the right is the name of a field
+            ParsedASTKind::LHS_ACCESS(access) => self.visit(&mut access.left),
+            ParsedASTKind::STRING(_)
+            | ParsedASTKind::CHAR(_)
+            | ParsedASTKind::NUMBER(_)
+            | ParsedASTKind::BREAK
+            | ParsedASTKind::CONTINUE
+            | ParsedASTKind::UNIT
+            | ParsedASTKind::NULL
+            | ParsedASTKind::TYPE(_)
+            | ParsedASTKind::ENUM(_)
+            | ParsedASTKind::ERROR => {}
+        }
+    }
+
+    fn visit_program(&mut self, program: &mut Program) {
+        // functions and types can be used before they are declared
+        for item in program.body.iter_mut() {
+            let item = statement(item);
+            match &item.kind {
+                ParsedASTKind::FN(Fun {
+                    identifier: Some(name),
+                    ..
+                }) => {
+                    self.declare(name, &item.position, BindingKind::FUNCTION);
+                }
+                ParsedASTKind::DECL(decl) if is_type(decl) => {
+                    self.declare(&decl.identifier, &decl.position, BindingKind::TYPE);
+                }
+                _ => {}
+            }
+        }
+        for item in program.body.iter_mut() {
+            self.visit(item);
+        }
+    }
+
+    fn visit_decl(&mut self, decl: &mut Decl) {
+        if is_type(decl) {
+            // the ones at the top level have already been declared
+            if self.scopes.scope > 0 {
+                decl.identifier = self.declare(&decl.identifier, &decl.position, BindingKind::TYPE);
+            }
+            // the types it holds, which can include itself
+            match decl.value.as_mut().map(|v| &mut v.kind) {
+                Some(ParsedASTKind::TYPE(typ)) => {
+                    for (_, field) in typ.fields.iter_mut() {
+                        self.check_type(field, &decl.position);
+                    }
+                }
+                Some(ParsedASTKind::ENUM(variants)) => {
+                    for typ in variants.iter_mut().flat_map(|(_, payload)| payload) {
+                        self.check_type(typ, &decl.position);
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+        if let Some(typ) = decl.typ.as_mut() {
+            self.check_type(typ, &decl.position);
+        }
+        // the variable isn't in scope in its own value, so `var x = x + 1` in a block uses
+        // the x from outside it
+        if let Some(value) = decl.value.as_mut() {
+            self.visit(value);
+        }
+        decl.identifier = self.declare(&decl.identifier, &decl.position, BindingKind::VARIABLE);
+    }
+
+    fn visit_fn(&mut self, func: &mut Fun, position: &Position) {
+        let named = func.identifier.is_some();
+        if let Some(name) = func.identifier.as_mut() {
+            // declared before the body so it can call itself, the ones at the top level
+            // have already been declared
+            if self.scopes.scope > 0 {
+                *name = self.declare(name, position, BindingKind::FUNCTION);
+            }
+            self.depth += 1;
+        }
+        for param in func.params.iter_mut() {
+            if let Some(typ) = param.typ.as_mut() {
+                self.check_type(typ, &param.position);
+            }
+        }
+        if let Some(typ) = func.return_type.as_mut() {
+            self.check_type(typ, position);
+        }
+        self.scopes.new_scope();
+        for param in func.params.iter_mut() {
+            param.identifier =
+                self.declare(&param.identifier, &param.position, BindingKind::VARIABLE);
+        }
+        self.visit(&mut func.body);
+        self.scopes.leave_scope();
+        if named {
+            self.depth -= 1;
+        }
+    }
+
+    fn visit_match(&mut self, m: &mut Match) {
+        self.visit(&mut m.scrutinee);
+        // the bindings of an arm are only in scope in its body
+        for arm in m.arms.iter_mut() {
+            self.scopes.new_scope();
+            self.bind_pattern(&mut arm.pattern);
+            self.visit(&mut arm.body);
+            self.scopes.leave_scope();
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &mut Pattern) {
+        match &mut pattern.kind {
+            PatternKind::BINDING(name) => {
+                *name = self.declare(name, &pattern.position, BindingKind::VARIABLE);
+            }
+            PatternKind::LITERAL(literal) => self.visit(literal),
+            PatternKind::VARIANT(enum_name, _, payload) => {
+                self.lookup_type(enum_name, &pattern.position);
+                for part in payload.iter_mut() {
+                    self.bind_pattern(part);
+                }
+            }
+            PatternKind::WILDCARD => {}
+        }
+    }
+
+    // add a binding to the innermost scope, giving back the name the ir knows it by
+    fn declare(&mut self, name: &str, position: &Position, kind: BindingKind) -> String {
+        if let Some(first) = self.scopes.get_local(name.to_string()) {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0601",
+                    format!("`{}` is already declared in this scope", name),
+                    position.clone(),
+                )
+                .with_label("declared again here".to_string())
+                .with_secondary(first.position.clone(), "first declared here".to_string())
+                .with_note(
+                    "a name can be declared again inside a block, where it shadows the one outside"
+                        .to_string(),
+                ),
+            );
+        }
+        let ir_name = if self.scopes.scope == 0 {
+            name.to_string()
+        } else {
+            format!("{}.{}", name, self.bindings)
+        };
+        self.bindings += 1;
+        self.scopes.add(
+            name.to_string(),
+            Binding {
+                kind,
+                name: ir_name.clone(),
+                position: position.clone(),
+                depth: self.depth,
+                global: self.scopes.scope == 0,
+            },
+        );
+        ir_name
+    }
+
+    // rename a use of a name to the binding it refers to
+    fn lookup(&self, name: &mut String, position: &Position) {
+        let Some(binding) = self.scopes.get(name.to_string()) else {
+            // intrinsics aren't declared anywhere
+            if !INTRINSICS.contains(&name.as_str()) {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0600",
+                        format!("cannot find `{}` in this scope", name),
+                        position.clone(),
+                    )
+                    .with_label("not declared".to_string()),
+                );
+            }
+            return;
+        };
+        if binding.kind == BindingKind::VARIABLE && !binding.global && binding.depth < self.depth {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0600",
+                    format!("cannot find `{}` in this function", name),
+                    position.clone(),
+                )
+                .with_label("declared outside this function".to_string())
+                .with_secondary(binding.position.clone(), "declared here".to_string())
+                .with_note(
+                    "functions can only use their own variables, a lambda can use the ones around it"
+                        .to_string(),
+                ),
+            );
+            return;
+        }
+        *name = binding.name.clone();
+    }
+
+    // rename a use of the name of a type to the type it refers to
+    fn lookup_type(&self, name: &mut String, position: &Position) {
+        let label = match self.scopes.get(name.to_string()) {
+            Some(binding) if binding.kind == BindingKind::TYPE => {
+                *name = binding.name.clone();
+                return;
+            }
+            Some(_) => format!("`{}` isn't a type", name),
+            None => "not declared".to_string(),
+        };
+        self.diagnostics.emit(
+            Diagnostic::error(
+                "E0602",
+                format!("cannot find type `{}` in this scope", name),
+                position.clone(),
+            )
+            .with_label(label),
+        );
+    }
+
+    // the named types in a type, like `Point` in `[4]&Point`
+    fn check_type(&self, typ: &mut Type, position: &Position) {
+        match typ {
+            Type::STRUCT(name) | Type::ENUM(name) => self.lookup_type(name, position),
+            Type::SLICE(inner)
+            | Type::ARRAY(inner, _)
+            | Type::POINTER(inner)
+            | Type::REFERENCE(inner)
+            | Type::OPTIONAL(inner) => self.check_type(inner, position),
+            Type::FN(function) => {
+                for arg in function.args.iter_mut() {
+                    self.check_type(arg, position);
+                }
+                if let Some(typ) = function.return_type.as_mut() {
+                    self.check_type(typ, position);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// the name a binding was declared with, without the id it was given
+pub fn source_name(name: &str) -> &str {
+    name.split_once('.').map_or(name, |(name, _)| name)
+}
+
+fn is_type(decl: &Decl) -> bool {
+    matches!(
+        decl.value.as_ref().map(|v| &v.kind),
+        Some(ParsedASTKind::TYPE(_) | ParsedASTKind::ENUM(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{statement, ParsedASTKind};
+    use crate::diagnostic::DiagnosticSink;
+    use crate::lex::Lexer;
+    use crate::parse::Parser;
+    use crate::resolve::Resolver;
+    use crate::types::Type;
+
+    // resolve a program, giving back what its top level statements declare and the codes of
+    // any errors
+    fn resolve(program: &str) -> (Vec<String>, Vec<&'static str>) {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let mut ast = parser.parse();
+        Resolver::new(&diagnostics).resolve(&mut ast);
+        let ParsedASTKind::PROGRAM(program) = &mut ast.kind else {
+            panic!("expected a program");
+        };
+        let mut names = vec![];
+        for item in program.body.iter_mut() {
+            let item = statement(item);
+            match &item.kind {
+                ParsedASTKind::DECL(decl) => names.push(decl.identifier.clone()),
+                ParsedASTKind::FN(func) => {
+                    names.extend(func.params.iter().map(|p| p.identifier.clone()))
+                }
+                _ => {}
+            }
+        }
+        let codes = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        (names, codes)
+    }
+
+    #[test]
+    fn resolves_names() {
+        // top level functions and types can be used before they are declared, everything
+        // else below the top level gets its own name
+        let (names, errors) = resolve(
+            "var a = twice(n=2)
+fn twice(n: i32) -> i32 { var a = n * 2 a }
+var p = Point{x=1}
+const Point = type { x: i32 }
+var b = { var a = 1 { var a = a + 1 a } }
+printf(\"%d\", a)",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(names, vec!["a", "n.3", "p", "Point", "b"]);
+
+        let codes = |program: &str| resolve(program).1;
+        assert_eq!(codes("var x = y"), vec!["E0600"]);
+        assert_eq!(codes("var x = y\nvar y = 1"), vec!["E0600"]);
+        assert_eq!(codes("{ var x = 1 }\nvar y = x"), vec!["E0600"]);
+        assert!(codes("var x = 1\nfn f() -> i32 { x }").is_empty());
+        assert_eq!(codes("{ var x = 1\nfn f() -> i32 { x } }"), vec!["E0600"]);
+        assert_eq!(
+            codes("fn g() { var x = 1\nfn f() -> i32 { x } }"),
+            vec!["E0600"]
+        );
+        assert!(codes("var x = 1\nvar f = fn() -> i32 { x }").is_empty());
+        assert_eq!(codes("var x = 1\nvar x = 2"), vec!["E0601"]);
+        assert_eq!(codes("fn f(a: i32, a: i32) {}"), vec!["E0601"]);
+        assert_eq!(codes("fn f() {}\nfn f() {}"), vec!["E0601"]);
+        assert!(codes("var x = 1\n{ var x = 2 }").is_empty());
+        assert!(
+            codes("match 1 { n => { var n = n } }\nvar f = fn() { format(\"hi\") }").is_empty()
+        );
+        assert_eq!(codes("var p = Q{x=1}"), vec!["E0602"]);
+        assert_eq!(codes("var q = 1\nvar p = q{x=1}"), vec!["E0602"]);
+        assert_eq!(codes("var o = E::A"), vec!["E0602"]);
+        assert_eq!(codes("fn f(p: &Q) {}"), vec!["E0602"]);
+        assert_eq!(codes("fn f() -> [Q] {}"), vec!["E0602"]);
+        assert_eq!(codes("const L = type { next: *Q }"), vec!["E0602"]);
+        assert!(codes("const L = type { next: *L }\nfn f(l: L) {}").is_empty());
+        assert_eq!(
+            codes("{ const P = type { x: i32 } }\nvar p: P"),
+            vec!["E0602"]
+        );
+    }
+
+    #[test]
+    fn gives_types_in_blocks_their_own_names() {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(
+            "{ const P = type { x: i32 }\nvar p: P = P{x=1} }
+{ const P = type { y: i32 }\nvar p: P = P{y=1} }"
+                .to_string(),
+        ));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let mut ast = parser.parse();
+        Resolver::new(&diagnostics).resolve(&mut ast);
+        assert!(!diagnostics.has_errors(), "{:?}", diagnostics.diagnostics());
+        let ParsedASTKind::PROGRAM(program) = &mut ast.kind else {
+            panic!("expected a program");
+        };
+        // the name of each type, and the types of the variable and the struct literal using it
+        let mut names = vec![];
+        for item in program.body.iter_mut() {
+            let ParsedASTKind::BLOCK(block) = &mut statement(item).kind else {
+                panic!("expected a block");
+            };
+            for item in block.body.iter_mut() {
+                let ParsedASTKind::DECL(decl) = &statement(item).kind else {
+                    continue;
+                };
+                match (&decl.typ, decl.value.as_ref().map(|value| &value.kind)) {
+                    (Some(Type::STRUCT(typ)), Some(ParsedASTKind::STRUCT_LITERAL(literal))) => {
+                        names.push(typ.clone());
+                        names.push(literal.name.clone());
+                    }
+                    _ => names.push(decl.identifier.clone()),
+                }
+            }
+        }
+        assert_eq!(names, vec!["P.0", "P.0", "P.0", "P.2", "P.2", "P.2"]);
+    }
+}
@@ -23,11 +23,14 @@ where
         };
     }
 
+    // inner scopes shadow the ones they are in
     pub fn get(&self, key: K) -> Option<&T> {
-        return match self.symbols.get(self.scope) {
-            Some(map) => map.get(&key),
-            None => panic!(),
-        };
+        self.symbols.iter().rev().find_map(|map| map.get(&key))
+    }
+
+    // only the innermost scope, i.e. to find a name declared twice in the same one
+    pub fn get_local(&self, key: K) -> Option<&T> {
+        self.symbols.get(self.scope)?.get(&key)
     }
 
     pub fn new_scope(&mut self) {
@@ -1,4 +1,7 @@
-// todo add position to tokens
+use std::fmt;
+use std::rc::Rc;
+
+use crate::types::Type;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 #[warn(dead_code)]
@@ -29,8 +32,43 @@ pub enum Token {
 
     EQUAL,
 
-    NUMBER(String),
+    // operators made of more than one char
+    EQUAL_EQUAL,
+    NOT_EQUAL,
+    LESS,
+    LESS_EQUAL,
+    GREATER,
+    GREATER_EQUAL,
+    AND,
+    OR,
+    NOT,
+    ARROW,
+    FAT_ARROW,
+    DOT_DOT,
+    PIPE,
+    DOUBLE_COLON,
+    PLUS_EQUAL,
+    MINUS_EQUAL,
+    STAR_EQUAL,
+    DIV_EQUAL,
+
+    PERCENT,
+    AMPERSAND,
+    BAR,
+    CARET,
+    TILDE,
+    QUESTION,
+
+    NUMBER(NumberLiteral),
+    // a string with values in it, i.e. "hi $name"
+    INTERPOLATED_STRING(Vec<StringPart>),
+    // the text of a `///` comment, attached to the declaration after it
+    DOC_COMMENT(String),
+    // only produced when the lexer is keeping trivia, they hold the text as written
+    COMMENT(String),
+    WHITESPACE(String),
     STRING(String),
+    CHARACTER(char),
     IDENTIFIER(String),
 
     VAR,
@@ -41,12 +79,21 @@ pub enum Token {
     PUB,
     PRIV,
 
+    U8,
+    U16,
     U32,
+    U64,
+    I8,
+    I16,
     I32,
+    I64,
     F32,
+    F64,
     BOOL,
+    CHAR,
     FN,
     TYPE,
+    ENUM,
 
     TRUE,
     FALSE,
@@ -54,15 +101,76 @@ pub enum Token {
     IF,
     ELSE,
     FOR,
+    IN,
+    WHILE,
+    MATCH,
+    BREAK,
+    CONTINUE,
 
     RET,
 
     COMP,
+    NULL,
+    UNSAFE,
+}
+
+// a number as written in the source, the parser works out its value and checks it fits
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct NumberLiteral {
+    // the literal as it was written
+    pub text: String,
+    // the digits without any prefix, suffix or underscores
+    pub digits: String,
+    pub radix: u32,
+    // has a fraction or exponent
+    pub float: bool,
+    // i.e. `10u8`
+    pub suffix: Option<Type>,
 }
 
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum StringPart {
+    LITERAL(String),
+    // the tokens of an interpolated value
+    CODE(Vec<PositionedToken>),
+}
+
+// a span in the source, lines and columns start at 1 and the ends are exclusive
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Position {
-    col_start: u32,
-    col_end: u32,
-    line_start: u32,
-    line_end: u32,
+    pub file: Rc<str>,
+    // byte offsets into the source
+    pub start: usize,
+    pub end: usize,
+    pub col_start: u32,
+    pub col_end: u32,
+    pub line_start: u32,
+    pub line_end: u32,
+}
+
+impl Position {
+    // the span covering both self and other (other is expected to come after self)
+    pub fn join(&self, other: &Position) -> Position {
+        Position {
+            file: self.file.clone(),
+            start: self.start,
+            end: other.end,
+            col_start: self.col_start,
+            col_end: other.col_end,
+            line_start: self.line_start,
+            line_end: other.line_end,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line_start, self.col_start)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PositionedToken {
+    pub token: Token,
+    pub position: Position,
 }
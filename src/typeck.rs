@@ -0,0 +1,1587 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
+
+use log::debug;
+
+use crate::{
+    ast::{
+        statement, Arg, Binary, Call, Decl, For, Fun, Index, LeftUnary, LhsAccess, Match, Number,
+        ParsedAST, ParsedASTKind, Pattern, PatternKind, Program, StructLiteral, Variant,
+    },
+    diagnostic::{Diagnostic, DiagnosticSink},
+    resolve::source_name,
+    symtable::SymTable,
+    token::{Position, Token},
+    types::{join, recursive_path, Enums, Structs, Type},
+};
+
+// works out the type of every expression before the ir is generated. each node is annotated
+// with its type and declarations without a type are given the type of their value, so the
+// ir parser doesn't have to guess. anything we can't work out the type of is left as none
+// and isn't checked
+pub struct TypeChecker<'a> {
+    pub diagnostics: &'a DiagnosticSink,
+    // the fields of the struct types and the variants of the enum types that are declared
+    pub structs: Structs,
+    pub enums: Enums,
+    // the types of the variables and functions in scope
+    pub scopes: SymTable<String, Type>,
+    // the return types of the functions we are inside, the innermost is last
+    pub return_types: Vec<Option<Type>>,
+    // the types we've said have infinite size, so a loop of them is only reported once
+    pub infinite: HashSet<String>,
+    // the functions declared with a name, which are called with every param named
+    pub functions: HashSet<String>,
+    // how many `unsafe` blocks we are inside, pointers can only be offset in one
+    pub unsafe_depth: usize,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(diagnostics: &'a DiagnosticSink) -> TypeChecker<'a> {
+        TypeChecker {
+            diagnostics,
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            scopes: SymTable::new(),
+            return_types: vec![],
+            infinite: HashSet::new(),
+            functions: HashSet::new(),
+            unsafe_depth: 0,
+        }
+    }
+
+    pub fn check(&mut self, ast: &mut ParsedAST) {
+        let now = Instant::now();
+        self.infer(ast);
+        let elapsed = now.elapsed();
+        debug!(
+            "type checking time elapsed {:.2?}ms ({:.2?}s).",
+            elapsed.as_millis(),
+            elapsed.as_secs()
+        );
+    }
+
+    // work out the type of a node and everything in it, annotating each one
+    fn infer(&mut self, ast: &mut ParsedAST) -> Option<Type> {
+        let typ = match &mut ast.kind {
+            ParsedASTKind::PROGRAM(program) => {
+                self.infer_program(program);
+                None
+            }
+            ParsedASTKind::STMT(inner) => self.infer(inner),
+            ParsedASTKind::EXPRESSION_INSTRUCTION(instruction) => self.infer(&mut instruction.rhs),
+            ParsedASTKind::BLOCK(block) => {
+                self.scopes.new_scope();
+                // a block has the type of its last statement
+                let mut typ = None;
+                for item in block.body.iter_mut() {
+                    typ = self.infer(item);
+                }
+                self.scopes.leave_scope();
+                typ
+            }
+            ParsedASTKind::IF(iff) => {
+                self.infer(&mut iff.condition);
+                self.expect_condition(&iff.condition);
+                let body = self.infer(&mut iff.body);
+                let else_body = match iff.else_body.as_mut() {
+                    Some(else_body) => {
+                        let typ = self.infer(else_body);
+                        self.check_if_arms(&iff.body, else_body);
+                        typ
+                    }
+                    None => None,
+                };
+                body.or(else_body)
+            }
+            ParsedASTKind::WHILE(w) => {
+                self.infer(&mut w.condition);
+                self.expect_condition(&w.condition);
+                self.infer(&mut w.body);
+                None
+            }
+            ParsedASTKind::FOR(f) => {
+                self.infer_for(f);
+                None
+            }
+            ParsedASTKind::RET(value) => {
+                if let Some(value) = value.as_mut() {
+                    self.infer(value);
+                    let expected = self.return_types.last().cloned().flatten();
+                    self.expect(&expected, value);
+                }
+                None
+            }
+            ParsedASTKind::DECL(decl) => {
+                self.infer_decl(decl, &ast.position);
+                None
+            }
+            ParsedASTKind::ASSIGN(assign) => {
+                let lhs = self.infer(&mut assign.lhs);
+                self.infer(&mut assign.rhs);
+                self.expect(&lhs, &assign.rhs);
+                None
+            }
+            ParsedASTKind::IDENTIFIER(name) => self.scopes.get(name.to_string()).cloned(),
+            ParsedASTKind::STRING(_) => Some(Type::STRING),
+            ParsedASTKind::CHAR(_) => Some(Type::CHAR),
+            ParsedASTKind::NUMBER(Number::INTEGER(_, typ))
+            | ParsedASTKind::NUMBER(Number::FLOAT(_, typ)) => Some(typ.clone()),
+            ParsedASTKind::FN(func) => self.infer_fn(func),
+            ParsedASTKind::LEFT_UNARY(left_unary) => {
+                self.infer_left_unary(left_unary, &ast.position)
+            }
+            ParsedASTKind::BINARY(binary) => self.infer_binary(binary, &ast.position),
+            ParsedASTKind::GROUP(group) => self.infer(&mut group.expression),
+            ParsedASTKind::CALL(call) => self.infer_call(call, &ast.position),
+            ParsedASTKind::VARIANT(variant) => self.infer_variant(variant, &ast.position),
+            ParsedASTKind::MATCH(m) => self.infer_match(m, &ast.position),
+            ParsedASTKind::STRUCT_LITERAL(literal) => {
+                self.infer_struct_literal(literal, &ast.position)
+            }
+            ParsedASTKind::ARRAY_LITERAL(elements) => {
+                self.infer_array_literal(elements, &ast.position)
+            }
+            ParsedASTKind::INDEX(index) => self.infer_index(index),
+            ParsedASTKind::UNWRAP(operand) => self.infer_unwrap(operand),
+            ParsedASTKind::UNSAFE(body) => {
+                self.unsafe_depth += 1;
+                let typ = self.infer(body);
+                self.unsafe_depth -= 1;
+                typ
+            }
+            ParsedASTKind::LHS_ACCESS(access) => self.infer_field(access, &ast.position),
+            ParsedASTKind::BREAK
+            | ParsedASTKind::CONTINUE
+            | ParsedASTKind::UNIT
+            | ParsedASTKind::NULL
+            | ParsedASTKind::TYPE(_)
+            | ParsedASTKind::ENUM(_)
+            | ParsedASTKind::ERROR => None,
+        };
+        ast.typ = typ.clone();
+        typ
+    }
+
+    fn infer_program(&mut self, program: &mut Program) {
+        // types can be used before they are declared
+        for item in program.body.iter_mut() {
+            if let ParsedASTKind::DECL(decl) = &statement(item).kind {
+                match decl.value.as_ref().map(|v| &v.kind) {
+                    Some(ParsedASTKind::TYPE(typ)) => {
+                        self.structs
+                            .insert(decl.identifier.clone(), typ.fields.clone());
+                    }
+                    Some(ParsedASTKind::ENUM(variants)) => {
+                        self.enums.insert(decl.identifier.clone(), variants.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // and functions can be called before they are defined. now we know which names are
+        // enums, the types the declarations use can be resolved
+        for item in program.body.iter_mut() {
+            match &mut statement(item).kind {
+                ParsedASTKind::FN(func) => self.add_signature(func),
+                ParsedASTKind::DECL(decl) => self.declare_type(decl),
+                _ => {}
+            }
+        }
+        for item in program.body.iter_mut() {
+            self.infer(item);
+        }
+    }
+
+    // record the fields of a struct type or the variants of an enum type
+    fn declare_type(&mut self, decl: &mut Decl) {
+        match decl.value.as_mut().map(|v| &mut v.kind) {
+            Some(ParsedASTKind::TYPE(typ)) => {
+                for (_, field) in typ.fields.iter_mut() {
+                    *field = field.resolve(&self.enums);
+                }
+                self.structs
+                    .insert(decl.identifier.clone(), typ.fields.clone());
+            }
+            Some(ParsedASTKind::ENUM(variants)) => {
+                for (_, payload) in variants.iter_mut() {
+                    for typ in payload.iter_mut() {
+                        *typ = typ.resolve(&self.enums);
+                    }
+                }
+                self.enums.insert(decl.identifier.clone(), variants.clone());
+            }
+            _ => {}
+        }
+    }
+
+    // a type that holds itself by value would be infinitely big. a loop of types is reported
+    // at the first of them we check
+    fn check_size(&mut self, decl: &Decl) {
+        let Some(path) = recursive_path(&decl.identifier, &self.structs, &self.enums) else {
+            return;
+        };
+        if path.iter().any(|name| self.infinite.contains(name)) {
+            return;
+        }
+        self.infinite.extend(path.iter().cloned());
+        let name = source_name(&decl.identifier);
+        let mut diagnostic = Diagnostic::error(
+            "E0503",
+            format!("recursive type `{}` has infinite size", name),
+            decl.position.clone(),
+        )
+        .with_label(format!("`{}` contains itself", name));
+        // spell out the loop when it goes through other types
+        if path.len() > 2 {
+            let chain = path[1..]
+                .iter()
+                .map(|name| format!("`{}`", source_name(name)))
+                .collect::<Vec<String>>()
+                .join(", which contains ");
+            diagnostic = diagnostic.with_note(format!("`{}` contains {}", name, chain));
+        }
+        self.diagnostics.emit(diagnostic.with_note(format!(
+            "hold it through a reference instead, like `&{}`",
+            name
+        )));
+    }
+
+    fn infer_decl(&mut self, decl: &mut Decl, position: &Position) {
+        if let Some(ParsedASTKind::TYPE(_) | ParsedASTKind::ENUM(_)) =
+            decl.value.as_ref().map(|v| &v.kind)
+        {
+            self.declare_type(decl);
+            self.check_size(decl);
+            return;
+        }
+        decl.typ = decl.typ.as_ref().map(|typ| typ.resolve(&self.enums));
+        if let Some(value) = decl.value.as_mut() {
+            let typ = self.infer(value);
+            self.expect(&decl.typ, value);
+            if decl.requires_infering && typ.is_some() {
+                decl.typ = typ;
+                decl.requires_infering = false;
+            }
+        } else if let Some(typ) = decl.typ.as_ref().filter(|typ| typ.pointee().is_some()) {
+            // without a value it would be null
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0227",
+                    format!("`{}` cannot be null", typ),
+                    position.clone(),
+                )
+                .with_label("give it a value, or make it optional".to_string()),
+            );
+        }
+        if let Some(typ) = &decl.typ {
+            self.scopes.add(decl.identifier.clone(), typ.clone());
+        }
+    }
+
+    fn resolve_fun_types(&self, func: &mut Fun) {
+        for param in func.params.iter_mut() {
+            param.typ = param.typ.as_ref().map(|typ| typ.resolve(&self.enums));
+        }
+        func.return_type = func
+            .return_type
+            .as_ref()
+            .map(|typ| typ.resolve(&self.enums));
+    }
+
+    fn add_signature(&mut self, func: &mut Fun) {
+        self.resolve_fun_types(func);
+        if let Some(name) = &func.identifier {
+            self.scopes.add(name.to_string(), func.fn_type());
+            self.functions.insert(name.to_string());
+        }
+    }
+
+    fn infer_fn(&mut self, func: &mut Fun) -> Option<Type> {
+        // so the body can call the function
+        self.add_signature(func);
+        self.scopes.new_scope();
+        // params that are functions are called through their value, even if they have the
+        // same name as a function
+        let functions = self.functions.clone();
+        for p in func.params.iter() {
+            if let Some(typ) = &p.typ {
+                self.scopes.add(p.identifier.clone(), typ.clone());
+            }
+            self.functions.remove(&p.identifier);
+        }
+        self.return_types.push(func.return_type.clone());
+        self.infer(&mut func.body);
+        self.return_types.pop();
+        self.functions = functions;
+        self.scopes.leave_scope();
+
+        // the last expression is returned if the function has a return type
+        if let ParsedASTKind::BLOCK(block) = &statement(&mut func.body).kind {
+            if let Some(last) = block.body.last() {
+                self.expect(&func.return_type, last);
+            }
+        }
+        match func.identifier {
+            Some(_) => None,
+            None => Some(func.fn_type()),
+        }
+    }
+
+    fn infer_for(&mut self, f: &mut For) {
+        self.infer(&mut f.iterable);
+        // the variable goes through the values of the range
+        let variable = match &f.iterable.kind {
+            ParsedASTKind::BINARY(Binary {
+                left,
+                op: Token::DOT_DOT,
+                right,
+            }) => arithmetic_type(left, right),
+            _ => None,
+        };
+        self.scopes.new_scope();
+        if let Some(typ) = variable {
+            self.scopes.add(f.variable.clone(), typ);
+        }
+        self.infer(&mut f.body);
+        self.scopes.leave_scope();
+    }
+
+    fn infer_left_unary(
+        &mut self,
+        left_unary: &mut LeftUnary,
+        position: &Position,
+    ) -> Option<Type> {
+        match left_unary {
+            LeftUnary::COMP(operand) => self.infer(operand),
+            LeftUnary::NEG(operand) => {
+                let typ = self.infer(operand);
+                if let Some(typ) = typ.as_ref().filter(|typ| !is_number(typ)) {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0501",
+                            format!("cannot apply `-` to `{}`", typ),
+                            position.clone(),
+                        )
+                        .with_label("only numbers can be negated".to_string()),
+                    );
+                }
+                typ
+            }
+            LeftUnary::NOT(operand) => {
+                self.infer(operand);
+                self.expect_condition(operand);
+                Some(Type::BOOL)
+            }
+            LeftUnary::ADDRESS(operand) => {
+                let typ = self.infer(operand);
+                if !has_address(operand) {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0223",
+                            "cannot take the address of this expression".to_string(),
+                            operand.position.clone(),
+                        )
+                        .with_label(
+                            "only variables and their elements have an address".to_string(),
+                        ),
+                    );
+                    return None;
+                }
+                Some(Type::REFERENCE(Box::new(typ?)))
+            }
+            LeftUnary::DEREF(operand) => {
+                self.infer(operand);
+                self.pointee_type(operand)
+            }
+        }
+    }
+
+    // the type a pointer points to, an optional must be unwrapped first as it may be null
+    fn pointee_type(&self, pointer: &ParsedAST) -> Option<Type> {
+        if let Some(pointee) = pointer.typ.as_ref().and_then(|typ| typ.pointee()) {
+            return Some(pointee.clone());
+        }
+        let diagnostic = match &pointer.typ {
+            Some(typ @ Type::OPTIONAL(_)) => Diagnostic::error(
+                "E0224",
+                format!("`{}` may be null", typ),
+                pointer.position.clone(),
+            )
+            .with_label(
+                "use `.?` to get the pointer, it is checked when the program runs".to_string(),
+            ),
+            Some(typ) => Diagnostic::error(
+                "E0225",
+                "only pointers can be dereferenced".to_string(),
+                pointer.position.clone(),
+            )
+            .with_label(format!("this is `{}`", typ)),
+            None => Diagnostic::error(
+                "E0225",
+                "only pointers can be dereferenced".to_string(),
+                pointer.position.clone(),
+            )
+            .with_label("the type of this isn't known".to_string()),
+        };
+        self.diagnostics.emit(diagnostic);
+        None
+    }
+
+    // `p.?`, the pointer in an optional
+    fn infer_unwrap(&mut self, operand: &mut ParsedAST) -> Option<Type> {
+        match self.infer(operand)? {
+            Type::OPTIONAL(inner) => Some(*inner),
+            typ => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0228",
+                        "only optional values can be unwrapped".to_string(),
+                        operand.position.clone(),
+                    )
+                    .with_label(format!("this is `{}`, which can't be null", typ)),
+                );
+                None
+            }
+        }
+    }
+
+    fn infer_binary(&mut self, binary: &mut Binary, position: &Position) -> Option<Type> {
+        let left = self.infer(&mut binary.left);
+        let right = self.infer(&mut binary.right);
+        if let Token::AND | Token::OR = binary.op {
+            self.expect_condition(&binary.left);
+            self.expect_condition(&binary.right);
+            return Some(Type::BOOL);
+        }
+        if let (
+            Token::PLUS | Token::MINUS,
+            Some(typ @ (Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_))),
+        ) = (binary.op, &left)
+        {
+            self.check_offset(typ, binary);
+        }
+        // unknown operators are reported when the ir is generated
+        let symbol = operator(binary.op)?;
+        let comparison = !matches!(
+            binary.op,
+            Token::PLUS | Token::MINUS | Token::STAR | Token::DIV | Token::PERCENT | Token::DOT_DOT
+        );
+        if let (Some(l), Some(r)) = (&left, &right) {
+            let allowed = match l {
+                _ if comparison => {
+                    (is_number(l) && is_number(r)) || assignable(l, r) || assignable(r, l)
+                }
+                _ if *binary.op == Token::DOT_DOT => l.is_integer() && r.is_integer(),
+                // pointers are moved along by a number of values
+                Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_)
+                    if matches!(binary.op, Token::PLUS | Token::MINUS) =>
+                {
+                    r.is_integer()
+                }
+                _ => is_number(l) && is_number(r),
+            };
+            if !allowed {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0501",
+                        format!("cannot apply `{}` to `{}` and `{}`", symbol, l, r),
+                        position.clone(),
+                    )
+                    .with_secondary(binary.left.position.clone(), format!("this is `{}`", l))
+                    .with_secondary(binary.right.position.clone(), format!("this is `{}`", r)),
+                );
+            }
+        }
+        match left {
+            // a range isn't a value, it is only iterated over or sliced with
+            _ if *binary.op == Token::DOT_DOT => None,
+            _ if comparison => Some(Type::BOOL),
+            Some(typ @ (Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_))) => Some(typ),
+            _ => arithmetic_type(&binary.left, &binary.right),
+        }
+    }
+
+    // `p + n` moves a pointer n values along, like c this isn't checked so it needs `unsafe`
+    fn check_offset(&self, typ: &Type, binary: &Binary) {
+        let diagnostic = match typ {
+            Type::POINTER(_) if self.unsafe_depth > 0 => return,
+            Type::POINTER(_) => Diagnostic::error(
+                "E0226",
+                "pointer arithmetic is only allowed inside `unsafe`".to_string(),
+                binary.left.position.join(&binary.right.position),
+            )
+            .with_label("wrap this in `unsafe { ... }`".to_string()),
+            _ => Diagnostic::error(
+                "E0226",
+                format!("`{}` cannot be offset", typ),
+                binary.left.position.clone(),
+            )
+            .with_label("only `*T` pointers can be offset".to_string()),
+        };
+        self.diagnostics.emit(diagnostic);
+    }
+
+    fn infer_call(&mut self, call: &mut Call, position: &Position) -> Option<Type> {
+        let callee = self.infer(&mut call.callee);
+        for arg in call.args.iter_mut() {
+            self.infer(&mut arg.value);
+        }
+        self.check_arg_names(call, &callee, position);
+        let function = match callee {
+            Some(Type::FN(function)) => function,
+            Some(typ) => {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0204",
+                        "only functions can be called".to_string(),
+                        call.callee.position.clone(),
+                    )
+                    .with_label(format!("this is a `{}`", typ)),
+                );
+                return None;
+            }
+            // intrinsics aren't in scope, so we don't know their type
+            None => return None,
+        };
+        if function.names.is_empty() {
+            // the params aren't named, so the args are taken in order
+            for (arg, expected) in call.args.iter().zip(function.args.iter()) {
+                if arg.name.is_none() {
+                    self.expect(&Some(expected.clone()), &arg.value);
+                }
+            }
+        } else {
+            // named args are matched with the parameters they name
+            for arg in call.args.iter() {
+                let expected = function
+                    .names
+                    .iter()
+                    .position(|name| Some(name) == arg.name.as_ref())
+                    .and_then(|i| function.args.get(i).cloned());
+                self.expect(&expected, &arg.value);
+            }
+        }
+        function.return_type.map(|typ| *typ)
+    }
+
+    // functions must be called with every parameter named, i.e. `add(a=1, b=2)`. a function
+    // value is called with the names its type gives the params, if it names them. intrinsics
+    // take their args as they are
+    fn check_arg_names(&self, call: &Call, callee: &Option<Type>, position: &Position) {
+        // a function value that isn't named, i.e. `fns[0](x)`, is shown by its type
+        let (owner, named_function) = match (&call.callee.kind, callee) {
+            (ParsedASTKind::IDENTIFIER(name), None | Some(Type::FN(_))) => {
+                (source_name(name).to_string(), self.functions.contains(name))
+            }
+            (_, Some(typ @ Type::FN(_))) => (typ.to_string(), false),
+            // anything else can't be called, see infer_call
+            _ => return,
+        };
+        let params = match callee {
+            Some(Type::FN(function)) if named_function || !function.names.is_empty() => {
+                &function.names
+            }
+            _ => {
+                let Some(arg) = call.args.iter().find(|arg| arg.name.is_some()) else {
+                    return;
+                };
+                let mut diagnostic = Diagnostic::error(
+                    "E0213",
+                    format!("`{}` doesn't take named arguments", owner),
+                    arg.position.clone(),
+                )
+                .with_label("remove the name".to_string());
+                if let (ParsedASTKind::IDENTIFIER(_), Some(typ @ Type::FN(_))) =
+                    (&call.callee.kind, callee)
+                {
+                    diagnostic = diagnostic.with_note(format!(
+                        "`{}` is a `{}`, which doesn't name its parameters",
+                        owner, typ
+                    ));
+                }
+                self.diagnostics.emit(diagnostic);
+                return;
+            }
+        };
+        self.check_names(&owner, params, &call.args, ARGS, position);
+    }
+
+    // match `name=value` args to the names they are for. every name must be given exactly
+    // once
+    fn check_names(
+        &self,
+        owner: &str,
+        params: &[String],
+        args: &[Arg],
+        named: Named,
+        position: &Position,
+    ) {
+        let errors = self.diagnostics.diagnostics().len();
+        let mut given: Vec<Option<usize>> = vec![None; params.len()];
+        for (i, arg) in args.iter().enumerate() {
+            let Some(name) = &arg.name else {
+                let label = match params.get(i) {
+                    Some(param) => format!("write this as `{}=...`", param),
+                    None => format!("name this {}", named.what),
+                };
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0212",
+                        format!("{}s must be named", named.what),
+                        arg.position.clone(),
+                    )
+                    .with_label(label),
+                );
+                continue;
+            };
+            let Some(param) = params.iter().position(|param| param == name) else {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0213",
+                        format!("`{}` has no {} named `{}`", owner, named.of, name),
+                        arg.position.clone(),
+                    )
+                    .with_label(format!("unknown {}", named.what)),
+                );
+                continue;
+            };
+            if let Some(first) = given[param] {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0214",
+                        format!("`{}` is given more than once", name),
+                        arg.position.clone(),
+                    )
+                    .with_label("given again here".to_string())
+                    .with_secondary(args[first].position.clone(), "first given here".to_string()),
+                );
+                continue;
+            }
+            given[param] = Some(i);
+        }
+        // the missing args are likely caused by the ones that were wrong
+        if self.diagnostics.diagnostics().len() > errors {
+            return;
+        }
+
+        let missing: Vec<String> = params
+            .iter()
+            .zip(given.iter())
+            .filter(|(_, arg)| arg.is_none())
+            .map(|(param, _)| format!("`{}`", param))
+            .collect();
+        if !missing.is_empty() {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0215",
+                    format!("missing {}(s) {}", named.what, missing.join(", ")),
+                    position.clone(),
+                )
+                .with_label(format!("`{}` needs all of its {}s", owner, named.what)),
+            );
+        }
+    }
+
+    // a struct used as an enum or the other way round. names that aren't types at all are
+    // reported by the resolver
+    fn wrong_kind(&self, name: &str, expected: &str, found: &str, position: &Position) {
+        self.diagnostics.emit(
+            Diagnostic::error(
+                "E0218",
+                format!("`{}` is not {}", source_name(name), expected),
+                position.clone(),
+            )
+            .with_label(format!("`{}` is {}", source_name(name), found)),
+        );
+    }
+
+    // the types of the payload of `E::V`, if there is such a variant
+    fn payload_types(&self, enum_name: &str, variant: &str) -> Option<Vec<Type>> {
+        let variants = self.enums.get(enum_name)?;
+        let (_, payload) = variants.iter().find(|(name, _)| name == variant)?;
+        Some(payload.clone())
+    }
+
+    // find `E::V`, checking it holds the number of values it is given
+    fn variant_payload(
+        &self,
+        enum_name: &str,
+        variant: &str,
+        count: usize,
+        position: &Position,
+    ) -> Option<Vec<Type>> {
+        if !self.enums.contains_key(enum_name) {
+            if self.structs.contains_key(enum_name) {
+                self.wrong_kind(enum_name, "an enum", "a struct", position);
+            }
+            return None;
+        }
+        let Some(payload) = self.payload_types(enum_name, variant) else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0230",
+                    format!(
+                        "`{}` has no variant named `{}`",
+                        source_name(enum_name),
+                        variant
+                    ),
+                    position.clone(),
+                )
+                .with_label("unknown variant".to_string()),
+            );
+            return None;
+        };
+        if payload.len() != count {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0231",
+                    format!(
+                        "wrong number of values for `{}::{}`",
+                        source_name(enum_name),
+                        variant
+                    ),
+                    position.clone(),
+                )
+                .with_label(format!("expected {}, found {}", payload.len(), count)),
+            );
+            return None;
+        }
+        Some(payload)
+    }
+
+    fn infer_variant(&mut self, variant: &mut Variant, position: &Position) -> Option<Type> {
+        for value in variant.payload.iter_mut() {
+            self.infer(value);
+        }
+        let payload = self.variant_payload(
+            &variant.enum_name,
+            &variant.variant,
+            variant.payload.len(),
+            position,
+        )?;
+        for (value, expected) in variant.payload.iter().zip(payload) {
+            self.expect(&Some(expected), value);
+        }
+        Some(Type::ENUM(variant.enum_name.clone()))
+    }
+
+    fn infer_match(&mut self, m: &mut Match, position: &Position) -> Option<Type> {
+        // if we don't know the type of the value, the patterns can tell us
+        let typ = self.infer(&mut m.scrutinee).or_else(|| {
+            m.arms.iter().find_map(|arm| match &arm.pattern.kind {
+                PatternKind::VARIANT(enum_name, _, _) => Some(Type::ENUM(enum_name.clone())),
+                _ => None,
+            })
+        });
+        let mut valid = true;
+        let mut arm_types: Vec<(Type, Position)> = vec![];
+        for arm in m.arms.iter_mut() {
+            self.scopes.new_scope();
+            valid &= self.bind_pattern(&mut arm.pattern, &typ);
+            if let Some(body) = self.infer(&mut arm.body) {
+                arm_types.push((body, arm.body.position.clone()));
+            }
+            self.scopes.leave_scope();
+        }
+        let result = arm_types.first().map(|(typ, _)| typ.clone());
+        if !valid {
+            return result;
+        }
+        let rows: Vec<Vec<Option<&Pattern>>> =
+            m.arms.iter().map(|arm| vec![Some(&arm.pattern)]).collect();
+        if let Some(witness) = unmatched(&rows, &[typ], &self.enums) {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0229",
+                    "match is not exhaustive".to_string(),
+                    position.clone(),
+                )
+                .with_label(format!("`{}` is not matched", witness[0])),
+            );
+            return result;
+        }
+        self.check_match_arms(&arm_types);
+        result
+    }
+
+    // declare the variables a pattern binds, they have the type of the value they match.
+    // false if the pattern couldn't match a value of the type
+    fn bind_pattern(&mut self, pattern: &mut Pattern, typ: &Option<Type>) -> bool {
+        match &mut pattern.kind {
+            PatternKind::BINDING(name) => {
+                if let Some(typ) = typ {
+                    self.scopes.add(name.clone(), typ.clone());
+                }
+                true
+            }
+            PatternKind::LITERAL(literal) => {
+                let (Some(typ), Some(literal_type)) = (typ, self.infer(literal)) else {
+                    return true;
+                };
+                let fits = match (typ, &literal_type) {
+                    (Type::CHAR, Type::CHAR) | (Type::BOOL, Type::BOOL) => true,
+                    (typ, literal_type) => is_number(typ) && is_number(literal_type),
+                };
+                if !fits {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0232",
+                            format!("this pattern can't match a `{}`", typ),
+                            pattern.position.clone(),
+                        )
+                        .with_label(format!("this is `{}`", literal_type)),
+                    );
+                }
+                fits
+            }
+            PatternKind::VARIANT(enum_name, variant, payload) => {
+                if let Some(typ) = typ
+                    .as_ref()
+                    .filter(|typ| **typ != Type::ENUM(enum_name.clone()))
+                {
+                    self.diagnostics.emit(
+                        Diagnostic::error(
+                            "E0232",
+                            format!("this pattern can't match a `{}`", typ),
+                            pattern.position.clone(),
+                        )
+                        .with_label(format!("this matches a `{}`", source_name(enum_name))),
+                    );
+                    return false;
+                }
+                let Some(types) =
+                    self.variant_payload(enum_name, variant, payload.len(), &pattern.position)
+                else {
+                    return false;
+                };
+                let mut valid = true;
+                for (part, typ) in payload.iter_mut().zip(types) {
+                    valid &= self.bind_pattern(part, &Some(typ));
+                }
+                valid
+            }
+            PatternKind::WILDCARD => true,
+        }
+    }
+
+    // like the branches of an if, every arm of a match must give the same type
+    fn check_match_arms(&self, arm_types: &[(Type, Position)]) {
+        let Some((first_type, first_position)) = arm_types.first() else {
+            return;
+        };
+        let Some((typ, position)) = arm_types.iter().find(|(typ, _)| typ != first_type) else {
+            return;
+        };
+        self.diagnostics.emit(
+            Diagnostic::error(
+                "E0233",
+                "match arms have different types".to_string(),
+                position.clone(),
+            )
+            .with_label(format!("expected `{}`, found `{}`", first_type, typ))
+            .with_secondary(first_position.clone(), format!("this is `{}`", first_type)),
+        );
+    }
+
+    // the value of an if is one of its branches, so they must have the same type
+    fn check_if_arms(&self, body: &ParsedAST, else_body: &ParsedAST) {
+        let (Some(body_type), Some(else_type)) = (&body.typ, &else_body.typ) else {
+            return;
+        };
+        if body_type != else_type {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0211",
+                    "`if` and `else` have different types".to_string(),
+                    else_body.position.clone(),
+                )
+                .with_label(format!("expected `{}`, found `{}`", body_type, else_type))
+                .with_secondary(body.position.clone(), format!("this is `{}`", body_type)),
+            );
+        }
+    }
+
+    fn infer_struct_literal(
+        &mut self,
+        literal: &mut StructLiteral,
+        position: &Position,
+    ) -> Option<Type> {
+        for field in literal.fields.iter_mut() {
+            self.infer(&mut field.value);
+        }
+        let Some(fields) = self.structs.get(&literal.name) else {
+            if self.enums.contains_key(&literal.name) {
+                self.wrong_kind(&literal.name, "a struct", "an enum", position);
+            }
+            return None;
+        };
+        let names: Vec<String> = fields.iter().map(|(name, _)| name.clone()).collect();
+        for field in literal.fields.iter() {
+            let expected = fields
+                .iter()
+                .find(|(name, _)| Some(name) == field.name.as_ref())
+                .map(|(_, typ)| typ.clone());
+            self.expect(&expected, &field.value);
+        }
+        self.check_names(
+            source_name(&literal.name),
+            &names,
+            &literal.fields,
+            FIELDS,
+            position,
+        );
+        Some(Type::STRUCT(literal.name.clone()))
+    }
+
+    fn infer_array_literal(
+        &mut self,
+        elements: &mut [ParsedAST],
+        position: &Position,
+    ) -> Option<Type> {
+        for element in elements.iter_mut() {
+            self.infer(element);
+        }
+        let Some(first) = elements.first() else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0219",
+                    "empty arrays are not supported yet".to_string(),
+                    position.clone(),
+                )
+                .with_label("the type of the elements can't be known".to_string()),
+            );
+            return None;
+        };
+        // like the arms of an if, we can only check the elements we know the type of
+        let first_type = first.typ.clone()?;
+        let different = elements
+            .iter()
+            .skip(1)
+            .find(|element| element.typ.as_ref().is_some_and(|typ| *typ != first_type));
+        if let Some(element) = different {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0220",
+                    "array elements have different types".to_string(),
+                    element.position.clone(),
+                )
+                .with_label(format!(
+                    "expected `{}`, found `{}`",
+                    first_type,
+                    element.typ.as_ref().unwrap()
+                ))
+                .with_secondary(first.position.clone(), format!("this is `{}`", first_type)),
+            );
+        }
+        Some(Type::ARRAY(Box::new(first_type), elements.len()))
+    }
+
+    fn infer_index(&mut self, index: &mut Index) -> Option<Type> {
+        let left = self.infer(&mut index.left);
+        self.infer(&mut index.index);
+        let range = matches!(
+            index.index.kind,
+            ParsedASTKind::BINARY(Binary {
+                op: Token::DOT_DOT,
+                ..
+            })
+        );
+        if !range {
+            self.expect(&Some(Type::I64), &index.index);
+        }
+        let element = match left {
+            Some(Type::ARRAY(element, _) | Type::SLICE(element)) => element,
+            typ => {
+                let label = match &typ {
+                    Some(typ) => format!("this is `{}`", typ),
+                    None => "the type of this isn't known".to_string(),
+                };
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0221",
+                        "only arrays and slices can be indexed".to_string(),
+                        index.left.position.clone(),
+                    )
+                    .with_label(label),
+                );
+                return None;
+            }
+        };
+        self.check_index(&index.left, &index.index);
+        match range {
+            true => Some(Type::SLICE(element)),
+            false => Some(*element),
+        }
+    }
+
+    // an index we can see is past the end of an array is reported now, rather than when the
+    // program runs
+    fn check_index(&self, array: &ParsedAST, index: &ParsedAST) {
+        let (Some(typ @ Type::ARRAY(_, len)), ParsedASTKind::NUMBER(Number::INTEGER(i, _))) =
+            (&array.typ, &index.kind)
+        else {
+            return;
+        };
+        if (*i as usize) < *len {
+            return;
+        }
+        self.diagnostics.emit(
+            Diagnostic::error(
+                "E0222",
+                format!("index {} is out of bounds", i),
+                index.position.clone(),
+            )
+            .with_label(format!("`{}` has {} element(s)", typ, len)),
+        );
+    }
+
+    fn infer_field(&mut self, access: &mut LhsAccess, position: &Position) -> Option<Type> {
+        let left = self.infer(&mut access.left);
+        if access.right == "len" && matches!(left, Some(Type::ARRAY(..) | Type::SLICE(_))) {
+            return Some(Type::I64);
+        }
+        let typ = match &access.left.kind {
+            // the field we're getting this one from has been reported if it's wrong
+            ParsedASTKind::LHS_ACCESS(_) if left.is_none() => return None,
+            // a pointer to a struct has the fields of the struct, also when it is a field itself
+            _ => left.map(|typ| typ.pointee().cloned().unwrap_or(typ)),
+        };
+        let fields = match &typ {
+            Some(Type::STRUCT(name)) => self.structs.get(name).map(|fields| (name, fields)),
+            _ => None,
+        };
+        let Some((name, fields)) = fields else {
+            let label = match &typ {
+                Some(typ) => format!("this is `{}`", typ),
+                None => "the type of this isn't known".to_string(),
+            };
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0217",
+                    format!(
+                        "cannot get the field `{}`, only structs have fields",
+                        access.right
+                    ),
+                    access.left.position.clone(),
+                )
+                .with_label(label),
+            );
+            return None;
+        };
+        let Some((_, field)) = fields.iter().find(|(field, _)| *field == access.right) else {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0216",
+                    format!(
+                        "`{}` has no field named `{}`",
+                        source_name(name),
+                        access.right
+                    ),
+                    position.clone(),
+                )
+                .with_label("unknown field".to_string()),
+            );
+            return None;
+        };
+        Some(field.clone())
+    }
+
+    // conditions are true if they aren't zero, so they have to be numbers (or bools)
+    fn expect_condition(&self, condition: &ParsedAST) {
+        let Some(typ) = condition.typ.as_ref().filter(|typ| !is_number(typ)) else {
+            return;
+        };
+        self.diagnostics.emit(
+            Diagnostic::error(
+                "E0504",
+                format!("`{}` cannot be used as a condition", typ),
+                condition.position.clone(),
+            )
+            .with_label("expected a `bool`".to_string()),
+        );
+    }
+
+    // report a value that can't be used where it is. if we don't know either type there is
+    // nothing to check
+    fn expect(&self, expected: &Option<Type>, value: &ParsedAST) {
+        // only optional types can hold null
+        if let (ParsedASTKind::NULL, Some(expected)) = (&value.kind, expected) {
+            if !matches!(expected, Type::OPTIONAL(_)) {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0227",
+                        format!("`{}` cannot be null", expected),
+                        value.position.clone(),
+                    )
+                    .with_label("only optional types, like `?*i32`, can be null".to_string()),
+                );
+            }
+            return;
+        }
+        let (Some(expected), Some(found)) = (expected, &value.typ) else {
+            return;
+        };
+        // integer literals are range checked below instead
+        let literal = integer_literal(value).is_some() && expected.is_integer();
+        if !literal && !assignable(expected, found) {
+            self.diagnostics.emit(
+                Diagnostic::error(
+                    "E0500",
+                    "mismatched types".to_string(),
+                    value.position.clone(),
+                )
+                .with_label(format!("expected `{}`, found `{}`", expected, found)),
+            );
+            return;
+        }
+        // a literal takes the type it is used as, so it has to fit in it
+        if let (Some((min, max)), Some(literal)) = (integer_range(expected), integer_literal(value))
+        {
+            if literal < min || literal > max {
+                self.diagnostics.emit(
+                    Diagnostic::error(
+                        "E0502",
+                        format!("literal out of range for `{}`", expected),
+                        value.position.clone(),
+                    )
+                    .with_label(format!("`{}` doesn't fit in `{}`", literal, expected))
+                    .with_note(format!("the range of `{}` is {}..={}", expected, min, max)),
+                );
+            }
+        }
+    }
+}
+
+// what the names of `name=value` refer to, for errors
+struct Named {
+    what: &'static str,
+    of: &'static str,
+}
+
+const ARGS: Named = Named {
+    what: "argument",
+    of: "parameter",
+};
+
+const FIELDS: Named = Named {
+    what: "field",
+    of: "field",
+};
+
+// `&x` or `&a[i]`, only variables (and their elements) live somewhere with an address
+fn has_address(ast: &ParsedAST) -> bool {
+    match &ast.kind {
+        ParsedASTKind::IDENTIFIER(_) => true,
+        ParsedASTKind::INDEX(index) => {
+            matches!(index.left.kind, ParsedASTKind::IDENTIFIER(_))
+                && !matches!(
+                    index.index.kind,
+                    ParsedASTKind::BINARY(Binary {
+                        op: Token::DOT_DOT,
+                        ..
+                    })
+                )
+        }
+        _ => false,
+    }
+}
+
+// the values an integer type holds, bools are 0 or 1
+fn integer_range(typ: &Type) -> Option<(i128, i128)> {
+    match typ {
+        Type::BOOL => Some((0, 1)),
+        typ => typ.integer_bounds(),
+    }
+}
+
+fn is_number(typ: &Type) -> bool {
+    typ.is_integer() || typ.is_float()
+}
+
+// a number written in the source, which takes the type of what it is used with
+fn is_literal(ast: &ParsedAST) -> bool {
+    match &ast.kind {
+        ParsedASTKind::NUMBER(_) => true,
+        ParsedASTKind::GROUP(group) => is_literal(&group.expression),
+        ParsedASTKind::LEFT_UNARY(LeftUnary::NEG(operand)) => is_literal(operand),
+        _ => false,
+    }
+}
+
+// the value of an integer written in the source
+fn integer_literal(ast: &ParsedAST) -> Option<i128> {
+    match &ast.kind {
+        ParsedASTKind::NUMBER(Number::INTEGER(value, _)) => Some(*value as i128),
+        ParsedASTKind::GROUP(group) => integer_literal(&group.expression),
+        ParsedASTKind::LEFT_UNARY(LeftUnary::NEG(operand)) => integer_literal(operand).map(|v| -v),
+        _ => None,
+    }
+}
+
+// the type of arithmetic on two operands that have been annotated. `x + 1` has the type of
+// x, even if x is narrower than the literal's default type
+pub fn arithmetic_type(left: &ParsedAST, right: &ParsedAST) -> Option<Type> {
+    let (Some(l), Some(r)) = (&left.typ, &right.typ) else {
+        return left.typ.clone().or_else(|| right.typ.clone());
+    };
+    let alike = (l.is_integer() && r.is_integer()) || (l.is_float() && r.is_float());
+    if alike && is_literal(left) && !is_literal(right) {
+        Some(r.clone())
+    } else if alike && is_literal(right) && !is_literal(left) {
+        Some(l.clone())
+    } else {
+        Some(join(l, r))
+    }
+}
+
+// whether a value of the type `from` can be used where a `to` is expected. numbers convert
+// to each other like they do in arithmetic, except floats don't become ints
+fn assignable(to: &Type, from: &Type) -> bool {
+    match (to, from) {
+        // a function can be used where its params aren't named, but not where they are named
+        // differently, or the args of calls to it would go to the wrong params
+        (Type::FN(to), Type::FN(from)) => {
+            to == from && (to.names.is_empty() || from.names.is_empty() || to.names == from.names)
+        }
+        _ if to == from => true,
+        // every value of from has to fit in to, so i32 can become i64 but not u32
+        _ if to.is_integer() && from.is_integer() => {
+            let (Some((to_min, to_max)), Some((from_min, from_max))) =
+                (integer_range(to), integer_range(from))
+            else {
+                return false;
+            };
+            to_min <= from_min && from_max <= to_max
+        }
+        _ if to.is_float() && is_number(from) => true,
+        // an array can be passed as a slice of all of it
+        (Type::SLICE(to), Type::ARRAY(from, _)) => to == from,
+        // a reference can be used as a pointer, but not the other way around
+        (Type::POINTER(to), Type::REFERENCE(from)) => to == from,
+        (Type::OPTIONAL(inner), from) => assignable(inner, from),
+        _ => false,
+    }
+}
+
+fn operator(op: &Token) -> Option<&'static str> {
+    match op {
+        Token::PLUS => Some("+"),
+        Token::MINUS => Some("-"),
+        Token::STAR => Some("*"),
+        Token::DIV => Some("/"),
+        Token::PERCENT => Some("%"),
+        Token::DOT_DOT => Some(".."),
+        Token::EQUAL_EQUAL => Some("=="),
+        Token::NOT_EQUAL => Some("!="),
+        Token::LESS => Some("<"),
+        Token::LESS_EQUAL => Some("<="),
+        Token::GREATER => Some(">"),
+        Token::GREATER_EQUAL => Some(">="),
+        _ => None,
+    }
+}
+
+pub fn irrefutable(pattern: &Pattern) -> bool {
+    matches!(
+        pattern.kind,
+        PatternKind::WILDCARD | PatternKind::BINDING(_)
+    )
+}
+
+// values of the given types that none of the rows of patterns match, shown as patterns, or
+// none if every value is matched. `None` in a row matches anything. the first value is
+// split up by the variants (or bools) it could be, each row that matches a variant goes on
+// to match its payload. values with too many possibilities to list, like numbers, are only
+// covered by the rows that match anything. see "warnings for pattern matching" (maranget)
+fn unmatched(
+    rows: &[Vec<Option<&Pattern>>],
+    types: &[Option<Type>],
+    enums: &Enums,
+) -> Option<Vec<String>> {
+    let Some((first, rest)) = types.split_first() else {
+        return match rows.is_empty() {
+            true => Some(vec![]),
+            false => None,
+        };
+    };
+    // each possible first value, what it's shown as and the types of what it holds
+    let constructors: Vec<(String, Vec<Option<Type>>)> = match first {
+        Some(Type::ENUM(name)) if enums.contains_key(name) => enums[name]
+            .iter()
+            .map(|(variant, payload)| {
+                let payload = payload.iter().cloned().map(Some).collect();
+                (format!("{}::{}", source_name(name), variant), payload)
+            })
+            .collect(),
+        Some(Type::BOOL) => vec![("true".to_string(), vec![]), ("false".to_string(), vec![])],
+        _ => {
+            let rows: Vec<Vec<Option<&Pattern>>> = rows
+                .iter()
+                .filter(|row| row[0].is_none_or(irrefutable))
+                .map(|row| row[1..].to_vec())
+                .collect();
+            let mut witness = unmatched(&rows, rest, enums)?;
+            witness.insert(0, "_".to_string());
+            return Some(witness);
+        }
+    };
+    // which constructor a pattern is, bools are true then false like above
+    let constructor = |kind: &PatternKind| match (kind, first) {
+        (PatternKind::VARIANT(_, variant, _), Some(Type::ENUM(name))) => {
+            enums[name].iter().position(|(v, _)| v == variant)
+        }
+        (PatternKind::LITERAL(literal), _) => match literal.kind {
+            ParsedASTKind::NUMBER(Number::INTEGER(value, _)) => Some((value == 0) as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    for (i, (shown, payload)) in constructors.iter().enumerate() {
+        // the rows that match this constructor, with the patterns for what it holds in place
+        // of the pattern for it
+        let specialised: Vec<Vec<Option<&Pattern>>> = rows
+            .iter()
+            .filter_map(|row| {
+                let mut parts: Vec<Option<&Pattern>> = match row[0] {
+                    Some(pattern) if !irrefutable(pattern) => {
+                        if constructor(&pattern.kind) != Some(i) {
+                            return None;
+                        }
+                        match &pattern.kind {
+                            PatternKind::VARIANT(_, _, parts) => parts.iter().map(Some).collect(),
+                            _ => vec![],
+                        }
+                    }
+                    _ => vec![None; payload.len()],
+                };
+                parts.extend_from_slice(&row[1..]);
+                Some(parts)
+            })
+            .collect();
+        let types: Vec<Option<Type>> = payload.iter().chain(rest.iter()).cloned().collect();
+        if let Some(mut witness) = unmatched(&specialised, &types, enums) {
+            let after = witness.split_off(payload.len());
+            let shown = match payload.is_empty() {
+                true => shown.to_string(),
+                false => format!("{}({})", shown, witness.join(", ")),
+            };
+            return Some(std::iter::once(shown).chain(after).collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{statement, ParsedASTKind};
+    use crate::diagnostic::DiagnosticSink;
+    use crate::lex::Lexer;
+    use crate::parse::Parser;
+    use crate::resolve::Resolver;
+    use crate::typeck::TypeChecker;
+
+    // check a program, giving back the type each top level declaration ends up with and the
+    // codes of any errors
+    fn check(program: &str) -> (Vec<String>, Vec<&'static str>) {
+        let diagnostics = DiagnosticSink::new();
+        let mut lexer = Lexer::new("test.sy", &diagnostics);
+        lexer.lex(Box::new(program.to_string()));
+        let mut parser = Parser {
+            tokens: &lexer.tokens,
+            diagnostics: &diagnostics,
+        };
+        let mut ast = parser.parse();
+        Resolver::new(&diagnostics).resolve(&mut ast);
+        TypeChecker::new(&diagnostics).check(&mut ast);
+        let ParsedASTKind::PROGRAM(program) = &mut ast.kind else {
+            panic!("expected a program");
+        };
+        let mut decls = vec![];
+        for item in program.body.iter_mut() {
+            let item = statement(item);
+            if let ParsedASTKind::DECL(decl) = &item.kind {
+                let typ = decl.typ.as_ref().map(|typ| typ.to_string());
+                decls.push(format!(
+                    "{}: {}",
+                    decl.identifier,
+                    typ.unwrap_or("?".into())
+                ));
+            }
+        }
+        let codes = diagnostics.diagnostics().iter().map(|d| d.code).collect();
+        (decls, codes)
+    }
+
+    #[test]
+    fn infers_declaration_types() {
+        let (decls, errors) = check(
+            "var a = 1
+var b: i64 = 2
+var c = b + 1
+var d = 1 + b
+var e = 1.5 * 2
+var f = c < 3
+const Point = type { x: i32 y: f64 }
+var p = Point{x=1, y=2.0f64}
+var g = p.y
+var xs = [1, 2, 3]
+var s = xs[1..2]
+fn twice(n: u8) -> u8 { n * 2 }
+var t = twice(n=3)
+var r = &a
+var v = *r
+var w = if f { 1.0f64 } else { g }",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            decls,
+            vec![
+                "a: i32",
+                "b: i64",
+                "c: i64",
+                "d: i64",
+                "e: f32",
+                "f: bool",
+                "Point: ?",
+                "p: Point",
+                "g: f64",
+                "xs: [3]i32",
+                "s: [i32]",
+                "t: u8",
+                "r: &i32",
+                "v: i32",
+                "w: f64",
+            ]
+        );
+
+        let codes = |program: &str| check(program).1;
+        assert_eq!(codes("var x: i32 = \"hi\""), vec!["E0500"]);
+        assert_eq!(codes("var x = 1\nx = 'c'"), vec!["E0500"]);
+        assert_eq!(
+            codes("fn f(a: i32) -> i32 { a }\nvar x = f(a=1.5)"),
+            vec!["E0500"]
+        );
+        assert_eq!(codes("fn f() -> bool { ret \"no\" }"), vec!["E0500"]);
+        assert_eq!(codes("fn f() -> i32 { 'c' }"), vec!["E0500"]);
+        assert_eq!(
+            codes("var n = 1\nvar p: *i32 = &n\nvar q: &i32 = p"),
+            vec!["E0500"]
+        );
+        assert_eq!(codes("var x = \"a\" * 2"), vec!["E0501"]);
+        assert_eq!(codes("var x = -'c'"), vec!["E0501"]);
+        assert_eq!(codes("var x = 'a' < 1"), vec!["E0501"]);
+    }
+
+    #[test]
+    fn literals_fit_the_type_they_are_used_as() {
+        let codes = |program: &str| check(program).1;
+        assert!(
+            codes("var a: i8 = -128\nvar b: i8 = 127\nvar c: u8 = 255\nvar d: u8 = 0").is_empty()
+        );
+        assert!(
+            codes("var a: i64 = -9223372036854775808\nvar b: u64 = 18446744073709551615")
+                .is_empty()
+        );
+        assert_eq!(codes("var x: i8 = 1000"), vec!["E0502"]);
+        assert_eq!(codes("var x: i8 = -129"), vec!["E0502"]);
+        assert_eq!(codes("var x: u8 = 256"), vec!["E0502"]);
+        assert_eq!(codes("var x: u8 = -1"), vec!["E0502"]);
+        assert_eq!(codes("var x: u32 = 1\nx = (4294967296)"), vec!["E0502"]);
+        assert_eq!(codes("fn f(a: i16) {}\nf(a=40000)"), vec!["E0502"]);
+    }
+
+    #[test]
+    fn integers_only_widen_and_conditions_are_numbers() {
+        let codes = |program: &str| check(program).1;
+        assert!(codes("var a: i32 = 1\nvar b: i64 = a\nvar c: u8 = 2\nvar d: i16 = c").is_empty());
+        assert!(codes("var f: bool = true\nvar n: i32 = f").is_empty());
+        assert_eq!(codes("var a: i64 = 1\nvar b: i32 = a"), vec!["E0500"]);
+        assert_eq!(codes("var a: i32 = 1\nvar b: u32 = a"), vec!["E0500"]);
+        assert_eq!(codes("var a: u64 = 1\nvar b: i64 = a"), vec!["E0500"]);
+        assert_eq!(codes("fn f(x: u8) {}\nvar n = 300\nf(x=n)"), vec!["E0500"]);
+
+        assert!(codes("var n = 1\nwhile n < 3 { n = n + 1 }\nif n { 1 }\nif 1.5 { 2 }").is_empty());
+        assert_eq!(codes("while \"s\" { 1 }"), vec!["E0504"]);
+        assert_eq!(codes("var c = 'c'\nif c { 1 }"), vec!["E0504"]);
+        assert_eq!(
+            codes("var x = !\"s\" || 1 && \"t\""),
+            vec!["E0504", "E0504"]
+        );
+    }
+
+    #[test]
+    fn types_that_hold_themselves_have_no_size() {
+        let codes = |program: &str| check(program).1;
+        assert_eq!(
+            codes(
+                "const T = type { x: T }
+var k: T"
+            ),
+            vec!["E0503"]
+        );
+        assert_eq!(codes("const T = type { xs: [2]T }"), vec!["E0503"]);
+        // a loop is only reported once
+        assert_eq!(
+            codes(
+                "const A = type { b: B }
+const B = type { n: i32 a: A }"
+            ),
+            vec!["E0503"]
+        );
+        assert!(codes("const Node = type { value: i32 next: ?&Node }").is_empty());
+        assert!(codes("const Tree = type { children: [Tree] parent: *Tree }").is_empty());
+    }
+
+    #[test]
+    fn enums_that_hold_themselves_have_no_size() {
+        let codes = |program: &str| check(program).1;
+        assert_eq!(codes("const L = enum { Cons(i32, L), Nil }"), vec!["E0503"]);
+        assert_eq!(
+            codes("fn f() { const L = enum { Cons(i32, L), Nil } }"),
+            vec!["E0503"]
+        );
+        assert_eq!(
+            codes("const E = enum { Leaf(S), Nil }\nconst S = type { e: E }"),
+            vec!["E0503"]
+        );
+        assert!(codes("const L = enum { Cons(i32, &L), Nil }").is_empty());
+    }
+
+    #[test]
+    fn misused_values_are_reported_before_the_ir() {
+        let codes = |program: &str| check(program).1;
+        assert_eq!(codes("var x = if 1 { 1 } else { 'c' }"), vec!["E0211"]);
+        assert_eq!(
+            codes("fn add(a: i32, b: i32) -> i32 { a + b }\nvar x = add(1, b=2)"),
+            vec!["E0212"]
+        );
+        assert_eq!(
+            codes("var f: fn(i32) -> i32 = fn (n: i32) -> i32 { n }\nvar x = f(n=1)"),
+            vec!["E0213"]
+        );
+        assert_eq!(
+            codes("const P = type { x: i32 }\nvar p = P{x=1}\nvar y = p.y"),
+            vec!["E0216"]
+        );
+        assert_eq!(
+            codes("const E = enum { A }\nvar e = E{}\nconst P = type { x: i32 }\nvar p = P::A"),
+            vec!["E0218", "E0218"]
+        );
+        assert_eq!(codes("var x = 1\nvar y = x[0]"), vec!["E0221"]);
+        assert_eq!(codes("var a = [1, 2]\nvar y = a[2]"), vec!["E0222"]);
+        assert_eq!(codes("var p = &(1 + 2)"), vec!["E0223"]);
+        assert_eq!(
+            codes("var x = 1\nvar p: *i32 = &x\nvar q = p + 1"),
+            vec!["E0226"]
+        );
+        assert!(codes("var x = 1\nvar p: *i32 = &x\nvar q = unsafe { p + 1 }").is_empty());
+        assert_eq!(codes("var p: &i32 = null"), vec!["E0227"]);
+        assert_eq!(
+            codes("const E = enum { A, B }\nvar x = match E::A { E::A => 1 }"),
+            vec!["E0229"]
+        );
+    }
+}
@@ -1,33 +1,442 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::resolve::source_name;
+
+// the fields of each declared struct, by name
+pub type Structs = HashMap<String, Vec<(String, Type)>>;
+
+// the variants of each declared enum and the types of their payloads, by name
+pub type Enums = HashMap<String, Vec<(String, Vec<Type>)>>;
+
+#[derive(Debug, Clone, Eq)]
 pub struct FnPrimative {
     pub args: Vec<Type>,
     pub return_type: Option<Box<Type>>,
+    // the names of the params, so a value holding the function is called with named args
+    // like the function is. empty if the type doesn't name them, i.e. `fn(i32) -> i32`
+    pub names: Vec<String>,
+}
+
+// the names aren't part of the type, a function can be passed where its params aren't named
+impl PartialEq for FnPrimative {
+    fn eq(&self, other: &FnPrimative) -> bool {
+        self.args == other.args && self.return_type == other.return_type
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
+    U8,
+    U16,
     U32,
+    U64,
+    I8,
+    I16,
     I32,
+    I64,
     F32,
+    F64,
     BOOL,
+    // a unicode scalar value
+    CHAR,
     STRING,
-    SLICE,
+    // a pointer to some elements and how many there are, i.e. `[i32]`
+    SLICE(Box<Type>),
+    // a fixed number of elements, i.e. `[4]i32`
+    ARRAY(Box<Type>, usize),
+    // a pointer that can be offset inside `unsafe`, i.e. `*i32`
+    POINTER(Box<Type>),
+    // a pointer that can't be offset, i.e. `&i32`
+    REFERENCE(Box<Type>),
+    // a value that may be null, only pointers can be optional, i.e. `?*i32`
+    OPTIONAL(Box<Type>),
     FN(FnPrimative),
     BLOCK,
     TYPE,
     // name of the struct
     // todo this should probably be optional
     STRUCT(String),
+    // name of the enum. the parser can't tell enums from structs, so named types are
+    // parsed as structs and become enums once we know what was declared
+    ENUM(String),
 }
 
 impl Type {
-    pub fn size_in_bytes(&self) -> usize {
+    // none if the type isn't one a value can have, like an unknown struct or a struct that
+    // contains itself (see recursive_path)
+    pub fn size_in_bytes(&self, structs: &Structs, enums: &Enums) -> Option<usize> {
+        match self {
+            Type::U8 | Type::I8 | Type::BOOL => Some(1),
+            Type::U16 | Type::I16 => Some(2),
+            Type::U32 | Type::I32 | Type::F32 | Type::CHAR => Some(4),
+            Type::U64 | Type::I64 | Type::F64 | Type::STRING => Some(8),
+            // null is the zero address, so an optional pointer is just a pointer
+            Type::POINTER(_) | Type::REFERENCE(_) | Type::OPTIONAL(_) => Some(8),
+            // a function pointer and a pointer to its environment
+            Type::FN(_) => Some(16),
+            // a pointer and a length
+            Type::SLICE(_) => Some(16),
+            Type::ARRAY(element, len) => Some(element.size_in_bytes(structs, enums)? * len),
+            Type::STRUCT(name) => Some(struct_layout(structs.get(name)?, structs, enums)?.1),
+            Type::ENUM(name) => Some(enum_layout(enums.get(name)?, structs, enums)?.2),
+            _ => None,
+        }
+    }
+
+    pub fn align_in_bytes(&self, structs: &Structs, enums: &Enums) -> Option<usize> {
+        match self {
+            Type::FN(_) | Type::SLICE(_) => Some(8),
+            Type::ARRAY(element, _) => element.align_in_bytes(structs, enums),
+            // a struct is aligned to its most aligned field
+            Type::STRUCT(name) => {
+                let mut align = 1;
+                for (_, typ) in structs.get(name)?.iter() {
+                    align = align.max(typ.align_in_bytes(structs, enums)?);
+                }
+                Some(align)
+            }
+            // the tag is a u32
+            Type::ENUM(name) => Some(enum_layout(enums.get(name)?, structs, enums)?.1.max(4)),
+            t => t.size_in_bytes(structs, enums),
+        }
+    }
+
+    // what a pointer or reference points to
+    pub fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::POINTER(pointee) | Type::REFERENCE(pointee) => Some(pointee),
+            _ => None,
+        }
+    }
+
+    // the smallest and largest values of an integer type
+    pub fn integer_bounds(&self) -> Option<(i128, i128)> {
+        match self {
+            Type::U8 => Some((0, u8::MAX as i128)),
+            Type::U16 => Some((0, u16::MAX as i128)),
+            Type::U32 => Some((0, u32::MAX as i128)),
+            Type::U64 => Some((0, u64::MAX as i128)),
+            Type::I8 => Some((i8::MIN as i128, i8::MAX as i128)),
+            Type::I16 => Some((i16::MIN as i128, i16::MAX as i128)),
+            Type::I32 => Some((i32::MIN as i128, i32::MAX as i128)),
+            Type::I64 => Some((i64::MIN as i128, i64::MAX as i128)),
+            _ => None,
+        }
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    // an integer as this type holds it, cut down to its width then sign or zero extended back
+    // to an i64. a u64 keeps its bits, so the ones above i64::MAX look negative
+    pub fn wrap(&self, value: i64) -> i64 {
+        match self {
+            Type::U8 => value as u8 as i64,
+            Type::U16 => value as u16 as i64,
+            Type::U32 => value as u32 as i64,
+            Type::I8 => value as i8 as i64,
+            Type::I16 => value as i16 as i64,
+            Type::I32 => value as i32 as i64,
+            _ => value,
+        }
+    }
+
+    // bools are 0 or 1, so they are integers too
+    pub fn is_integer(&self) -> bool {
+        self.integer_bounds().is_some() || *self == Type::BOOL
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    // the parser can't tell enums from structs, this turns the names of enums into enums
+    pub fn resolve(&self, enums: &Enums) -> Type {
+        let resolve = |typ: &Type| Box::new(typ.resolve(enums));
         match self {
-            Type::U32 => 4,
-            Type::I32 => 4,
-            Type::F32 => 4,
-            Type::STRUCT(_) => todo!("size of struct"),
-            _ => panic!("unknown type"),
+            Type::STRUCT(name) if enums.contains_key(name) => Type::ENUM(name.to_string()),
+            Type::SLICE(element) => Type::SLICE(resolve(element)),
+            Type::ARRAY(element, len) => Type::ARRAY(resolve(element), *len),
+            Type::POINTER(pointee) => Type::POINTER(resolve(pointee)),
+            Type::REFERENCE(pointee) => Type::REFERENCE(resolve(pointee)),
+            Type::OPTIONAL(inner) => Type::OPTIONAL(resolve(inner)),
+            Type::FN(function) => Type::FN(FnPrimative {
+                args: function.args.iter().map(|arg| arg.resolve(enums)).collect(),
+                return_type: function.return_type.as_deref().map(resolve),
+                names: function.names.clone(),
+            }),
+            typ => typ.clone(),
+        }
+    }
+}
+
+// the type of arithmetic on two numbers. like the backends do, mixing ints and floats
+// gives a float, f32 and f64 give f64, and the narrower of two ints is widened
+pub fn join(left: &Type, right: &Type) -> Type {
+    match (left.is_float(), right.is_float()) {
+        (true, true) if left != right => Type::F64,
+        (true, _) => left.clone(),
+        (false, true) => right.clone(),
+        (false, false) => {
+            let range = |typ: &Type| typ.integer_bounds().map(|(min, max)| max - min);
+            if range(right) > range(left) {
+                right.clone()
+            } else {
+                left.clone()
+            }
         }
     }
 }
+
+// the offset of each field and the size of the whole struct. like c, each field
+// starts at a multiple of its alignment and the size is padded to a multiple of
+// the struct's alignment, so that arrays of it keep every field aligned
+pub fn struct_layout(
+    fields: &[(String, Type)],
+    structs: &Structs,
+    enums: &Enums,
+) -> Option<(Vec<usize>, usize)> {
+    let mut offsets = vec![];
+    let mut size: usize = 0;
+    let mut align: usize = 1;
+    for (_, typ) in fields.iter() {
+        let field_align = typ.align_in_bytes(structs, enums)?;
+        size = size.next_multiple_of(field_align);
+        offsets.push(size);
+        size += typ.size_in_bytes(structs, enums)?;
+        align = align.max(field_align);
+    }
+    Some((offsets, size.next_multiple_of(align)))
+}
+
+// the chain of types that leads a struct or enum back to itself, i.e. `["T", "U", "T"]` if
+// `T` has a field of type `U` which has a field of type `T`. a value of such a type would
+// have to hold itself, so it can't have a size. a pointer holds an address, so going
+// through one (`&T`, `*T`, `?&T`) or a slice is fine
+pub fn recursive_path(name: &str, structs: &Structs, enums: &Enums) -> Option<Vec<String>> {
+    struct Search<'s> {
+        structs: &'s Structs,
+        enums: &'s Enums,
+        target: &'s str,
+        path: Vec<String>,
+        seen: HashSet<String>,
+    }
+    impl Search<'_> {
+        fn visit(&mut self, current: &str) -> bool {
+            // a type that refers to itself may not know it's an enum yet, so look the name
+            // up in both
+            let held: Vec<&Type> = match (self.structs.get(current), self.enums.get(current)) {
+                (Some(fields), _) => fields.iter().map(|(_, typ)| typ).collect(),
+                (None, Some(variants)) => variants.iter().flat_map(|(_, p)| p.iter()).collect(),
+                (None, None) => return false,
+            };
+            for typ in held {
+                let Some(inner) = held_by_value(typ) else {
+                    continue;
+                };
+                self.path.push(inner.to_string());
+                if inner == self.target
+                    || (self.seen.insert(inner.to_string()) && self.visit(inner))
+                {
+                    return true;
+                }
+                self.path.pop();
+            }
+            false
+        }
+    }
+    let mut search = Search {
+        structs,
+        enums,
+        target: name,
+        path: vec![name.to_string()],
+        seen: HashSet::new(),
+    };
+    search.visit(name).then_some(search.path)
+}
+
+// the named type a value of this type holds in itself rather than through a pointer
+fn held_by_value(typ: &Type) -> Option<&str> {
+    match typ {
+        Type::STRUCT(name) | Type::ENUM(name) => Some(name),
+        Type::ARRAY(element, _) => held_by_value(element),
+        _ => None,
+    }
+}
+
+// an enum is a u32 tag followed by the payload of the variant it holds. each payload is
+// laid out like a struct, and they all start at the same offset, which is aligned for the
+// most aligned of them. gives that offset, the alignment of the payloads and the size of
+// the whole enum
+pub fn enum_layout(
+    variants: &[(String, Vec<Type>)],
+    structs: &Structs,
+    enums: &Enums,
+) -> Option<(usize, usize, usize)> {
+    let mut payload_align: usize = 1;
+    let mut payload_size: usize = 0;
+    for (_, payload) in variants.iter() {
+        let fields: Vec<(String, Type)> = payload
+            .iter()
+            .map(|typ| (String::new(), typ.clone()))
+            .collect();
+        let (_, size) = struct_layout(&fields, structs, enums)?;
+        payload_size = payload_size.max(size);
+        for typ in payload.iter() {
+            payload_align = payload_align.max(typ.align_in_bytes(structs, enums)?);
+        }
+    }
+    let offset = 4usize.next_multiple_of(payload_align);
+    let size = (offset + payload_size).next_multiple_of(payload_align.max(4));
+    Some((offset, payload_align, size))
+}
+
+// types are shown to the user as they are written
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // types declared in a block have an id after their name, see resolve.rs
+            Type::STRUCT(name) | Type::ENUM(name) => write!(f, "{}", source_name(name)),
+            Type::FN(function) => {
+                let args: Vec<String> = match function.names.len() == function.args.len() {
+                    true => function
+                        .names
+                        .iter()
+                        .zip(function.args.iter())
+                        .map(|(name, typ)| format!("{}: {}", name, typ))
+                        .collect(),
+                    false => function.args.iter().map(|typ| typ.to_string()).collect(),
+                };
+                write!(f, "fn({})", args.join(", "))?;
+                match &function.return_type {
+                    Some(return_type) => write!(f, " -> {}", return_type),
+                    None => Ok(()),
+                }
+            }
+            Type::SLICE(element) => write!(f, "[{}]", element),
+            Type::ARRAY(element, len) => write!(f, "[{}]{}", len, element),
+            Type::POINTER(pointee) => write!(f, "*{}", pointee),
+            Type::REFERENCE(pointee) => write!(f, "&{}", pointee),
+            Type::OPTIONAL(inner) => write!(f, "?{}", inner),
+            t => write!(f, "{}", format!("{:?}", t).to_lowercase()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn struct_fields_are_aligned_and_padded() {
+        let mut structs = Structs::new();
+        let enums = Enums::new();
+        structs.insert(
+            "Small".to_string(),
+            vec![
+                ("a".to_string(), Type::U8),
+                ("b".to_string(), Type::I32),
+                ("c".to_string(), Type::U8),
+            ],
+        );
+        structs.insert(
+            "Outer".to_string(),
+            vec![
+                ("flag".to_string(), Type::BOOL),
+                ("inner".to_string(), Type::STRUCT("Small".to_string())),
+                ("big".to_string(), Type::I64),
+            ],
+        );
+        structs.insert("Empty".to_string(), vec![]);
+        structs.insert(
+            "Buffer".to_string(),
+            vec![
+                ("len".to_string(), Type::U8),
+                ("data".to_string(), Type::ARRAY(Box::new(Type::U16), 3)),
+                ("view".to_string(), Type::SLICE(Box::new(Type::U8))),
+            ],
+        );
+
+        assert_eq!(
+            struct_layout(&structs["Small"], &structs, &enums),
+            Some((vec![0, 4, 8], 12))
+        );
+        assert_eq!(
+            Type::STRUCT("Small".to_string()).align_in_bytes(&structs, &enums),
+            Some(4)
+        );
+        assert_eq!(
+            struct_layout(&structs["Outer"], &structs, &enums),
+            Some((vec![0, 4, 16], 24))
+        );
+        assert_eq!(
+            Type::STRUCT("Outer".to_string()).size_in_bytes(&structs, &enums),
+            Some(24)
+        );
+        assert_eq!(
+            Type::STRUCT("Empty".to_string()).size_in_bytes(&structs, &enums),
+            Some(0)
+        );
+        assert_eq!(
+            struct_layout(&structs["Buffer"], &structs, &enums),
+            Some((vec![0, 2, 8], 24))
+        );
+    }
+
+    #[test]
+    fn enums_are_a_tag_then_the_largest_payload() {
+        let mut structs = Structs::new();
+        structs.insert("Pair".to_string(), vec![("a".to_string(), Type::U16)]);
+        let mut enums = Enums::new();
+        enums.insert(
+            "Shape".to_string(),
+            vec![
+                ("Circle".to_string(), vec![Type::F64]),
+                ("Rect".to_string(), vec![Type::U8, Type::I32]),
+                ("Empty".to_string(), vec![]),
+            ],
+        );
+        enums.insert(
+            "Small".to_string(),
+            vec![
+                ("A".to_string(), vec![Type::U8]),
+                ("B".to_string(), vec![Type::STRUCT("Pair".to_string())]),
+            ],
+        );
+        enums.insert(
+            "Flag".to_string(),
+            vec![("On".to_string(), vec![]), ("Off".to_string(), vec![])],
+        );
+
+        assert_eq!(
+            enum_layout(&enums["Shape"], &structs, &enums),
+            Some((8, 8, 16))
+        );
+        assert_eq!(
+            enum_layout(&enums["Small"], &structs, &enums),
+            Some((4, 2, 8))
+        );
+        assert_eq!(
+            Type::ENUM("Flag".to_string()).size_in_bytes(&structs, &enums),
+            Some(4)
+        );
+        assert_eq!(
+            Type::ENUM("Small".to_string()).align_in_bytes(&structs, &enums),
+            Some(4)
+        );
+        // an enum in a struct is aligned for its tag
+        structs.insert(
+            "Tagged".to_string(),
+            vec![
+                ("b".to_string(), Type::BOOL),
+                ("shape".to_string(), Type::ENUM("Shape".to_string())),
+            ],
+        );
+        assert_eq!(
+            struct_layout(&structs["Tagged"], &structs, &enums),
+            Some((vec![0, 8], 24))
+        );
+    }
+}